@@ -2,9 +2,12 @@ use std::time::Duration;
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use r2048_ai::ai::{
-    expectimax_sum_move, expectimax_weight_move, rand_move, sum_tiles_score, weight_score,
+    endgame_move, expectimax_move, expectimax_move_cached, expectimax_move_chance_cells,
+    expectimax_move_pruned, expectimax_move_table, expectimax_sum_move, expectimax_weight_move,
+    rand_move, smart_depth, sum_tiles_score, sum_tiles_score_bound, weight_score,
+    weight_score_bound, ChanceCells, TranspositionTable, ENDGAME_MAX_EMPTY,
 };
-use r2048_ai::game::{Game, State};
+use r2048_ai::game::{Board, Game, Move, PackedState, State};
 use rand::{prelude::StdRng, SeedableRng};
 
 fn random_game() -> u32 {
@@ -12,39 +15,65 @@ fn random_game() -> u32 {
     let mut move_rng = StdRng::seed_from_u64(2);
     // run for exactly 100 iterations so timing is easy to interpret
     for _ in 0..100 {
-        if let Some((_, s)) = rand_move(&mgr.state(), &mut move_rng) {
-            mgr.next_state(s);
+        if let Some((m, s)) = rand_move(mgr.state(), &mut move_rng) {
+            mgr.next_state(m, s);
         } else {
             panic!("game went too short");
         }
     }
-    return mgr.state().highest_tile();
+    mgr.state().highest_tile()
 }
 
 fn test_state() -> State {
-    let mut s = State::default();
-    let values: Vec<u8> = vec![0, 0, 1, 1, 0, 1, 2, 3, 0, 1, 2, 3, 3, 6, 9, 10];
-    for (i, x) in values.into_iter().enumerate() {
-        s.add(i, x);
-    }
-    s
+    State::from_exponents(&[0, 0, 1, 1, 0, 1, 2, 3, 0, 1, 2, 3, 3, 6, 9, 10]).unwrap()
 }
 
 fn sparse_state() -> State {
-    let mut s = State::default();
-    let values: Vec<u8> = vec![0, 1, 3, 8, 0, 0, 4, 2, 0, 1, 0, 0, 0, 0, 0, 0];
-    for (i, x) in values.into_iter().enumerate() {
-        s.add(i, x);
+    State::from_exponents(&[0, 1, 3, 8, 0, 0, 4, 2, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap()
+}
+
+// Snapshot boards from seeded games played by a depth-1 expectimax, taking
+// every 25th board so the corpus runs from nearly empty boards to crowded
+// late-game ones.
+fn sample_boards(seed: u64, n: usize) -> Vec<State> {
+    let mut boards = Vec::with_capacity(n);
+    let mut game_seed = seed;
+    while boards.len() < n {
+        let mut game = Game::from_rng(StdRng::seed_from_u64(game_seed));
+        game_seed += 1;
+        while let Some((m, s)) = expectimax_weight_move(game.state(), 1) {
+            if game.moves() % 25 == 0 {
+                boards.push(*game.state());
+                if boards.len() == n {
+                    break;
+                }
+            }
+            game.next_state(m, s);
+        }
     }
-    s
+    boards
 }
 
 fn small_criterion_benchmarks(c: &mut Criterion) {
-    c.bench_function("random game", |b| b.iter(|| random_game()));
+    c.bench_function("random game", |b| b.iter(random_game));
 
     let s = test_state();
     c.bench_function("sum score", |b| b.iter(|| sum_tiles_score(&black_box(s))));
     c.bench_function("weight score", |b| b.iter(|| weight_score(&black_box(s))));
+
+    // every move is legal from this board, so both compute all four
+    c.bench_function("legal moves", |b| b.iter(|| black_box(s).legal_moves()));
+    let packed = PackedState::from(s);
+    c.bench_function("legal moves packed", |b| {
+        b.iter(|| black_box(packed).legal_moves())
+    });
+    // vertical moves go through a transpose of the board
+    c.bench_function("up and down moves", |b| {
+        b.iter(|| {
+            let s = black_box(s);
+            (s.apply_move(Move::Up), s.apply_move(Move::Down))
+        })
+    });
 }
 
 fn expectimax_benchmarks(c: &mut Criterion) {
@@ -55,6 +84,18 @@ fn expectimax_benchmarks(c: &mut Criterion) {
     c.bench_function("expectimax weight-2", |b| {
         b.iter(|| expectimax_weight_move(&black_box(s), 2))
     });
+    // early in the game, sampling the chance nodes of open boards
+    c.bench_function("expectimax weight-3 early", |b| {
+        b.iter(|| expectimax_move(&black_box(s), 3, &weight_score))
+    });
+    let adaptive = ChanceCells::Adaptive {
+        threshold: 6,
+        max_cells: 4,
+        seed: 0,
+    };
+    c.bench_function("expectimax weight-3 early adaptive", |b| {
+        b.iter(|| expectimax_move_chance_cells(&black_box(s), 3, &weight_score, adaptive))
+    });
 
     let s = test_state();
     c.bench_function("expectimax sum-3", |b| {
@@ -63,6 +104,68 @@ fn expectimax_benchmarks(c: &mut Criterion) {
     c.bench_function("expectimax weight-3", |b| {
         b.iter(|| expectimax_weight_move(&black_box(s), 3))
     });
+
+    c.bench_function("expectimax weight-3 uncached", |b| {
+        b.iter(|| expectimax_move(&black_box(s), 3, &weight_score))
+    });
+    // the same search scoring each distinct leaf once (see
+    // cached_search_scores_fewer_leaves in src/ai.rs for the counts)
+    c.bench_function("expectimax weight-3 cached", |b| {
+        b.iter(|| expectimax_move_cached(&black_box(s), 3, &weight_score))
+    });
+    c.bench_function("expectimax weight-3 table", |b| {
+        b.iter(|| {
+            let mut table = TranspositionTable::new();
+            expectimax_move_table(&black_box(s), 3, &weight_score, &mut table)
+        })
+    });
+    c.bench_function("expectimax weight-3 symmetric table", |b| {
+        b.iter(|| {
+            let mut table = TranspositionTable::symmetric();
+            expectimax_move_table(&black_box(s), 3, &weight_score, &mut table)
+        })
+    });
+    c.bench_function("expectimax weight-3 pruned", |b| {
+        b.iter(|| expectimax_move_pruned(&black_box(s), 3, &weight_score, &weight_score_bound))
+    });
+    c.bench_function("expectimax sum-3 pruned", |b| {
+        b.iter(|| {
+            expectimax_move_pruned(&black_box(s), 3, &sum_tiles_score, &sum_tiles_score_bound)
+        })
+    });
+
+    // the total over a fixed corpus of boards from real games, which is less
+    // sensitive to the particular board than the benchmarks above
+    let boards = sample_boards(0, 50);
+    c.bench_function("expectimax weight-2 corpus", |b| {
+        b.iter(|| {
+            for s in black_box(&boards) {
+                expectimax_weight_move(s, 2);
+            }
+        })
+    });
+
+    // Depth::Smart tries the exhaustive endgame search before every move on
+    // nearly-full boards, so compare it to the search it usually falls back to
+    let endgames: Vec<State> = sample_boards(1, 1000)
+        .into_iter()
+        .filter(|s| s.empty_count() as usize <= ENDGAME_MAX_EMPTY)
+        .take(50)
+        .collect();
+    c.bench_function("endgame search corpus", |b| {
+        b.iter(|| {
+            for s in black_box(&endgames) {
+                endgame_move(s);
+            }
+        })
+    });
+    c.bench_function("expectimax weight smart depth endgame corpus", |b| {
+        b.iter(|| {
+            for s in black_box(&endgames) {
+                expectimax_weight_move(s, smart_depth(s));
+            }
+        })
+    });
 }
 
 criterion_group!(microbenches, small_criterion_benchmarks);
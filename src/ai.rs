@@ -7,12 +7,22 @@
 //! post](https://codemyroad.wordpress.com/2014/05/14/2048-ai-the-intelligent-bot/),
 //! which did some sort of hyperparameter search to come up with a weight
 //! matrix.
-use std::cmp::Ordering;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::cmp::Ordering;
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::{fs, io, path::Path, time::Duration, time::Instant};
 
 use crate::game;
-use crate::game::{Move, State};
+use crate::game::{Corner, Move, State};
+use crate::hash::HashMap;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 
 /// Generate a random legal move from `s`, and return the next state.
 pub fn rand_move<Rn: Rng>(s: &State, rng: &mut Rn) -> Option<(Move, State)> {
@@ -21,6 +31,8 @@ pub fn rand_move<Rn: Rng>(s: &State, rng: &mut Rn) -> Option<(Move, State)> {
 }
 
 mod weight {
+    use crate::game::Corner;
+
     pub(super) type Matrix = [f32; 16];
 
     // This magical weight matrix is taken from
@@ -47,9 +59,50 @@ mod weight {
         transpose(W3),
     ];
 
+    // Weights that fall off by a factor of 4 along a path that snakes back and
+    // forth through the rows, starting in the top-left corner.
+    const SNAKE0: Matrix = {
+        let mut w = [0f32; 16];
+        let mut weight = 1.0;
+        let mut k = 0;
+        while k < 16 {
+            let (row, col) = (k / 4, k % 4);
+            let col = if row % 2 == 0 { col } else { 3 - col };
+            w[row * 4 + col] = weight;
+            weight /= 4.0;
+            k += 1;
+        }
+        w
+    };
+    const SNAKE1: Matrix = rot_r(SNAKE0);
+    const SNAKE2: Matrix = rot_r(SNAKE1);
+    const SNAKE3: Matrix = rot_r(SNAKE2);
+    pub(super) const SNAKE_MATRICES: [Matrix; 8] = [
+        SNAKE0,
+        SNAKE1,
+        SNAKE2,
+        SNAKE3,
+        transpose(SNAKE0),
+        transpose(SNAKE1),
+        transpose(SNAKE2),
+        transpose(SNAKE3),
+    ];
+
+    /// The corner with the highest weight in each of `W_MATRICES`.
+    pub(super) const CORNERS: [Corner; 8] = [
+        Corner::TopLeft,
+        Corner::TopRight,
+        Corner::BottomRight,
+        Corner::BottomLeft,
+        Corner::TopLeft,
+        Corner::BottomLeft,
+        Corner::BottomRight,
+        Corner::TopRight,
+    ];
+
     const RIGHT_ROTATE_IDX: [usize; 16] = [12, 8, 4, 0, 13, 9, 5, 1, 14, 10, 6, 2, 15, 11, 7, 3];
 
-    const fn rot_r(w: Matrix) -> Matrix {
+    pub(super) const fn rot_r(w: Matrix) -> Matrix {
         // TODO: would be nice to initialize this in a better way (using a macro
         // probably), we have an initialization expression in terms of the
         // index...
@@ -62,7 +115,7 @@ mod weight {
         new_w
     }
 
-    const fn transpose(w: Matrix) -> Matrix {
+    pub(super) const fn transpose(w: Matrix) -> Matrix {
         let mut new_w = [0f32; 16];
         let mut i = 0;
         while i < 4 {
@@ -85,98 +138,2384 @@ mod weight {
     }
 }
 
-fn float_cmp(x: f32, y: f32) -> std::cmp::Ordering {
-    if x < y {
-        Ordering::Less
-    } else {
-        Ordering::Greater
+// Order scores for max_by, treating NaN as lower than any number so a NaN score
+// (say, from a heuristic dividing by zero) can never be picked as the best.
+fn float_cmp(x: f32, y: f32) -> Ordering {
+    match (x.is_nan(), y.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => x.partial_cmp(&y).unwrap(),
     }
 }
 
-fn state_tiles(s: &State) -> weight::Matrix {
-    let mut tiles = [0f32; 16];
-    for (i, tile) in tiles.iter_mut().enumerate() {
-        *tile = s.tile(i) as f32;
+// The first item with the highest score. Unlike max_by, which returns the last
+// of several equal maxima, ties go to the earliest item.
+fn first_max_by<T>(items: impl Iterator<Item = T>, score: impl Fn(&T) -> f32) -> Option<T> {
+    items.reduce(|best, item| match float_cmp(score(&item), score(&best)) {
+        Ordering::Greater => item,
+        _ => best,
+    })
+}
+
+// Tile values indexed by exponent, so scoring doesn't need to call pow. Like
+// State::tile, an empty cell has value 1.
+const TILE_VALUES: [f32; 16] = {
+    let mut values = [0f32; 16];
+    let mut i = 0;
+    while i < 16 {
+        values[i] = (1u32 << i) as f32;
+        i += 1;
     }
-    tiles
+    values
+};
+
+fn state_tiles(s: &State) -> weight::Matrix {
+    s.exponent_grid().map(|x| TILE_VALUES[x as usize])
 }
 
 /// Score a terminal state using a weight matrix that encourages tiles to be in
 /// one corner.
 pub fn weight_score(s: &State) -> f32 {
+    weight_score_detailed(s).0
+}
+
+/// Same as [`weight_score`], but also returns the index of the weight matrix
+/// (out of all its rotations and transposes) that gave the best score.
+pub fn weight_score_detailed(s: &State) -> (f32, usize) {
     let tiles: [f32; 16] = state_tiles(s);
-    weight::W_MATRICES
+    let scores = weight::W_MATRICES
+        .iter()
+        .enumerate()
+        .map(|(i, &w_mat)| (weight::dot(tiles, w_mat), i));
+    first_max_by(scores, |&(score, _)| score).unwrap()
+}
+
+/// The corner that [`weight_score`] rewards building toward on board `s`.
+pub fn weight_corner(s: &State) -> Corner {
+    weight::CORNERS[weight_score_detailed(s).1]
+}
+
+/// The weight matrices used by [`weight_score`], as row-major grids: the
+/// original matrix, its three right rotations, and the transposes of those
+/// four, in the order [`weight_score_detailed`] numbers them.
+pub fn weight_matrices() -> [[f64; 16]; 8] {
+    weight::W_MATRICES.map(|w| w.map(|x| x as f64))
+}
+
+/// Format a weight matrix as a 4x4 grid, one row per line.
+pub fn render_weight_matrix(w: &[f64; 16]) -> String {
+    w.chunks(4)
+        .map(|row| {
+            let cells: Vec<String> = row.iter().map(|x| format!("{x:.6}")).collect();
+            cells.join(" ") + "\n"
+        })
+        .collect()
+}
+
+/// A weight matrix for [`weight_score`]-style scoring, such as one read from a
+/// file with [`WeightConfig::load`], along with its rotations and transposes.
+/// All eight are computed once, when the config is built.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WeightConfig {
+    matrices: [[f32; 16]; 8],
+}
+
+impl Default for WeightConfig {
+    /// The weights used by [`weight_score`].
+    fn default() -> Self {
+        Self {
+            matrices: weight::W_MATRICES,
+        }
+    }
+}
+
+impl WeightConfig {
+    /// Use `base` (a row-major grid) and its symmetries, in the same order as
+    /// [`weight_matrices`].
+    pub fn from_base(base: [f32; 16]) -> Self {
+        let w1 = weight::rot_r(base);
+        let w2 = weight::rot_r(w1);
+        let w3 = weight::rot_r(w2);
+        Self {
+            matrices: [
+                base,
+                w1,
+                w2,
+                w3,
+                weight::transpose(base),
+                weight::transpose(w1),
+                weight::transpose(w2),
+                weight::transpose(w3),
+            ],
+        }
+    }
+
+    /// Parse a base matrix written as 16 numbers separated by whitespace or
+    /// commas.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let weights = text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.parse().map_err(|_| format!("invalid weight {x:?}")))
+            .collect::<Result<Vec<f32>, String>>()?;
+        let base: [f32; 16] = weights
+            .try_into()
+            .map_err(|w: Vec<f32>| format!("expected 16 weights, got {}", w.len()))?;
+        Ok(Self::from_base(base))
+    }
+
+    /// Read a base matrix from a file in the format of [`WeightConfig::parse`].
+    #[cfg(feature = "std")]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, msg))
+    }
+
+    /// The matrix the others are derived from.
+    pub fn base(&self) -> [f32; 16] {
+        self.matrices[0]
+    }
+
+    pub fn matrices(&self) -> &[[f32; 16]; 8] {
+        &self.matrices
+    }
+
+    /// Score `s` like [`weight_score`], but with these weights.
+    pub fn score(&self, s: &State) -> f32 {
+        let tiles = state_tiles(s);
+        self.matrices
+            .iter()
+            .map(|&w_mat| weight::dot(tiles, w_mat))
+            .max_by(|&x, &y| float_cmp(x, y))
+            .unwrap()
+    }
+}
+
+/// Score a state by how strongly its tiles are sorted toward `corner` (see
+/// [`State::corner_pressure`]).
+pub fn corner_pressure_score(corner: Corner) -> impl Fn(&State) -> f32 {
+    move |s: &State| s.corner_pressure(corner) as f32
+}
+
+/// Penalize a board whose two largest tiles aren't next to each other (see
+/// [`State::top_two_adjacent`]), by the value of the second-largest tile.
+pub fn top_two_adjacency_score(s: &State) -> f32 {
+    match s.top_two_tiles() {
+        Some((_, second)) if !s.top_two_adjacent() => -(s.tile(second) as f32),
+        _ => 0.0,
+    }
+}
+
+/// Score a state by how well its tiles are sorted along a snaking path from one
+/// corner, trying every rotation and transpose of the path like
+/// [`weight_score`].
+pub fn snake_score(s: &State) -> f32 {
+    let tiles = state_tiles(s);
+    weight::SNAKE_MATRICES
         .iter()
         .map(|&w_mat| weight::dot(tiles, w_mat))
         .max_by(|&x, &y| float_cmp(x, y))
         .unwrap()
 }
 
+/// A named combination of terminal scores, with hand-picked coefficients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Heuristic {
+    /// [`weight_score`], penalized when the two largest tiles are apart.
+    Corner,
+    /// [`snake_score`] alone.
+    Snake,
+    /// An even mix of [`weight_score`] and [`snake_score`], with a bonus of 5%
+    /// for each empty cell.
+    Balanced,
+}
+
+fn corner_heuristic(s: &State) -> f32 {
+    weight_score(s) + 0.1 * top_two_adjacency_score(s)
+}
+
+fn balanced_heuristic(s: &State) -> f32 {
+    let empty = s.empty_count() as f32;
+    (0.5 * weight_score(s) + 0.5 * snake_score(s)) * (1.0 + 0.05 * empty)
+}
+
+impl Heuristic {
+    pub const ALL: [Heuristic; 3] = [Heuristic::Corner, Heuristic::Snake, Heuristic::Balanced];
+
+    /// The name this heuristic is selected by.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Heuristic::Corner => "corner",
+            Heuristic::Snake => "snake",
+            Heuristic::Balanced => "balanced",
+        }
+    }
+
+    pub fn scorer(&self) -> Scorer {
+        match self {
+            Heuristic::Corner => corner_heuristic,
+            Heuristic::Snake => snake_score,
+            Heuristic::Balanced => balanced_heuristic,
+        }
+    }
+}
+
+/// The error from parsing a [`Heuristic`] with an unknown name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownHeuristic(pub String);
+
+impl fmt::Display for UnknownHeuristic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = Heuristic::ALL.iter().map(|h| h.name()).collect();
+        write!(
+            f,
+            "unknown heuristic {:?} (expected one of {})",
+            self.0,
+            names.join(", ")
+        )
+    }
+}
+
+impl core::error::Error for UnknownHeuristic {}
+
+impl FromStr for Heuristic {
+    type Err = UnknownHeuristic;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Heuristic::ALL
+            .into_iter()
+            .find(|h| h.name() == name)
+            .ok_or_else(|| UnknownHeuristic(name.to_string()))
+    }
+}
+
 /// Score a state just using the total value of all tiles, without regard to placement.
 pub fn sum_tiles_score(s: &State) -> f32 {
-    (0..16).map(|i| s.tile(i) as f32).sum()
+    state_tiles(s).iter().sum()
 }
 
-fn expectimax_score(s: &State, search_depth: u32, terminal_score: &impl Fn(&State) -> f32) -> f32 {
-    if search_depth == 0 {
-        return terminal_score(s);
+/// Score a state by how close its rows and columns are to being sorted.
+///
+/// Each line of four cells is penalized by the smaller of its total increase
+/// and its total decrease in exponent along the line (empty cells count as 0),
+/// so a board whose every row and column is sorted in one direction or the
+/// other gets the maximum score of 0.
+pub fn monotonicity_score(s: &State) -> f64 {
+    let grid = s.exponent_grid();
+    let mut penalty = 0;
+    for k in 0..4 {
+        let row = [
+            grid[k * 4],
+            grid[k * 4 + 1],
+            grid[k * 4 + 2],
+            grid[k * 4 + 3],
+        ];
+        let col = [grid[k], grid[4 + k], grid[8 + k], grid[12 + k]];
+        for line in [row, col] {
+            let (mut increase, mut decrease) = (0, 0);
+            for pair in line.windows(2) {
+                if pair[1] > pair[0] {
+                    increase += (pair[1] - pair[0]) as u32;
+                } else {
+                    decrease += (pair[0] - pair[1]) as u32;
+                }
+            }
+            penalty += increase.min(decrease);
+        }
     }
+    -(penalty as f64)
+}
 
-    fn state_place(s: &State, i: u8, x: u8) -> State {
-        let mut next_s = *s;
-        next_s.add(i as usize, x);
-        next_s
+// monotonicity_score as a terminal score for expectimax
+fn monotonicity_terminal_score(s: &State) -> f32 {
+    monotonicity_score(s) as f32
+}
+
+/// Score a state by how similar neighboring tiles are: the negative sum of the
+/// differences in exponent between each tile and the next tile to its right
+/// and below it, skipping over empty cells. A board of equal tiles scores the
+/// maximum of 0.
+pub fn smoothness_score(s: &State) -> f64 {
+    let grid = s.exponent_grid();
+    let mut penalty = 0;
+    for k in 0..4 {
+        let row = [k * 4, k * 4 + 1, k * 4 + 2, k * 4 + 3];
+        let col = [k, 4 + k, 8 + k, 12 + k];
+        for line in [row, col] {
+            let tiles: Vec<u8> = line.iter().map(|&i| grid[i]).filter(|&x| x > 0).collect();
+            for pair in tiles.windows(2) {
+                penalty += pair[0].abs_diff(pair[1]) as u32;
+            }
+        }
     }
+    -(penalty as f64)
+}
 
-    // we want to the expected value of the expectimax score over all the random
-    // placements that could happen in this state
-    let mut weighted_sum: f32 = 0.0;
-    let poss = s.empty();
-    let total_weight = poss.len() as f32;
-    for i in poss.into_iter() {
-        for (p, x) in [
+/// A weighted sum of several terminal scores.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CombinedScore {
+    /// The coefficient of [`weight_score`].
+    pub weight: f64,
+    /// The coefficient of [`smoothness_score`].
+    pub smoothness: f64,
+    /// The coefficient of [`monotonicity_score`].
+    pub monotonicity: f64,
+    /// The bonus for each empty cell.
+    pub empty: f64,
+}
+
+impl Default for CombinedScore {
+    /// Mostly [`weight_score`], with the other terms on a scale that nudges
+    /// the search between boards that score about the same.
+    fn default() -> Self {
+        Self {
+            weight: 1.0,
+            smoothness: 0.5,
+            monotonicity: 1.0,
+            empty: 2.0,
+        }
+    }
+}
+
+impl CombinedScore {
+    pub fn combined_score(&self, s: &State) -> f64 {
+        self.weight * weight_score(s) as f64
+            + self.smoothness * smoothness_score(s)
+            + self.monotonicity * monotonicity_score(s)
+            + self.empty * s.empty_count() as f64
+    }
+}
+
+/// The number of empty cells, which measures how much room there is to keep
+/// playing.
+pub fn empty_cells_score(s: &State) -> f64 {
+    s.empty_count() as f64
+}
+
+/// [`weight_score`] plus a bonus of `empty_weight` for each empty cell.
+pub fn free_space_weight_score(s: &State, empty_weight: f64) -> f64 {
+    weight_score(s) as f64 + empty_weight * empty_cells_score(s)
+}
+
+/// Which cells a chance node considers for the random tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChanceCells {
+    /// Every empty cell, in ascending index order.
+    #[default]
+    All,
+    /// At most `max_cells` of the empty cells, chosen by a shuffle seeded with
+    /// `seed` and the board, so that a sample is reproducible but doesn't favor
+    /// any position.
+    Sampled { max_cells: usize, seed: u64 },
+    /// Every empty cell while there are at most `threshold` of them, and
+    /// otherwise a sample of `max_cells` chosen as in
+    /// [`ChanceCells::Sampled`]. Early in the game, when many cells are empty
+    /// and any one placement matters little, this saves most of the search,
+    /// while boards near the end are still searched exactly.
+    Adaptive {
+        threshold: usize,
+        max_cells: usize,
+        seed: u64,
+    },
+}
+
+impl ChanceCells {
+    /// The cells to place a tile in on board `s`, as a bitmask like
+    /// [`State::empty_mask`].
+    fn mask(&self, s: &State) -> u16 {
+        let mask = s.empty_mask();
+        let count = mask.count_ones() as usize;
+        let (max_cells, seed) = match *self {
+            ChanceCells::All => return mask,
+            ChanceCells::Sampled { max_cells, seed } => (max_cells, seed),
+            ChanceCells::Adaptive {
+                threshold,
+                max_cells,
+                seed,
+            } => {
+                if count <= threshold {
+                    return mask;
+                }
+                (max_cells, seed)
+            }
+        };
+        if count <= max_cells {
+            return mask;
+        }
+        let mut cells = s.empty();
+        cells.shuffle(&mut StdRng::seed_from_u64(seed ^ s.pack()));
+        cells[..max_cells].iter().fold(0, |mask, &i| mask | 1 << i)
+    }
+
+    /// The cells to place a tile in on board `s`, in the order they're
+    /// searched.
+    pub fn cells(&self, s: &State) -> Vec<u8> {
+        game::mask_cells(self.mask(s)).collect()
+    }
+}
+
+// Every tile that could be added to `s` in one of the cells of `mask`: the
+// probability of its value and the board with it added.
+fn placements(s: &State, mask: u16) -> impl Iterator<Item = (f32, State)> + '_ {
+    game::mask_cells(mask).flat_map(move |i| {
+        [
             (game::TWO_SPAWN_PROB as f32, 1),
             (game::FOUR_SPAWN_PROB as f32, 2),
-        ] {
-            let next_s = state_place(s, i, x);
-            weighted_sum += p * expectimax_best(&next_s, search_depth - 1, terminal_score)
-                .map(|(_, _, s)| s)
-                .unwrap_or_else(|| terminal_score(s));
+        ]
+        .map(|(p, x)| {
+            let mut next_s = *s;
+            next_s.add_unchecked(i as usize, x);
+            (p, next_s)
+        })
+    })
+}
+
+// How a chance node combines the scores of the tiles that could be added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChanceValue {
+    // the expected score, for a tile added at random as in the game
+    Expected,
+    // the lowest score, for a tile added by an adversary
+    Worst,
+}
+
+// The score of a chance node that adds a tile in one of the cells of `mask`
+// (which mustn't be 0) on `s`, combining the placements according to `value`.
+// `search` scores the board after each placement, or returns None if there's
+// no legal move from it; if the game is over there's nothing left to search,
+// however much depth remains, so the dead board itself is the leaf.
+fn chance_score(
+    s: &State,
+    mask: u16,
+    value: ChanceValue,
+    terminal_score: &impl Fn(&State) -> f32,
+    mut search: impl FnMut(&State) -> Option<f32>,
+) -> f32 {
+    let mut score = |next_s: &State| search(next_s).unwrap_or_else(|| terminal_score(next_s));
+    match value {
+        ChanceValue::Expected => {
+            let mut weighted_sum: f32 = 0.0;
+            for (p, next_s) in placements(s, mask) {
+                weighted_sum += p * score(&next_s);
+            }
+            weighted_sum / mask.count_ones() as f32
         }
+        ChanceValue::Worst => placements(s, mask)
+            .map(|(_, next_s)| score(&next_s))
+            .fold(f32::INFINITY, f32::min),
+    }
+}
+
+fn expectimax_score(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+    chance: ChanceCells,
+) -> f32 {
+    if search_depth == 0 {
+        return terminal_score(s);
+    }
+    // we want to the expected value of the expectimax score over all the random
+    // placements that could happen in this state
+    let poss = chance.mask(s);
+    if poss == 0 {
+        // no tile can be added (or none was sampled), so there's no chance
+        // node to average over
+        return terminal_score(s);
     }
-    return weighted_sum / total_weight;
+    chance_score(s, poss, ChanceValue::Expected, terminal_score, |next_s| {
+        expectimax_best(next_s, search_depth - 1, terminal_score, chance).map(|(_, _, s)| s)
+    })
+}
+
+// pick the highest-scoring move; shared so that every caller agrees on ties
+fn best_scored(
+    scored_moves: impl Iterator<Item = (Move, State, f32)>,
+) -> Option<(Move, State, f32)> {
+    first_max_by(scored_moves, |&(_, _, score)| score)
 }
 
 fn expectimax_best(
     s: &State,
     search_depth: u32,
     terminal_score: &impl Fn(&State) -> f32,
+    chance: ChanceCells,
+) -> Option<(Move, State, f32)> {
+    let scored_moves = s.legal_moves().into_iter().map(|(m, s)| {
+        (
+            m,
+            s,
+            expectimax_score(&s, search_depth, terminal_score, chance),
+        )
+    });
+    best_scored(scored_moves)
+}
+
+/// Score every legal move from `s` with expectimax, in the order of
+/// [`State::legal_moves`].
+pub fn score_moves(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+) -> Vec<(Move, State, f32)> {
+    s.legal_moves()
+        .into_iter()
+        .map(|(m, s)| {
+            let score = expectimax_score(&s, search_depth, terminal_score, ChanceCells::All);
+            (m, s, score)
+        })
+        .collect()
+}
+
+/// Pick the move expectimax would choose out of the output of [`score_moves`].
+pub fn top_scored_move(scored_moves: &[(Move, State, f32)]) -> Option<(Move, State, f32)> {
+    best_scored(scored_moves.iter().copied())
+}
+
+/// How to choose between moves with exactly the same score, which happens
+/// often early in the game when the board is nearly empty.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TieBreak {
+    /// The first move in the order of [`Move::ALL`] (left, right, up, down),
+    /// which is what every search does on its own.
+    #[default]
+    PreferOrder,
+    /// The move that leaves the most empty cells, then the first in order.
+    PreferMoreEmpties,
+    /// Any of the tied moves, chosen at random.
+    Random,
+}
+
+impl TieBreak {
+    /// Pick the best move out of the output of [`score_moves`], breaking ties
+    /// with this policy. Only [`TieBreak::Random`] draws from `rng`.
+    pub fn pick<Rn: Rng>(
+        &self,
+        scored_moves: &[(Move, State, f32)],
+        rng: &mut Rn,
+    ) -> Option<(Move, State, f32)> {
+        let (_, _, best) = top_scored_move(scored_moves)?;
+        let tied = scored_moves
+            .iter()
+            .copied()
+            .filter(|&(_, _, score)| float_cmp(score, best) == Ordering::Equal);
+        match self {
+            TieBreak::PreferOrder => tied.take(1).next(),
+            TieBreak::PreferMoreEmpties => first_max_by(tied, |&(_, s, _)| s.empty_count() as f32),
+            TieBreak::Random => tied.collect::<Vec<_>>().choose(rng).copied(),
+        }
+    }
+}
+
+/// The top two moves out of [`score_moves`], when they score too closely to
+/// call.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NearTie {
+    pub best: (Move, f32),
+    pub runner_up: (Move, f32),
+}
+
+/// Check whether the top two of `scored_moves` score within `epsilon` of each
+/// other.
+pub fn near_tie(scored_moves: &[(Move, State, f32)], epsilon: f32) -> Option<NearTie> {
+    let (best, _, best_score) = top_scored_move(scored_moves)?;
+    let (runner_up, _, runner_up_score) =
+        best_scored(scored_moves.iter().copied().filter(|&(m, _, _)| m != best))?;
+    if best_score - runner_up_score > epsilon {
+        return None;
+    }
+    Some(NearTie {
+        best: (best, best_score),
+        runner_up: (runner_up, runner_up_score),
+    })
+}
+
+/// Score every legal move from `s` like [`score_moves`], sorted from best to
+/// worst. Equal scores stay in the order of [`State::legal_moves`], so the
+/// first entry is the move [`expectimax_move`] picks.
+pub fn expectimax_ranked(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+) -> Vec<(Move, State, f32)> {
+    let mut ranked = score_moves(s, search_depth, terminal_score);
+    // a stable sort, so ties go to the earlier move as in best_scored
+    ranked.sort_by(|&(_, _, x), &(_, _, y)| float_cmp(y, x));
+    ranked
+}
+
+/// Choose a move by expectimax search to a fixed depth, scoring the boards at
+/// the bottom of the search with `terminal_score`.
+pub fn expectimax_move(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+) -> Option<(Move, State)> {
+    let ranked = expectimax_ranked(s, search_depth, terminal_score);
+    ranked.first().map(|&(m, s, _)| (m, s))
+}
+
+/// Same as [`expectimax_move`], but each chance node only considers the cells
+/// picked by `chance`, which trades accuracy for a smaller search.
+pub fn expectimax_move_chance_cells(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+    chance: ChanceCells,
+) -> Option<(Move, State)> {
+    expectimax_best(s, search_depth, terminal_score, chance).map(|(m, s, _)| (m, s))
+}
+
+// sampled_best's estimate of the expected score after a tile is added to `s`
+fn sampled_score(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+    samples: usize,
+    rng: &mut impl Rng,
+) -> f32 {
+    let mask = s.empty_mask();
+    if search_depth == 0 || mask == 0 {
+        return terminal_score(s);
+    }
+    let search = |next_s: &State, rng: &mut _| {
+        sampled_best(next_s, search_depth - 1, terminal_score, samples, rng).map(|(_, _, s)| s)
+    };
+    // with few enough placements, expand them all as expectimax_score does
+    if 2 * mask.count_ones() as usize <= samples {
+        return chance_score(s, mask, ChanceValue::Expected, terminal_score, |next_s| {
+            search(next_s, rng)
+        });
+    }
+    let cells: Vec<u8> = game::mask_cells(mask).collect();
+    let mut sum = 0.0;
+    for _ in 0..samples {
+        let i = *cells.choose(rng).unwrap();
+        let x = if rng.gen_bool(game::TWO_SPAWN_PROB) {
+            1
+        } else {
+            2
+        };
+        let mut next_s = *s;
+        next_s.add_unchecked(i as usize, x);
+        sum += search(&next_s, rng).unwrap_or_else(|| terminal_score(&next_s));
+    }
+    sum / samples as f32
+}
+
+fn sampled_best(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+    samples: usize,
+    rng: &mut impl Rng,
+) -> Option<(Move, State, f32)> {
+    let scored_moves: Vec<_> = s
+        .legal_moves()
+        .into_iter()
+        .map(|(m, s)| {
+            let score = sampled_score(&s, search_depth, terminal_score, samples, rng);
+            (m, s, score)
+        })
+        .collect();
+    best_scored(scored_moves.into_iter())
+}
+
+/// Expectimax where each chance node averages over `samples` tiles drawn from
+/// `rng` (a random empty cell, then a 2 or 4 with the game's odds) rather than
+/// every tile that could be added, unlike [`expectimax_move_chance_cells`], which
+/// searches every tile in a fixed subset of the cells.
+///
+/// Each chance node costs `samples` children instead of two per empty cell,
+/// which matters most on nearly empty boards. The price is noise: every
+/// estimate is unbiased, but picking the best of several noisy estimates
+/// favors moves whose samples happened to come out high, and the fewer the
+/// samples the larger that bias. Chance nodes with at most `samples` possible
+/// tiles are expanded exactly, so with `samples` of 32 or more this picks the
+/// same move as [`expectimax_move`].
+pub fn expectimax_sampled_move(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+    samples: usize,
+    rng: &mut impl Rng,
+) -> Option<(Move, State)> {
+    sampled_best(s, search_depth, terminal_score, samples, rng).map(|(m, s, _)| (m, s))
+}
+
+// the score after the worst tile for the player is added to `s`, as chosen by
+// an adversary looking `search_depth` moves ahead
+fn worst_score(s: &State, search_depth: u32, terminal_score: &impl Fn(&State) -> f32) -> f32 {
+    let mask = s.empty_mask();
+    if search_depth == 0 || mask == 0 {
+        return terminal_score(s);
+    }
+    chance_score(s, mask, ChanceValue::Worst, terminal_score, |next_s| {
+        worst_best(next_s, search_depth - 1, terminal_score).map(|(_, _, s)| s)
+    })
+}
+
+fn worst_best(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
 ) -> Option<(Move, State, f32)> {
     let scored_moves = s
         .legal_moves()
         .into_iter()
-        .map(|(m, s)| (m, s, expectimax_score(&s, search_depth, terminal_score)));
-    scored_moves.max_by(|&(_, _, score1), &(_, _, score2)| float_cmp(score1, score2))
+        .map(|(m, s)| (m, s, worst_score(&s, search_depth, terminal_score)));
+    best_scored(scored_moves)
 }
 
-fn expectimax_move(
+/// Like [`expectimax_move`], but every tile is added by an adversary that
+/// picks the cell and value worst for the player, rather than at random.
+///
+/// This plays as if the game were out to get it, so it gives up expected score
+/// for moves that hold up even in the worst case.
+pub fn minimax_worst_move(
     s: &State,
     search_depth: u32,
     terminal_score: &impl Fn(&State) -> f32,
 ) -> Option<(Move, State)> {
-    expectimax_best(s, search_depth, terminal_score).map(|(m, s, _)| (m, s))
+    worst_best(s, search_depth, terminal_score).map(|(m, s, _)| (m, s))
 }
 
-pub fn smart_depth(s: &State) -> u32 {
-    #[allow(clippy::let_and_return)]
-    let depth = if s.empty().len() < 5 { 3 } else { 2 };
-    depth
+/// [`minimax_worst_move`] with iterative deepening within a time budget, as in
+/// [`expectimax_timed_move`].
+#[cfg(feature = "std")]
+pub fn minimax_worst_timed_move(
+    s: &State,
+    budget: Duration,
+    terminal_score: &impl Fn(&State) -> f32,
+) -> Option<(Move, State, u32)> {
+    let start = Instant::now();
+    let mut last_start = start;
+    deepening(
+        s,
+        |s, depth| minimax_worst_move(s, depth, terminal_score),
+        |depth| {
+            let now = Instant::now();
+            let predicted = (now - last_start) * DEEPENING_GROWTH;
+            last_start = now;
+            depth <= MAX_TIMED_DEPTH && (now - start) + predicted < budget
+        },
+    )
 }
 
-pub fn expectimax_weight_move(s: &State, search_depth: u32) -> Option<(Move, State)> {
-    expectimax_move(s, search_depth, &weight_score)
+/// The line of play expectimax expects from `s`, up to `plies` moves long.
+///
+/// Each entry is a board and the move expectimax picks from it. The first move
+/// is the one [`expectimax_move`] would choose; after each move, the next board
+/// stands in for the random tile with a 2 in the first empty cell, and is
+/// searched again to the same depth. The line ends early if there's no legal
+/// move.
+pub fn principal_variation(
+    s: &State,
+    search_depth: u32,
+    plies: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+) -> Vec<(State, Move)> {
+    match expectimax_best(s, search_depth, terminal_score, ChanceCells::All) {
+        Some((m, _, _)) => principal_variation_after(s, m, search_depth, plies, terminal_score),
+        None => Vec::new(),
+    }
 }
 
-pub fn expectimax_sum_move(s: &State, search_depth: u32) -> Option<(Move, State)> {
-    expectimax_move(s, search_depth, &sum_tiles_score)
+/// Like [`principal_variation`], but starting with the legal move `m` from
+/// `s`, which a caller has already chosen (perhaps by some other search).
+pub fn principal_variation_after(
+    s: &State,
+    m: Move,
+    search_depth: u32,
+    plies: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+) -> Vec<(State, Move)> {
+    let mut line = Vec::new();
+    let (mut s, mut m) = (*s, m);
+    for ply in 0..plies {
+        if ply > 0 {
+            let Some((best, _, _)) =
+                expectimax_best(&s, search_depth, terminal_score, ChanceCells::All)
+            else {
+                break;
+            };
+            m = best;
+        }
+        let Some(mut next_s) = s.apply_move(m) else {
+            break;
+        };
+        line.push((s, m));
+        let i = next_s.empty()[0];
+        next_s.add(i as usize, 1);
+        s = next_s;
+    }
+    line
+}
+
+/// Wrap `terminal_score` so that each distinct board is only scored once.
+///
+/// Within a single search the same leaf is often reached through several
+/// different move orders and spawns. The cache is keyed only on the board (not
+/// the remaining depth), so it must only wrap the terminal score.
+pub(crate) fn memoize_leaves(
+    terminal_score: &impl Fn(&State) -> f32,
+) -> impl Fn(&State) -> f32 + '_ {
+    let cache = RefCell::new(HashMap::new());
+    move |s: &State| {
+        *cache
+            .borrow_mut()
+            .entry(s.pack())
+            .or_insert_with(|| terminal_score(s))
+    }
+}
+
+/// Same as [`expectimax_move`], but memoizes `terminal_score` for the duration
+/// of this search.
+///
+/// This gives the same result as the uncached search as long as
+/// `terminal_score` is a pure function of the board.
+pub fn expectimax_move_cached(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+) -> Option<(Move, State)> {
+    expectimax_move(s, search_depth, &memoize_leaves(terminal_score))
+}
+
+/// Expectimax scores of boards already searched, keyed by the packed board and
+/// the depth it was searched to.
+///
+/// The same board is often reached at the same depth through different move
+/// orders, so [`expectimax_sum_move`] keeps a table for each search. A table
+/// can also be kept across searches, as long as they all use the same terminal
+/// score. A score is only reused for a search to the
+/// same depth, never to satisfy a deeper one.
+///
+/// A table holds at most [`TranspositionTable::max_len`] scores and is
+/// cleared when it fills up, so a long or deep search only loses the scores
+/// stored so far rather than growing without bound.
+///
+/// A table made with [`TranspositionTable::symmetric`] is keyed on
+/// [`State::canonical_key`] instead, so a board shares its score with its
+/// rotations and reflections.
+#[derive(Debug, Clone)]
+pub struct TranspositionTable {
+    scores: HashMap<(u64, u32), f32>,
+    symmetric: bool,
+    max_len: usize,
+    #[cfg(feature = "tt-stats")]
+    lookups: u64,
+    #[cfg(feature = "tt-stats")]
+    hits: u64,
+}
+
+/// The number of scores a [`TranspositionTable`] holds by default, a few tens
+/// of megabytes.
+pub const TABLE_MAX_LEN: usize = 1 << 20;
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::with_max_len(TABLE_MAX_LEN)
+    }
+}
+
+impl TranspositionTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A table that holds at most `max_len` scores.
+    pub fn with_max_len(max_len: usize) -> Self {
+        Self {
+            scores: HashMap::new(),
+            symmetric: false,
+            max_len,
+            #[cfg(feature = "tt-stats")]
+            lookups: 0,
+            #[cfg(feature = "tt-stats")]
+            hits: 0,
+        }
+    }
+
+    /// A table that stores one score for all eight symmetries of a board.
+    ///
+    /// This is only right for a terminal score that treats every symmetry
+    /// alike, as [`weight_score`] and [`sum_tiles_score`] do. Scores are then
+    /// the same up to rounding, since the tiles are added up in a different
+    /// order.
+    pub fn symmetric() -> Self {
+        Self {
+            symmetric: true,
+            ..Self::default()
+        }
+    }
+
+    /// The most scores this table holds before it's cleared.
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    fn key(&self, s: &State) -> u64 {
+        if self.symmetric {
+            s.canonical_key()
+        } else {
+            s.pack()
+        }
+    }
+
+    /// The number of (board, depth) pairs stored.
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Forget every score, for example before switching terminal scores.
+    pub fn clear(&mut self) {
+        self.scores.clear();
+    }
+
+    fn insert(&mut self, s: &State, search_depth: u32, score: f32) {
+        if self.scores.len() >= self.max_len {
+            self.scores.clear();
+        }
+        self.scores.insert((self.key(s), search_depth), score);
+    }
+
+    fn get(&mut self, s: &State, search_depth: u32) -> Option<f32> {
+        let score = self.scores.get(&(self.key(s), search_depth)).copied();
+        #[cfg(feature = "tt-stats")]
+        {
+            self.lookups += 1;
+            self.hits += score.is_some() as u64;
+        }
+        score
+    }
+
+    /// The fraction of lookups so far that found a score (0 before the first
+    /// lookup).
+    #[cfg(feature = "tt-stats")]
+    pub fn hit_rate(&self) -> f64 {
+        if self.lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / self.lookups as f64
+    }
+}
+
+// expectimax_score (with every chance cell), looking up and storing scores in
+// table
+fn table_score(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+    table: &mut TranspositionTable,
+) -> f32 {
+    if search_depth == 0 {
+        return terminal_score(s);
+    }
+    if let Some(score) = table.get(s, search_depth) {
+        return score;
+    }
+    let poss = s.empty_mask();
+    let score = if poss == 0 {
+        terminal_score(s)
+    } else {
+        chance_score(s, poss, ChanceValue::Expected, terminal_score, |next_s| {
+            table_best(next_s, search_depth - 1, terminal_score, table).map(|(_, _, s)| s)
+        })
+    };
+    table.insert(s, search_depth, score);
+    score
+}
+
+fn table_best(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+    table: &mut TranspositionTable,
+) -> Option<(Move, State, f32)> {
+    let mut scored_moves = Vec::new();
+    for (m, next_s) in s.legal_moves() {
+        let score = table_score(&next_s, search_depth, terminal_score, table);
+        scored_moves.push((m, next_s, score));
+    }
+    best_scored(scored_moves.into_iter())
+}
+
+/// Same as [`expectimax_move`], but reuses (and adds to) the scores in
+/// `table`.
+pub fn expectimax_move_table(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+    table: &mut TranspositionTable,
+) -> Option<(Move, State)> {
+    table_best(s, search_depth, terminal_score, table).map(|(m, s, _)| (m, s))
+}
+
+/// An upper bound on the terminal score of every board reachable from a board
+/// `s` after `spawns` more tiles are added, for pruning (see
+/// [`expectimax_move_pruned`]).
+pub type ScoreBound = fn(&State, u32) -> f32;
+
+// Merges never change the total value of the tiles and each added tile is
+// worth at most 4, so this bounds the total value of any board reachable from
+// `s` after `spawns` more tiles.
+fn reachable_total(s: &State, spawns: u32) -> f32 {
+    let total: u32 = s
+        .exponent_grid()
+        .iter()
+        .filter(|&&x| x > 0)
+        .map(|&x| 1 << x)
+        .sum();
+    (total + 4 * spawns) as f32
+}
+
+/// A [`ScoreBound`] for [`weight_score`]: no weight is more than the largest
+/// one, and empty cells count for 1.
+pub fn weight_score_bound(s: &State, spawns: u32) -> f32 {
+    let max_weight = weight::W_MATRICES[0].iter().copied().fold(0.0, f32::max);
+    let empty_weight: f32 = weight::W_MATRICES[0].iter().sum();
+    max_weight * reachable_total(s, spawns) + empty_weight
+}
+
+/// A [`ScoreBound`] for [`sum_tiles_score`], which can't count more than 15
+/// empty cells on a board that isn't empty.
+pub fn sum_tiles_score_bound(s: &State, spawns: u32) -> f32 {
+    reachable_total(s, spawns) + 15.0
+}
+
+// How far below a threshold a value must provably be before the search is cut
+// off. This is far more than the rounding error of averaging a few hundred
+// scores, so that a pruned node would never have won a comparison against the
+// threshold in the unpruned search either.
+fn prune_margin(threshold: f64) -> f64 {
+    1e-3 * threshold.abs().max(1.0)
+}
+
+/// The result of a pruned search: the exact score (computed the same way as
+/// the unpruned search), or an upper bound on the score when it's less than
+/// the threshold the node was searched with.
+type Pruned = Result<f32, f32>;
+
+// expectimax_score, but returns Err(alpha) as soon as the score is provably
+// less than alpha
+fn pruned_score(
+    s: &State,
+    search_depth: u32,
+    alpha: f32,
+    terminal_score: &impl Fn(&State) -> f32,
+    bound: &impl Fn(&State, u32) -> f32,
+) -> Pruned {
+    if search_depth == 0 {
+        return Ok(terminal_score(s));
+    }
+    let poss = s.empty_mask();
+    if poss == 0 {
+        return Ok(terminal_score(s));
+    }
+    let upper = bound(s, search_depth) as f64;
+    // the score must reach this for the parent to care about it exactly
+    let target = alpha as f64 - prune_margin(alpha as f64);
+    let total_weight = poss.count_ones() as f32;
+    let mut weighted_sum: f32 = 0.0;
+    let mut remaining = poss.count_ones() as f64;
+    for (p, next_s) in placements(s, poss) {
+        remaining -= p as f64;
+        // this outcome must score at least child_alpha for the average to
+        // reach the target, even if every later outcome hits the bound
+        let child_alpha =
+            (target * total_weight as f64 - weighted_sum as f64 - remaining * upper) / p as f64;
+        if child_alpha > upper {
+            return Err(alpha);
+        }
+        let score = match pruned_best(
+            &next_s,
+            search_depth - 1,
+            child_alpha as f32,
+            terminal_score,
+            bound,
+        ) {
+            Some((_, _, Ok(score))) => score,
+            Some((_, _, Err(_))) => return Err(alpha),
+            None => terminal_score(&next_s),
+        };
+        weighted_sum += p * score;
+    }
+    Ok(weighted_sum / total_weight)
+}
+
+// expectimax_best, but moves that provably score less than alpha or the best
+// move so far aren't searched exactly
+fn pruned_best(
+    s: &State,
+    search_depth: u32,
+    alpha: f32,
+    terminal_score: &impl Fn(&State) -> f32,
+    bound: &impl Fn(&State, u32) -> f32,
+) -> Option<(Move, State, Pruned)> {
+    let mut best: Option<(Move, State, f32)> = None;
+    let mut pruned_below = f32::NEG_INFINITY;
+    let mut first = None;
+    for (m, next_s) in s.legal_moves() {
+        first.get_or_insert((m, next_s));
+        let threshold = best.map_or(alpha, |(_, _, score)| score.max(alpha));
+        match pruned_score(&next_s, search_depth, threshold, terminal_score, bound) {
+            // like best_scored, ties go to the earlier move
+            Ok(score) => {
+                if best.is_none_or(|(_, _, best_score)| score > best_score) {
+                    best = Some((m, next_s, score));
+                }
+            }
+            Err(upper) => pruned_below = pruned_below.max(upper),
+        }
+    }
+    match best {
+        Some((m, next_s, score)) if score >= pruned_below => Some((m, next_s, Ok(score))),
+        _ => first.map(|(m, next_s)| (m, next_s, Err(pruned_below))),
+    }
+}
+
+/// Same as [`expectimax_move`], but skips the rest of a chance node once its
+/// expected score can't beat a move already searched, using `bound` to limit
+/// how well the unsearched tiles could turn out.
+///
+/// This always picks the same move as [`expectimax_move`], as long as `bound`
+/// really is an upper bound on `terminal_score`.
+pub fn expectimax_move_pruned(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+    bound: &impl Fn(&State, u32) -> f32,
+) -> Option<(Move, State)> {
+    pruned_best(s, search_depth, f32::NEG_INFINITY, terminal_score, bound).map(|(m, s, _)| (m, s))
+}
+
+/// A terminal score, as used by the built-in algorithms.
+pub type Scorer = fn(&State) -> f32;
+
+/// A cheap test of whether a move from the first board to the second is worth
+/// searching at all.
+pub type MoveFilter = fn(&State, &State) -> bool;
+
+/// A [`MoveFilter`] that rejects moves that take the highest tile out of the
+/// corner it's in.
+pub fn keeps_corner(before: &State, after: &State) -> bool {
+    let i = before.max_tile_position();
+    let in_corner = [0, 3, 12, 15].contains(&i);
+    !in_corner || after.max_tile_position() == i
+}
+
+/// Same as [`expectimax_move_cached`], but only searches the moves that pass
+/// `filter`, unless none of them do.
+///
+/// The filter is only applied at the root, to skip obviously bad moves before
+/// the expensive search.
+pub fn expectimax_move_prefiltered(
+    s: &State,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+    filter: MoveFilter,
+) -> Option<(Move, State)> {
+    let moves = s.legal_moves();
+    let kept: Vec<(Move, State)> = moves
+        .iter()
+        .copied()
+        .filter(|(_, next_s)| filter(s, next_s))
+        .collect();
+    let moves = if kept.is_empty() { moves } else { kept };
+    let terminal_score = memoize_leaves(terminal_score);
+    let scored_moves = moves.into_iter().map(|(m, s)| {
+        let score = expectimax_score(&s, search_depth, &terminal_score, ChanceCells::All);
+        (m, s, score)
+    });
+    best_scored(scored_moves).map(|(m, s, _)| (m, s))
+}
+
+/// Depth at which [`expectimax_timed_move`] stops deepening regardless of the
+/// time budget (deeper searches take far too long to ever finish in time).
+pub const MAX_TIMED_DEPTH: u32 = 6;
+
+/// Run expectimax at depths 1, 2, ... for as long as `keep_going(depth)` allows
+/// starting the next depth.
+///
+/// Returns the move chosen by the deepest search along with that depth. Depth
+/// 1 is always searched.
+pub fn expectimax_deepening(
+    s: &State,
+    terminal_score: &impl Fn(&State) -> f32,
+    keep_going: impl FnMut(u32) -> bool,
+) -> Option<(Move, State, u32)> {
+    deepening(
+        s,
+        |s, depth| expectimax_move(s, depth, terminal_score),
+        keep_going,
+    )
+}
+
+// iterative deepening with any search to a fixed depth
+fn deepening(
+    s: &State,
+    search: impl Fn(&State, u32) -> Option<(Move, State)>,
+    mut keep_going: impl FnMut(u32) -> bool,
+) -> Option<(Move, State, u32)> {
+    let (m, next_s) = search(s, 1)?;
+    let mut best = (m, next_s, 1);
+    let mut depth = 2;
+    while keep_going(depth) {
+        if let Some((m, next_s)) = search(s, depth) {
+            best = (m, next_s, depth);
+        }
+        depth += 1;
+    }
+    Some(best)
+}
+
+// rough factor by which each extra ply multiplies the search time
+#[cfg(feature = "std")]
+const DEEPENING_GROWTH: u32 = 30;
+
+/// Search with iterative deepening within a time budget, returning the chosen
+/// move and the depth actually completed.
+///
+/// A depth is only started if, extrapolating from the previous depth, it is
+/// expected to finish within the budget. Depth 1 is always completed, so the
+/// budget can still be exceeded on very small budgets.
+#[cfg(feature = "std")]
+pub fn expectimax_timed_move(
+    s: &State,
+    budget: Duration,
+    terminal_score: &impl Fn(&State) -> f32,
+) -> Option<(Move, State, u32)> {
+    let start = Instant::now();
+    let mut last_start = start;
+    expectimax_deepening(s, terminal_score, |depth| {
+        let now = Instant::now();
+        let predicted = (now - last_start) * DEEPENING_GROWTH;
+        last_start = now;
+        depth <= MAX_TIMED_DEPTH && (now - start) + predicted < budget
+    })
+}
+
+/// Most empty cells for which [`endgame_move`] tries an exhaustive search.
+pub const ENDGAME_MAX_EMPTY: usize = 2;
+
+/// How many moves ahead [`endgame_move`] looks for the end of the game.
+pub const ENDGAME_MAX_DEPTH: u32 = 6;
+
+// moves can open up space even on a nearly-full board, so also bound the total
+// number of boards searched
+const ENDGAME_MAX_NODES: u32 = 50_000;
+
+// Result of an exhaustive search to the end of the game.
+struct Exhaustive {
+    // None if the game is over
+    best: Option<(Move, State)>,
+    // expected number of points still to be scored
+    score: f32,
+    // the most moves the game can still last
+    depth: u32,
+}
+
+// Exhaustive expectimax to the end of the game, where the value of a board is
+// the points still to be scored from it. Gives up and returns None if the game
+// might last more than `moves_left` moves or more than `nodes` boards need to
+// be searched.
+fn exhaustive_best(s: &State, moves_left: u32, nodes: &mut u32) -> Option<Exhaustive> {
+    let moves = s.legal_moves();
+    if moves.is_empty() {
+        return Some(Exhaustive {
+            best: None,
+            score: 0.0,
+            depth: 0,
+        });
+    }
+    if moves_left == 0 || *nodes == 0 {
+        return None;
+    }
+    *nodes -= 1;
+    let mut scored_moves = Vec::with_capacity(moves.len());
+    let mut depth = 0;
+    for (m, next_s) in moves {
+        let mut weighted_sum: f32 = 0.0;
+        let poss = next_s.empty();
+        for &i in poss.iter() {
+            for (p, x) in [
+                (game::TWO_SPAWN_PROB as f32, 1),
+                (game::FOUR_SPAWN_PROB as f32, 2),
+            ] {
+                let mut spawned = next_s;
+                spawned.add_unchecked(i as usize, x);
+                let rest = exhaustive_best(&spawned, moves_left - 1, nodes)?;
+                weighted_sum += p * rest.score;
+                depth = depth.max(rest.depth + 1);
+            }
+        }
+        let score = s.merge_score(m) as f32 + weighted_sum / poss.len() as f32;
+        scored_moves.push((m, next_s, score));
+    }
+    let (m, next_s, score) = best_scored(scored_moves.into_iter())?;
+    Some(Exhaustive {
+        best: Some((m, next_s)),
+        score,
+        depth,
+    })
+}
+
+/// Find the truly optimal move in an endgame, where the board is nearly full
+/// and every line of play ends the game within a few moves.
+///
+/// This searches all the way to the end of the game and maximizes the expected
+/// final score, with no heuristic. Returns the move along with how many moves
+/// the search looked ahead, or `None` if `s` isn't such an endgame.
+pub fn endgame_move(s: &State) -> Option<(Move, State, u32)> {
+    if s.empty_count() as usize > ENDGAME_MAX_EMPTY {
+        return None;
+    }
+    let mut nodes = ENDGAME_MAX_NODES;
+    let found = exhaustive_best(s, ENDGAME_MAX_DEPTH, &mut nodes)?;
+    found.best.map(|(m, next_s)| (m, next_s, found.depth))
+}
+
+/// Most empty cells for which [`survival_move`] plays for survival.
+pub const SURVIVAL_MAX_EMPTY: usize = 2;
+
+/// The fewest legal moves there could be after move `m` from `s` and the worst
+/// possible tile, or `None` if `m` isn't legal.
+pub fn worst_case_freedom(s: &State, m: Move) -> Option<u32> {
+    let next_s = s.legal_moves().into_iter().find(|&(m2, _)| m2 == m)?.1;
+    let mut worst = u32::MAX;
+    for i in next_s.empty_cells() {
+        for x in [1, 2] {
+            let mut spawned = next_s;
+            spawned.add_unchecked(i as usize, x);
+            worst = worst.min(spawned.num_legal_moves());
+        }
+    }
+    Some(worst)
+}
+
+/// On a nearly-full board, pick the move that leaves the most legal moves
+/// after the worst possible tile (see [`worst_case_freedom`]), breaking ties
+/// with `terminal_score` on the board right after the move.
+///
+/// Returns `None` if `s` has more than [`SURVIVAL_MAX_EMPTY`] empty cells, when
+/// the usual heuristics are a better guide.
+pub fn survival_move(s: &State, terminal_score: &impl Fn(&State) -> f32) -> Option<(Move, State)> {
+    if s.empty_count() as usize > SURVIVAL_MAX_EMPTY {
+        return None;
+    }
+    let mut best: Option<(Move, State, u32, f32)> = None;
+    for (m, next_s) in s.legal_moves() {
+        let freedom = worst_case_freedom(s, m).unwrap();
+        let score = terminal_score(&next_s);
+        let better = best.is_none_or(|(_, _, best_freedom, best_score)| {
+            (freedom, score) > (best_freedom, best_score)
+        });
+        if better {
+            best = Some((m, next_s, freedom, score));
+        }
+    }
+    best.map(|(m, next_s, _, _)| (m, next_s))
+}
+
+/// The search depth [`smart_depth`] uses by number of empty cells: a board
+/// with at most `max_empty` empty cells is searched `depth` moves deep, using
+/// the first entry that applies. Every chance node branches on each empty
+/// cell, so a fuller board can afford a deeper search.
+pub const SMART_DEPTHS: [(usize, u32); 3] = [(1, 4), (4, 3), (16, 2)];
+
+/// Boards with at most this many legal moves and at most
+/// [`SMART_FEW_MOVES_MAX_EMPTY`] empty cells are searched one move deeper than
+/// [`SMART_DEPTHS`] says, since each level costs that much less.
+pub const SMART_FEW_MOVES: u32 = 2;
+
+/// The most empty cells a board can have and still get the
+/// [`SMART_FEW_MOVES`] bonus. On an emptier board the chance nodes dominate the
+/// cost, so having few moves doesn't make a deeper search cheap.
+pub const SMART_FEW_MOVES_MAX_EMPTY: usize = 4;
+
+/// Pick a search depth for `s` from [`SMART_DEPTHS`] and
+/// [`SMART_FEW_MOVES`]. A forced move gets depth 1, since there's no choice to
+/// search for.
+pub fn smart_depth(s: &State) -> u32 {
+    let empty = s.empty_count() as usize;
+    let depth = SMART_DEPTHS
+        .iter()
+        .find(|&&(max_empty, _)| empty <= max_empty)
+        .map_or(2, |&(_, depth)| depth);
+    match s.num_legal_moves() {
+        0 | 1 => 1,
+        n if n <= SMART_FEW_MOVES && empty <= SMART_FEW_MOVES_MAX_EMPTY => depth + 1,
+        _ => depth,
+    }
+}
+
+pub fn expectimax_weight_move(s: &State, search_depth: u32) -> Option<(Move, State)> {
+    // weight_score is expensive enough that caching it pays off (compare the
+    // "expectimax weight-3 cached" and "uncached" benchmarks), and pruning
+    // skips some of it altogether
+    expectimax_move_pruned(
+        s,
+        search_depth,
+        &memoize_leaves(&weight_score),
+        &weight_score_bound,
+    )
+}
+
+/// How much work a search did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchStats {
+    /// Boards scored with the terminal score.
+    pub leaves: u64,
+    /// Chance nodes expanded (boards where a tile is added in each empty cell
+    /// in turn).
+    pub nodes: u64,
+}
+
+/// Same as [`expectimax_weight_move`], but also counts how many leaves were
+/// scored and chance nodes expanded, for comparing depths and pruning.
+///
+/// Leaves are memoized, so each distinct leaf is only counted once.
+pub fn expectimax_weight_move_counted(
+    s: &State,
+    search_depth: u32,
+) -> (Option<(Move, State)>, SearchStats) {
+    let leaves = Cell::new(0);
+    let nodes = Cell::new(0);
+    let counting_score = |s: &State| {
+        leaves.set(leaves.get() + 1);
+        weight_score(s)
+    };
+    // the pruned search computes a bound once for every chance node it expands
+    let counting_bound = |s: &State, spawns: u32| {
+        nodes.set(nodes.get() + 1);
+        weight_score_bound(s, spawns)
+    };
+    let found = expectimax_move_pruned(
+        s,
+        search_depth,
+        &memoize_leaves(&counting_score),
+        &counting_bound,
+    );
+    let stats = SearchStats {
+        leaves: leaves.get(),
+        nodes: nodes.get(),
+    };
+    (found, stats)
+}
+
+/// Estimate the probability of reaching a tile with exponent `target_exp`
+/// from `s`, as the fraction of `rollouts` games that get there playing
+/// [`expectimax_weight_move`] to `depth`, with tiles drawn from `rng`.
+///
+/// Each rollout plays until it reaches the target or runs out of moves, so a
+/// distant target takes a long time to estimate. A target larger than any tile
+/// a cell can hold (see [`State::can_possibly_reach`]) has probability 0.
+pub fn win_probability(
+    s: &State,
+    target_exp: u8,
+    depth: u32,
+    rng: &mut impl Rng,
+    rollouts: usize,
+) -> f64 {
+    if target_exp > game::MAX_EXPONENT {
+        return 0.0;
+    }
+    let target = 1 << target_exp;
+    if s.highest_tile() >= target {
+        return 1.0;
+    }
+    if rollouts == 0 {
+        return 0.0;
+    }
+    let wins = (0..rollouts)
+        .filter(|_| {
+            let mut s = *s;
+            while let Some((_, next)) = expectimax_weight_move(&s, depth) {
+                if next.highest_tile() >= target {
+                    return true;
+                }
+                s = next;
+                s.rand_add(rng);
+            }
+            false
+        })
+        .count();
+    wins as f64 / rollouts as f64
+}
+
+pub fn expectimax_sum_move(s: &State, search_depth: u32) -> Option<(Move, State)> {
+    // sum_tiles_score is cheap, so the time goes into searching subtrees that
+    // the table only searches once
+    let mut table = TranspositionTable::new();
+    expectimax_move_table(s, search_depth, &sum_tiles_score, &mut table)
+}
+
+pub fn expectimax_monotonic_move(s: &State, search_depth: u32) -> Option<(Move, State)> {
+    expectimax_move_cached(s, search_depth, &monotonicity_terminal_score)
+}
+
+#[cfg(feature = "std")]
+pub fn expectimax_monotonic_timed_move(s: &State, budget: Duration) -> Option<(Move, State, u32)> {
+    expectimax_timed_move(s, budget, &memoize_leaves(&monotonicity_terminal_score))
+}
+
+/// Choose a move by expectimax, scoring boards with `score`.
+pub fn expectimax_combined_move(
+    s: &State,
+    search_depth: u32,
+    score: &CombinedScore,
+) -> Option<(Move, State)> {
+    expectimax_move_cached(s, search_depth, &|s: &State| score.combined_score(s) as f32)
+}
+
+#[cfg(feature = "std")]
+pub fn expectimax_weight_timed_move(s: &State, budget: Duration) -> Option<(Move, State, u32)> {
+    expectimax_timed_move(s, budget, &memoize_leaves(&weight_score))
+}
+
+#[cfg(feature = "std")]
+pub fn expectimax_sum_timed_move(s: &State, budget: Duration) -> Option<(Move, State, u32)> {
+    expectimax_timed_move(s, budget, &sum_tiles_score)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::game::Game;
+
+    #[test]
+    fn float_cmp_nan() {
+        assert_eq!(Ordering::Equal, float_cmp(1.5, 1.5));
+        assert_eq!(Ordering::Equal, float_cmp(f32::NAN, f32::NAN));
+        assert_eq!(Ordering::Less, float_cmp(f32::NAN, f32::NEG_INFINITY));
+        for scores in [
+            [f32::NAN, 1.0, 2.0],
+            [2.0, f32::NAN, 1.0],
+            [1.0, 2.0, f32::NAN],
+        ] {
+            let best = scores.iter().copied().max_by(|&x, &y| float_cmp(x, y));
+            assert_eq!(Some(2.0), best, "{scores:?}");
+        }
+        let s = State::default();
+        let moves = [(Move::Up, f32::NAN), (Move::Left, 1.0), (Move::Right, 1.0)];
+        let best = best_scored(moves.into_iter().map(|(m, score)| (m, s, score)));
+        assert_eq!(Some(Move::Left), best.map(|(m, _, _)| m));
+    }
+
+    #[test]
+    fn smart_depths() {
+        // no two neighbors are equal, so only cleared cells allow moves
+        let checkerboard = |cleared: &[usize]| {
+            let mut exps = [0; 16];
+            for (i, x) in exps.iter_mut().enumerate() {
+                *x = if cleared.contains(&i) {
+                    0
+                } else {
+                    1 + (i + i / 4) as u8 % 2
+                };
+            }
+            State::from_exponents(&exps).unwrap()
+        };
+        let single = |i| {
+            let mut s = State::default();
+            s.add(i, 1);
+            s
+        };
+        for (name, s, depth) in [
+            ("open board", single(5), 2),
+            ("corner tile", single(0), 2),
+            ("five empty", checkerboard(&[0, 5, 6, 9, 10]), 2),
+            ("four empty", checkerboard(&[5, 6, 9, 10]), 3),
+            ("two empty", checkerboard(&[5, 6]), 3),
+            ("two empty edge", checkerboard(&[11, 15]), 4),
+            ("one empty", checkerboard(&[5]), 4),
+            ("one empty corner", checkerboard(&[15]), 5),
+            ("forced move", checkerboard(&[0, 1, 2, 3]), 1),
+            ("game over", checkerboard(&[]), 1),
+        ] {
+            assert_eq!(depth, smart_depth(&s), "{name}:\n{s}");
+        }
+    }
+
+    /// Boards from a seeded random game, for comparing search variants.
+    fn sample_states(n: usize) -> Vec<State> {
+        let mut game = Game::from_rng(StdRng::seed_from_u64(3));
+        let mut move_rng = StdRng::seed_from_u64(4);
+        let mut states = vec![*game.state()];
+        while states.len() < n {
+            match rand_move(game.state(), &mut move_rng) {
+                Some((m, s)) => game.next_state(m, s),
+                None => game = Game::from_rng(StdRng::seed_from_u64(states.len() as u64)),
+            }
+            states.push(*game.state());
+        }
+        states
+    }
+
+    #[test]
+    fn exponent_tiles_match_values() {
+        // the original, pow-based implementation
+        fn value_tiles(s: &State) -> weight::Matrix {
+            let mut tiles = [0f32; 16];
+            for (i, tile) in tiles.iter_mut().enumerate() {
+                *tile = s.tile(i) as f32;
+            }
+            tiles
+        }
+        let states = sample_states(100);
+        for s in states.iter() {
+            assert_eq!(value_tiles(s), state_tiles(s));
+        }
+        let value_weight_score = |s: &State| {
+            weight::W_MATRICES
+                .iter()
+                .map(|&w| weight::dot(value_tiles(s), w))
+                .max_by(|&x, &y| float_cmp(x, y))
+                .unwrap()
+        };
+        let mut by_exponents = states.clone();
+        by_exponents.sort_by(|s1, s2| weight_score(s1).total_cmp(&weight_score(s2)));
+        let mut by_values = states;
+        by_values.sort_by(|s1, s2| value_weight_score(s1).total_cmp(&value_weight_score(s2)));
+        assert_eq!(by_values, by_exponents);
+    }
+
+    #[test]
+    fn deepening_stops_when_asked() {
+        let s = sample_states(10)[9];
+        let mut asked = vec![];
+        let (m, _, depth) = expectimax_deepening(&s, &weight_score, |d| {
+            asked.push(d);
+            d <= 2
+        })
+        .unwrap();
+        assert_eq!(vec![2, 3], asked);
+        assert_eq!(2, depth);
+        assert_eq!(Some(m), expectimax_weight_move(&s, 2).map(|(m, _)| m));
+    }
+
+    #[test]
+    fn timed_search_always_completes_depth_1() {
+        let s = sample_states(10)[9];
+        let (_, _, depth) = expectimax_weight_timed_move(&s, Duration::ZERO).unwrap();
+        assert_eq!(1, depth);
+    }
+
+    #[test]
+    fn cached_search_matches_uncached() {
+        for s in sample_states(50) {
+            assert_eq!(
+                expectimax_move(&s, 2, &weight_score),
+                expectimax_move_cached(&s, 2, &weight_score),
+                "different moves for\n{s}"
+            );
+        }
+    }
+
+    #[test]
+    fn cached_search_scores_fewer_leaves() {
+        let count = Cell::new(0);
+        let counting_score = |s: &State| {
+            count.set(count.get() + 1);
+            weight_score(s)
+        };
+        let s = sample_states(20)[19];
+        expectimax_move(&s, 2, &counting_score);
+        let uncached = count.replace(0);
+        expectimax_move_cached(&s, 2, &counting_score);
+        let cached = count.get();
+        // every distinct leaf is scored once, instead of once per path to it
+        assert_eq!(14240, uncached);
+        assert_eq!(1757, cached);
+    }
+
+    #[test]
+    fn table_search_matches_uncached() {
+        // one table for the whole game, as when playing
+        let mut table = TranspositionTable::new();
+        for s in sample_states(30) {
+            assert_eq!(
+                expectimax_move(&s, 2, &weight_score),
+                expectimax_move_table(&s, 2, &weight_score, &mut table),
+                "different moves for\n{s}"
+            );
+        }
+        assert!(!table.is_empty());
+    }
+
+    #[test]
+    fn table_is_capped() {
+        let mut table = TranspositionTable::with_max_len(100);
+        for s in sample_states(30) {
+            assert_eq!(
+                expectimax_move(&s, 2, &weight_score),
+                expectimax_move_table(&s, 2, &weight_score, &mut table)
+            );
+            assert!(table.len() <= 100);
+        }
+    }
+
+    #[test]
+    fn symmetric_table() {
+        let mut table = TranspositionTable::new();
+        let mut symmetric = TranspositionTable::symmetric();
+        for s in sample_states(30) {
+            // symmetric moves can tie, and rounding then decides between
+            // them, so compare the scores rather than the moves
+            let (_, _, best) = expectimax_best(&s, 2, &weight_score, ChanceCells::All).unwrap();
+            let (_, _, found) = table_best(&s, 2, &weight_score, &mut symmetric).unwrap();
+            assert!(
+                (best - found).abs() <= 1e-4 * best.abs(),
+                "{best} {found} for\n{s}"
+            );
+            expectimax_move_table(&s, 2, &weight_score, &mut table);
+        }
+        assert!(symmetric.len() < table.len());
+    }
+
+    #[test]
+    fn table_is_keyed_on_depth() {
+        let s = sample_states(20)[19];
+        let mut table = TranspositionTable::new();
+        expectimax_move_table(&s, 1, &weight_score, &mut table);
+        let shallow = table.len();
+        // a deeper search can't use the depth-1 scores of the same boards
+        assert_eq!(
+            expectimax_move(&s, 2, &weight_score),
+            expectimax_move_table(&s, 2, &weight_score, &mut table)
+        );
+        assert!(table.len() > 2 * shallow);
+    }
+
+    #[cfg(feature = "tt-stats")]
+    #[test]
+    fn table_hit_rate() {
+        let s = sample_states(20)[19];
+        let mut table = TranspositionTable::new();
+        assert_eq!(0.0, table.hit_rate());
+        expectimax_move_table(&s, 2, &weight_score, &mut table);
+        let first = table.hit_rate();
+        // repeating the search finds every root move in the table
+        expectimax_move_table(&s, 2, &weight_score, &mut table);
+        assert!(table.hit_rate() > first);
+    }
+
+    #[test]
+    fn pruned_search_matches_unpruned() {
+        for s in sample_states(60) {
+            for depth in 1..=2 {
+                assert_eq!(
+                    expectimax_move(&s, depth, &weight_score),
+                    expectimax_move_pruned(&s, depth, &weight_score, &weight_score_bound),
+                    "different weight moves at depth {depth} for\n{s}"
+                );
+                assert_eq!(
+                    expectimax_move(&s, depth, &sum_tiles_score),
+                    expectimax_move_pruned(&s, depth, &sum_tiles_score, &sum_tiles_score_bound),
+                    "different sum moves at depth {depth} for\n{s}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn score_bounds_hold() {
+        for s in sample_states(100) {
+            for (m, next_s) in s.legal_moves() {
+                let mut next_s = next_s;
+                next_s.add(next_s.empty()[0] as usize, 2);
+                assert!(
+                    weight_score(&next_s) <= weight_score_bound(&s, 1),
+                    "{m:?} from\n{s}"
+                );
+                assert!(
+                    sum_tiles_score(&next_s) <= sum_tiles_score_bound(&s, 1),
+                    "{m:?} from\n{s}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pruning_scores_fewer_leaves() {
+        let count = Cell::new(0);
+        let counting_score = |s: &State| {
+            count.set(count.get() + 1);
+            sum_tiles_score(s)
+        };
+        let s = sample_states(20)[19];
+        expectimax_move(&s, 2, &counting_score);
+        let unpruned = count.replace(0);
+        expectimax_move_pruned(&s, 2, &counting_score, &sum_tiles_score_bound);
+        let pruned = count.get();
+        assert!(pruned < unpruned, "{pruned} >= {unpruned}");
+    }
+
+    #[test]
+    fn counted_search() {
+        let mut s = State::default();
+        s.add(0, 1);
+        s.add(5, 1);
+        let mut last = SearchStats::default();
+        for depth in 1..=2 {
+            let (found, stats) = expectimax_weight_move_counted(&s, depth);
+            assert_eq!(expectimax_weight_move(&s, depth), found);
+            assert!(stats.leaves > last.leaves, "{stats:?} at depth {depth}");
+            assert!(stats.nodes > last.nodes, "{stats:?} at depth {depth}");
+            last = stats;
+        }
+        // the first level has a chance node for each legal move
+        let (_, stats) = expectimax_weight_move_counted(&s, 1);
+        assert_eq!(s.num_legal_moves() as u64, stats.nodes);
+    }
+
+    #[test]
+    fn ranked_moves() {
+        for s in sample_states(30) {
+            let ranked = expectimax_ranked(&s, 1, &weight_score);
+            assert_eq!(s.num_legal_moves() as usize, ranked.len());
+            assert!(
+                ranked.windows(2).all(|w| w[0].2 >= w[1].2),
+                "not sorted: {ranked:?}"
+            );
+            assert_eq!(
+                expectimax_weight_move(&s, 1),
+                ranked.first().map(|&(m, s, _)| (m, s))
+            );
+        }
+        assert!(expectimax_ranked(&State::default(), 2, &weight_score).is_empty());
+    }
+
+    #[test]
+    fn tie_breaks() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let s = State::from_tiles([1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let left = s.apply_move(Move::Left).unwrap();
+        let down = s.apply_move(Move::Down).unwrap();
+        // down keeps both tiles, so it leaves one fewer empty cell
+        let scored = [(Move::Down, down, 1.0), (Move::Left, left, 1.0)];
+        assert_eq!(
+            Some(Move::Down),
+            TieBreak::PreferOrder
+                .pick(&scored, &mut rng)
+                .map(|(m, _, _)| m)
+        );
+        assert_eq!(
+            Some(Move::Left),
+            TieBreak::PreferMoreEmpties
+                .pick(&scored, &mut rng)
+                .map(|(m, _, _)| m)
+        );
+        let picked: Vec<_> = (0..20)
+            .filter_map(|_| TieBreak::Random.pick(&scored, &mut rng))
+            .map(|(m, _, _)| m)
+            .collect();
+        assert!(picked.contains(&Move::Left) && picked.contains(&Move::Down));
+        // a strictly better move wins under every policy
+        let scored = [scored[0], (Move::Up, s, 2.0), scored[1]];
+        for tie_break in [
+            TieBreak::PreferOrder,
+            TieBreak::PreferMoreEmpties,
+            TieBreak::Random,
+        ] {
+            let (m, _, _) = tie_break.pick(&scored, &mut rng).unwrap();
+            assert_eq!(Move::Up, m);
+        }
+        assert_eq!(None, TieBreak::Random.pick(&[], &mut rng));
+    }
+
+    #[test]
+    fn win_probabilities() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let won = State::from_tiles([11, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(1.0, win_probability(&won, 11, 1, &mut rng, 10));
+        // nothing can merge, and any new tile fills the board for good
+        let dead = State::from_tiles([3, 4, 3, 8, 4, 3, 4, 9, 3, 4, 3, 10, 5, 6, 7, 0]);
+        assert_eq!(0.0, win_probability(&dead, 11, 1, &mut rng, 10));
+        let near = State::from_tiles([2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(1.0, win_probability(&near, 3, 1, &mut rng, 10));
+        // no cell can hold a tile this large
+        assert_eq!(0.0, win_probability(&near, 32, 1, &mut rng, 10));
+        assert_eq!(0.0, win_probability(&won, 255, 1, &mut rng, 10));
+    }
+
+    #[test]
+    fn rng_sampled_search() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for s in sample_states(20) {
+            // enough samples to cover every tile on a 16-cell board
+            assert_eq!(
+                expectimax_move(&s, 2, &sum_tiles_score),
+                expectimax_sampled_move(&s, 2, &sum_tiles_score, 32, &mut rng)
+            );
+            let (m, next) = expectimax_sampled_move(&s, 2, &weight_score, 3, &mut rng).unwrap();
+            assert_eq!(s.apply_move(m), Some(next));
+        }
+        let none = expectimax_sampled_move(&State::default(), 2, &weight_score, 3, &mut rng);
+        assert!(none.is_none());
+    }
+
+    #[test]
+    fn sampled_chance_nodes_average_to_expectimax() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let trials = 2000;
+        for s in sample_states(20).iter().filter(|s| s.empty_count() >= 4) {
+            // too few samples to expand every tile, so they're drawn at random
+            let samples = 4;
+            assert!(2 * s.empty_count() as usize > samples);
+            let exact = expectimax_score(s, 1, &weight_score, ChanceCells::All);
+            let mean = (0..trials)
+                .map(|_| sampled_score(s, 1, &weight_score, samples, &mut rng) as f64)
+                .sum::<f64>()
+                / trials as f64;
+            let error = (mean - exact as f64).abs() / exact as f64;
+            assert!(error < 0.005, "{s:?}: {mean} vs {exact}");
+        }
+    }
+
+    #[test]
+    fn endgame_finds_optimal_move() {
+        // Moving right keeps the game going long enough to eventually merge
+        // the two 128s, while moving up (which the heuristic prefers) ends it
+        // within a few moves for only a few points.
+        let s = State::from_exponents(&[6, 7, 0, 0, 4, 8, 5, 8, 8, 6, 4, 6, 4, 7, 5, 3]).unwrap();
+        let (m, _, depth) = endgame_move(&s).expect("should be an endgame");
+        assert_eq!(Move::Right, m);
+        assert!(depth <= ENDGAME_MAX_DEPTH);
+        assert_eq!(
+            Some(Move::Up),
+            expectimax_weight_move(&s, smart_depth(&s)).map(|(m, _)| m)
+        );
+    }
+
+    #[test]
+    fn no_endgame_on_open_board() {
+        for s in sample_states(20) {
+            if s.empty_count() as usize > ENDGAME_MAX_EMPTY {
+                assert_eq!(None, endgame_move(&s));
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric_moves_tie() {
+        // a lone tile in the corner can go right or down, which are mirror
+        // images of each other
+        let s = State::from_exponents(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        let tie = near_tie(&score_moves(&s, 1, &weight_score), 1e-3).expect("moves should tie");
+        let mut moves = [tie.best.0, tie.runner_up.0];
+        moves.sort_by_key(|&m| m as u8);
+        assert_eq!([Move::Right, Move::Down], moves);
+
+        // no symmetry here, so the top two moves are clearly separated
+        let s = State::from_exponents(&[6, 5, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 0]).unwrap();
+        assert_eq!(None, near_tie(&score_moves(&s, 1, &weight_score), 1e-3));
+        // no legal moves means no tie
+        assert_eq!(
+            None,
+            near_tie(&score_moves(&State::default(), 1, &weight_score), 1e-3)
+        );
+    }
+
+    #[test]
+    fn game_over_is_terminal_at_any_depth() {
+        // Left and right both merge the 64s, and then any tile added to the
+        // one empty cell ends the game.
+        let s = State::from_exponents(&[1, 2, 1, 2, 2, 1, 2, 1, 3, 2, 1, 3, 4, 5, 6, 6]).unwrap();
+        let scored = score_moves(&s, 50, &sum_tiles_score);
+        assert_eq!(2, scored.len());
+        for (m, next_s, score) in scored {
+            let empty = next_s.empty();
+            assert_eq!(1, empty.len());
+            let expected: f32 = [(0.9, 1), (0.1, 2)]
+                .into_iter()
+                .map(|(p, x)| {
+                    let mut dead = next_s;
+                    dead.add(empty[0] as usize, x);
+                    assert!(dead.legal_moves().is_empty());
+                    p * sum_tiles_score(&dead)
+                })
+                .sum();
+            assert!(
+                (expected - score).abs() < 1e-3,
+                "{m:?}: {expected} != {score}"
+            );
+        }
+    }
+
+    #[test]
+    fn chance_cells_order() {
+        for s in sample_states(10) {
+            assert_eq!(s.empty(), ChanceCells::All.cells(&s));
+        }
+    }
+
+    #[test]
+    fn chance_cells_sampled() {
+        let s = State::from_exponents(&[1, 0, 0, 2, 0, 0, 3, 0, 0, 1, 0, 0, 2, 0, 0, 1]).unwrap();
+        let sampled = |seed| ChanceCells::Sampled { max_cells: 2, seed }.cells(&s);
+        assert_eq!(sampled(7), sampled(7));
+        // every empty cell is about equally likely to be sampled
+        let mut counts = [0u32; 16];
+        for seed in 0..4000 {
+            let cells = sampled(seed);
+            assert_eq!(2, cells.len());
+            assert_ne!(cells[0], cells[1]);
+            for i in cells {
+                counts[i as usize] += 1;
+            }
+        }
+        for i in s.empty() {
+            // 8000 samples over 10 empty cells
+            let n = counts[i as usize];
+            assert!((650..950).contains(&n), "cell {i} sampled {n} times");
+        }
+        // small boards are searched in full
+        let all = ChanceCells::Sampled {
+            max_cells: 16,
+            seed: 0,
+        };
+        assert_eq!(s.empty(), all.cells(&s));
+        for s in sample_states(5) {
+            assert_eq!(
+                expectimax_move(&s, 2, &weight_score),
+                expectimax_move_chance_cells(&s, 2, &weight_score, all)
+            );
+        }
+    }
+
+    #[test]
+    fn minimax_worst() {
+        let s = State::from_tiles([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 0]);
+        let (expected, _) = expectimax_move(&s, 1, &weight_score).unwrap();
+        let (paranoid, _) = minimax_worst_move(&s, 1, &weight_score).unwrap();
+        assert_eq!(Move::Left, expected);
+        assert_eq!(Move::Up, paranoid);
+        // the adversary's move is the one with the best worst case
+        let worst_after = |m| worst_score(&s.apply_move(m).unwrap(), 1, &weight_score);
+        assert!(worst_after(paranoid) > worst_after(expected));
+        for s in sample_states(10) {
+            assert!(
+                worst_best(&s, 1, &weight_score).map(|(_, _, x)| x)
+                    <= expectimax_best(&s, 1, &weight_score, ChanceCells::All).map(|(_, _, x)| x)
+            );
+        }
+    }
+
+    #[test]
+    fn chance_cells_adaptive() {
+        let adaptive = ChanceCells::Adaptive {
+            threshold: 4,
+            max_cells: 2,
+            seed: 3,
+        };
+        // a crowded board is expanded in full
+        let endgame =
+            State::from_exponents(&[1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 0, 6, 7, 8, 0, 9]).unwrap();
+        assert_eq!(3, endgame.empty_count());
+        assert_eq!(endgame.empty(), adaptive.cells(&endgame));
+        assert_eq!(
+            expectimax_move(&endgame, 2, &weight_score),
+            expectimax_move_chance_cells(&endgame, 2, &weight_score, adaptive)
+        );
+        // while an open one is sampled
+        let open =
+            State::from_exponents(&[1, 0, 0, 2, 0, 0, 3, 0, 0, 1, 0, 0, 2, 0, 0, 1]).unwrap();
+        let cells = adaptive.cells(&open);
+        assert_eq!(2, cells.len());
+        assert!(cells.iter().all(|i| open.empty().contains(i)));
+    }
+
+    #[test]
+    fn weight_matrices_exported() {
+        let ws = weight_matrices();
+        for (w, w_mat) in ws.iter().zip(weight::W_MATRICES) {
+            assert_eq!(w_mat.map(|x| x as f64), *w);
+        }
+        let rot_r =
+            |w: &[f64; 16]| -> [f64; 16] { std::array::from_fn(|k| w[(3 - k % 4) * 4 + k / 4]) };
+        let transpose =
+            |w: &[f64; 16]| -> [f64; 16] { std::array::from_fn(|k| w[k % 4 * 4 + k / 4]) };
+        for i in 0..4 {
+            assert_eq!(ws[(i + 1) % 4], rot_r(&ws[i]));
+            assert_eq!(ws[i + 4], transpose(&ws[i]));
+        }
+
+        let grid = render_weight_matrix(&ws[0]);
+        assert_eq!(4, grid.lines().count());
+        assert!(grid.starts_with("0.135759 0.121925 "), "{grid}");
+    }
+
+    #[test]
+    fn monotonicity() {
+        // the same tiles, snaking back and forth from the top-left corner, and
+        // then shuffled
+        let snake = State::from_tiles([9, 8, 7, 6, 2, 3, 4, 5, 1, 1, 0, 0, 0, 0, 0, 0]);
+        let scrambled = State::from_tiles([3, 8, 1, 6, 2, 9, 4, 0, 1, 5, 0, 7, 0, 0, 0, 0]);
+        assert_eq!(0.0, monotonicity_score(&snake));
+        assert!(monotonicity_score(&snake) > monotonicity_score(&scrambled));
+        // a single row out of order costs its smaller direction
+        let bump = State::from_tiles([1, 3, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        // row: up 2, down 3; columns 1 and 2 each fall by 3 and 2 (no penalty)
+        assert_eq!(-2.0, monotonicity_score(&bump));
+        assert_eq!(0.0, monotonicity_score(&State::default()));
+    }
+
+    #[test]
+    fn smoothness() {
+        let rough = State::from_tiles([1, 3, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        // right: |1-3|; down: |3-5|
+        assert_eq!(-4.0, smoothness_score(&rough));
+        let mut smoother = rough;
+        smoother.set(0, 3);
+        assert!(smoothness_score(&smoother) > smoothness_score(&rough));
+        smoother.set(5, 3);
+        assert_eq!(0.0, smoothness_score(&smoother));
+        // empty cells are skipped, rather than counted as 0
+        let gap = State::from_tiles([2, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(0.0, smoothness_score(&gap));
+    }
+
+    #[test]
+    fn combined_score() {
+        let s = sample_states(20)[19];
+        let only = |weight, smoothness, monotonicity, empty| {
+            CombinedScore {
+                weight,
+                smoothness,
+                monotonicity,
+                empty,
+            }
+            .combined_score(&s)
+        };
+        assert_eq!(weight_score(&s) as f64, only(1.0, 0.0, 0.0, 0.0));
+        assert_eq!(smoothness_score(&s), only(0.0, 1.0, 0.0, 0.0));
+        assert_eq!(monotonicity_score(&s), only(0.0, 0.0, 1.0, 0.0));
+        assert_eq!(s.empty_count() as f64, only(0.0, 0.0, 0.0, 1.0));
+        assert!(expectimax_combined_move(&s, 1, &CombinedScore::default()).is_some());
+    }
+
+    #[test]
+    fn free_space() {
+        for s in sample_states(100) {
+            assert_eq!(weight_score(&s) as f64, free_space_weight_score(&s, 0.0));
+            // filling any cell with a 2 adds less to weight_score than the
+            // empty cell was worth
+            for i in s.empty() {
+                let mut fuller = s;
+                fuller.add(i as usize, 1);
+                assert!(
+                    free_space_weight_score(&s, 1.0) > free_space_weight_score(&fuller, 1.0),
+                    "filling {i} scored higher from\n{s}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn heuristics() {
+        let states = sample_states(20);
+        for h in Heuristic::ALL {
+            assert_eq!(Ok(h), h.name().parse());
+            let score = h.scorer();
+            let scores: Vec<f32> = states.iter().map(score).collect();
+            assert!(scores.iter().all(|&x| x > 0.0), "{h:?}: {scores:?}");
+            assert!(scores.iter().any(|&x| x != scores[0]), "{h:?} is constant");
+        }
+        let err = "diagonal".parse::<Heuristic>().unwrap_err();
+        assert_eq!(UnknownHeuristic("diagonal".to_string()), err);
+        assert_eq!(
+            "unknown heuristic \"diagonal\" (expected one of corner, snake, balanced)",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn snake_score() {
+        // a board sorted along the snake gets the highest score of its tiles
+        let sorted =
+            State::from_exponents(&[8, 7, 6, 5, 1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        let shuffled =
+            State::from_exponents(&[8, 1, 6, 5, 7, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert!(super::snake_score(&sorted) > super::snake_score(&shuffled));
+    }
+
+    #[test]
+    fn weight_config() {
+        let default = WeightConfig::default();
+        assert_eq!(default, WeightConfig::from_base(default.base()));
+        for s in sample_states(10) {
+            assert_eq!(weight_score(&s), default.score(&s));
+        }
+
+        let base: [f32; 16] = std::array::from_fn(|i| i as f32 / 2.0);
+        let text: Vec<String> = base.iter().map(|x| x.to_string()).collect();
+        let path = std::env::temp_dir().join(format!("r2048-weights-{}", std::process::id()));
+        fs::write(&path, text.join(" ")).unwrap();
+        let loaded = WeightConfig::load(&path);
+        fs::remove_file(&path).unwrap();
+        let w = loaded.unwrap();
+        assert_eq!(base, w.base());
+        let ws = w.matrices();
+        // the largest weight is in the bottom-right corner, and rotating right
+        // moves it to the bottom-left
+        assert_eq!(15.0 / 2.0, ws[1][12]);
+        for i in 0..4 {
+            for k in 0..16 {
+                assert_eq!(ws[i][(3 - k % 4) * 4 + k / 4], ws[(i + 1) % 4][k]);
+                assert_eq!(ws[i][k % 4 * 4 + k / 4], ws[i + 4][k]);
+            }
+        }
+
+        // a grid written as comma-separated rows
+        let rows: Vec<String> = base
+            .chunks(4)
+            .map(|row| {
+                let row: Vec<String> = row.iter().map(|x| x.to_string()).collect();
+                row.join(", ") + ","
+            })
+            .collect();
+        assert_eq!(Ok(w), WeightConfig::parse(&rows.join("\n")));
+
+        assert_eq!(
+            Err("expected 16 weights, got 3".to_string()),
+            WeightConfig::parse("1, 2, 3")
+        );
+        assert!(WeightConfig::parse(&"x ".repeat(16)).is_err());
+    }
+
+    #[test]
+    fn survival() {
+        let s = State::from_exponents(&[2, 0, 2, 3, 0, 1, 6, 3, 3, 1, 4, 1, 4, 2, 1, 2]).unwrap();
+        let (heuristic, _) = expectimax_weight_move(&s, smart_depth(&s)).unwrap();
+        let (survival, _) = survival_move(&s, &weight_score).unwrap();
+        assert_eq!(Move::Left, heuristic);
+        assert_eq!(Move::Right, survival);
+        // after Left a tile can leave only two moves, but after Right there
+        // are always four
+        assert_eq!(Some(2), worst_case_freedom(&s, heuristic));
+        assert_eq!(Some(4), worst_case_freedom(&s, survival));
+
+        assert_eq!(None, survival_move(&sample_states(1)[0], &weight_score));
+        let corner =
+            State::from_exponents(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!(None, worst_case_freedom(&corner, Move::Up));
+    }
+
+    #[test]
+    fn sparse_boards() {
+        let empty = State::default();
+        assert_eq!(None, expectimax_weight_move(&empty, 2));
+        assert_eq!(
+            None,
+            expectimax_sum_timed_move(&empty, Duration::from_millis(1))
+        );
+        assert_eq!(None, endgame_move(&empty));
+        assert!(score_moves(&empty, 2, &weight_score).is_empty());
+        let one = State::from_tiles([0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        for depth in 1..=3 {
+            assert!(expectimax_weight_move(&one, depth).is_some());
+            assert!(expectimax_sum_move(&one, depth).is_some());
+        }
+        assert_eq!(2, principal_variation(&one, 1, 2, &weight_score).len());
+        // sampling no cells at all scores the board right after the move
+        let none = ChanceCells::Sampled {
+            max_cells: 0,
+            seed: 0,
+        };
+        let (_, _, score) = expectimax_best(&one, 2, &weight_score, none).unwrap();
+        assert!(score.is_finite());
+    }
+
+    #[test]
+    fn weight_corners() {
+        // each matrix's largest weight is in its corner
+        for (w_mat, corner) in weight::W_MATRICES.iter().zip(weight::CORNERS) {
+            let max_i = (0..16)
+                .max_by(|&i, &j| w_mat[i].total_cmp(&w_mat[j]))
+                .unwrap();
+            let corner_i = match corner {
+                Corner::TopLeft => 0,
+                Corner::TopRight => 3,
+                Corner::BottomLeft => 12,
+                Corner::BottomRight => 15,
+            };
+            assert_eq!(corner_i, max_i, "{corner:?}");
+        }
+        let s = State::from_exponents(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 3, 9]).unwrap();
+        assert_eq!(Corner::BottomRight, weight_corner(&s));
+    }
+
+    #[test]
+    fn adjacent_top_tiles_score_better() {
+        let together =
+            State::from_exponents(&[8, 7, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        let apart =
+            State::from_exponents(&[8, 0, 0, 7, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!(0.0, top_two_adjacency_score(&together));
+        assert_eq!(-128.0, top_two_adjacency_score(&apart));
+        assert_eq!(0.0, top_two_adjacency_score(&State::default()));
+    }
+
+    #[test]
+    fn prefilter_skips_moves() {
+        // 256 in the corner; moving right or down takes it out
+        let s = State::from_exponents(&[8, 0, 0, 0, 2, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        let legal: Vec<Move> = s.legal_moves().iter().map(|&(m, _)| m).collect();
+        assert_eq!(vec![Move::Right, Move::Up, Move::Down], legal);
+
+        let count = Cell::new(0);
+        let counting_score = |s: &State| {
+            count.set(count.get() + 1);
+            weight_score(s)
+        };
+        expectimax_move_cached(&s, 2, &counting_score);
+        let all_leaves = count.replace(0);
+        let (m, _) = expectimax_move_prefiltered(&s, 2, &counting_score, keeps_corner).unwrap();
+        assert_eq!(Move::Up, m);
+        assert!(count.get() < all_leaves, "{} >= {all_leaves}", count.get());
+
+        // if every move fails the filter, they're all searched
+        let (m, _) = expectimax_move_prefiltered(&s, 1, &weight_score, |_, _| false).unwrap();
+        assert!(legal.contains(&m));
+    }
+
+    #[test]
+    fn principal_variation_is_legal() {
+        for s in sample_states(5) {
+            let line = principal_variation(&s, 2, 4, &weight_score);
+            assert!(!line.is_empty());
+            assert_eq!(s, line[0].0);
+            assert_eq!(
+                expectimax_move(&s, 2, &weight_score).map(|(m, _)| m),
+                Some(line[0].1)
+            );
+            for &(from, m) in line.iter() {
+                assert!(from.legal_moves().iter().any(|&(legal, _)| legal == m));
+            }
+            // each board follows from the previous move
+            for pair in line.windows(2) {
+                let (from, m) = pair[0];
+                let next = from
+                    .legal_moves()
+                    .into_iter()
+                    .find(|&(legal, _)| legal == m);
+                assert_eq!(1, next.unwrap().1.cell_distance(&pair[1].0));
+            }
+        }
+    }
 }
@@ -0,0 +1,222 @@
+//! Search in a background thread, so that a UI can stay responsive and ask for
+//! a move whenever it needs one.
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use rand::thread_rng;
+
+use crate::ai::{
+    expectimax_monotonic_move, expectimax_move_cached, expectimax_sampled_move,
+    expectimax_sum_move, expectimax_weight_move, minimax_worst_move, rand_move, weight_score,
+    MAX_TIMED_DEPTH,
+};
+use crate::game::{Move, State};
+use crate::mcts::mcts_move;
+use crate::{Algorithm, Config};
+
+// The best move and the depth it was found at fit in one atomic: the low two
+// bits are the move's index in Move::ALL and the rest are the depth plus one,
+// so that 0 means no move has been found yet.
+fn encode(m: Move, depth: u32) -> u32 {
+    let i = Move::ALL.iter().position(|&x| x == m).unwrap() as u32;
+    (depth + 1) << 2 | i
+}
+
+fn decode(x: u32) -> Option<(Move, u32)> {
+    if x == 0 {
+        return None;
+    }
+    Some((Move::ALL[(x & 3) as usize], (x >> 2) - 1))
+}
+
+// a search of a board to a given depth
+type DepthSearch<'a> = dyn Fn(&State, u32) -> Option<(Move, State)> + 'a;
+
+/// An iterative-deepening search of one board running in another thread.
+///
+/// The search goes as deep as [`MAX_TIMED_DEPTH`], publishing the move from
+/// each completed depth. The thread only checks whether to stop between depths,
+/// so [`BackgroundSearch::stop`] (or dropping the search) waits for the depth in
+/// progress to finish. That keeps abandoned searches from piling up, but on an
+/// open board the deepest searches can take seconds.
+#[derive(Debug)]
+pub struct BackgroundSearch {
+    best: Arc<AtomicU32>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BackgroundSearch {
+    /// Start searching `s` with the configured algorithm, ignoring its depth.
+    /// With [`Algorithm::Agent`], the agent chooses a move once.
+    pub fn start(s: State, config: &Config) -> Self {
+        let best = Arc::new(AtomicU32::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let config = config.clone();
+        let thread = {
+            let best = best.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let score = |s: &State| {
+                    if config.custom_weight_score() {
+                        config.weight_score(s)
+                    } else {
+                        weight_score(s)
+                    }
+                };
+                let search: Box<DepthSearch> = match config.algorithm {
+                    Algorithm::ExpectimaxSum(_) => Box::new(expectimax_sum_move),
+                    Algorithm::ExpectimaxWeight(_) if config.custom_weight_score() => {
+                        Box::new(|s, depth| expectimax_move_cached(s, depth, &score))
+                    }
+                    Algorithm::ExpectimaxWeight(_) => Box::new(expectimax_weight_move),
+                    Algorithm::ExpectimaxHeuristic(_, h) => {
+                        let score = h.scorer();
+                        Box::new(move |s, depth| expectimax_move_cached(s, depth, &score))
+                    }
+                    Algorithm::ExpectimaxMonotonic(_) => Box::new(expectimax_monotonic_move),
+                    Algorithm::MinimaxWorst(_) => {
+                        Box::new(|s, depth| minimax_worst_move(s, depth, &score))
+                    }
+                    Algorithm::ExpectimaxSampled { depth, samples } => {
+                        let found =
+                            expectimax_sampled_move(&s, depth, &score, samples, &mut thread_rng());
+                        if let Some((m, _)) = found {
+                            best.store(encode(m, depth), Ordering::SeqCst);
+                        }
+                        return;
+                    }
+                    Algorithm::Mcts { iterations } => {
+                        if let Some((m, _)) = mcts_move(&s, iterations, &mut thread_rng()) {
+                            best.store(encode(m, 0), Ordering::SeqCst);
+                        }
+                        return;
+                    }
+                    Algorithm::Random => {
+                        if let Some((m, _)) = rand_move(&s, &mut thread_rng()) {
+                            best.store(encode(m, 0), Ordering::SeqCst);
+                        }
+                        return;
+                    }
+                    Algorithm::Agent => {
+                        if let Some((m, _)) = config.agent.and_then(|agent| agent.next_move(&s)) {
+                            best.store(encode(m, 0), Ordering::SeqCst);
+                        }
+                        return;
+                    }
+                };
+                for depth in 1..=MAX_TIMED_DEPTH {
+                    if stop.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    match search(&s, depth) {
+                        Some((m, _)) => best.store(encode(m, depth), Ordering::SeqCst),
+                        // no legal moves
+                        None => return,
+                    }
+                }
+            })
+        };
+        Self {
+            best,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// The move chosen by the deepest search completed so far, if any.
+    pub fn best_so_far(&self) -> Option<Move> {
+        decode(self.best.load(Ordering::SeqCst)).map(|(m, _)| m)
+    }
+
+    /// The deepest search completed so far (0 if none has finished).
+    pub fn depth(&self) -> u32 {
+        decode(self.best.load(Ordering::SeqCst)).map_or(0, |(_, depth)| depth)
+    }
+
+    /// Stop searching, wait for the thread to exit, and return the best move
+    /// found.
+    pub fn stop(mut self) -> Option<Move> {
+        self.join();
+        self.best_so_far()
+    }
+
+    fn join(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            // a panic in the search has already been reported, and there's no
+            // move to recover from it
+            _ = thread.join();
+        }
+    }
+}
+
+impl Drop for BackgroundSearch {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::game::Game;
+    use crate::Depth;
+
+    #[test]
+    fn encoding() {
+        assert_eq!(None, decode(0));
+        for m in Move::ALL {
+            for depth in [0, 1, 6] {
+                assert_eq!(Some((m, depth)), decode(encode(m, depth)));
+            }
+        }
+    }
+
+    #[test]
+    fn search_deepens() {
+        let s = *Game::from_rng(StdRng::seed_from_u64(1)).state();
+        let config = Config {
+            algorithm: Algorithm::ExpectimaxWeight(Depth::Smart),
+            ..Config::default()
+        };
+        let search = BackgroundSearch::start(s, &config);
+        let legal: Vec<Move> = s.legal_moves().iter().map(|&(m, _)| m).collect();
+        if let Some(m) = search.best_so_far() {
+            assert!(legal.contains(&m));
+        }
+        let start = Instant::now();
+        while search.depth() < 2 {
+            assert!(start.elapsed() < Duration::from_secs(10), "search is stuck");
+            thread::sleep(Duration::from_millis(1));
+        }
+        let best = search.best.clone();
+        let m = search.stop().expect("no move found");
+        assert!(legal.contains(&m));
+        // the thread has exited and dropped its handle
+        assert_eq!(1, Arc::strong_count(&best));
+    }
+
+    #[test]
+    fn search_with_agent() {
+        let s = *Game::from_rng(StdRng::seed_from_u64(1)).state();
+        let config = Config {
+            algorithm: Algorithm::Agent,
+            agent: Some(Arc::new(Algorithm::ExpectimaxWeight(Depth::Fixed(1)))),
+            ..Config::default()
+        };
+        let search = BackgroundSearch::start(s, &config);
+        let start = Instant::now();
+        while search.best_so_far().is_none() {
+            assert!(start.elapsed() < Duration::from_secs(10), "search is stuck");
+            thread::sleep(Duration::from_millis(1));
+        }
+        let expected = expectimax_weight_move(&s, 1).map(|(m, _)| m);
+        assert_eq!(expected, search.stop());
+    }
+}
@@ -0,0 +1,206 @@
+//! Play many games in one process and summarize how they went.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use rand::Rng;
+
+use crate::{report_games, tile_bar_chart, Config};
+
+/// Totals over a batch of games.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    wins: usize,
+    highest_tiles: Vec<u32>,
+    moves: Vec<u32>,
+}
+
+impl BatchStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the result of one more game.
+    pub fn record(&mut self, won: bool, highest_tile: u32, moves: u32) {
+        if won {
+            self.wins += 1;
+        }
+        self.highest_tiles.push(highest_tile);
+        self.moves.push(moves);
+    }
+
+    pub fn games(&self) -> usize {
+        self.highest_tiles.len()
+    }
+
+    pub fn wins(&self) -> usize {
+        self.wins
+    }
+
+    /// The fraction of games won (0 if none have been played).
+    pub fn win_rate(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        self.wins as f64 / self.games() as f64
+    }
+
+    pub fn mean_highest_tile(&self) -> f64 {
+        mean(&self.highest_tiles)
+    }
+
+    /// The median highest tile, taking the lower of the middle two for an even
+    /// number of games so that it's always a tile that was reached.
+    pub fn median_highest_tile(&self) -> u32 {
+        let mut tiles = self.highest_tiles.clone();
+        tiles.sort_unstable();
+        match tiles.len() {
+            0 => 0,
+            n => tiles[(n - 1) / 2],
+        }
+    }
+
+    pub fn max_highest_tile(&self) -> u32 {
+        self.highest_tiles.iter().copied().max().unwrap_or(0)
+    }
+
+    pub fn mean_moves(&self) -> f64 {
+        mean(&self.moves)
+    }
+
+    /// The number of games that ended with each highest tile.
+    pub fn tile_histogram(&self) -> BTreeMap<u32, usize> {
+        let mut histogram = BTreeMap::new();
+        for &tile in &self.highest_tiles {
+            *histogram.entry(tile).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// Draw the [`BatchStats::tile_histogram`] as a bar chart, one line per
+    /// tile, with the longest bar `width` characters long.
+    pub fn render_histogram(&self, width: usize) -> String {
+        let histogram = self.tile_histogram();
+        tile_bar_chart(
+            histogram.into_iter().map(|(tile, n)| (tile, n as u64)),
+            width as u64,
+        )
+    }
+
+    /// Format the statistics as a few lines of text.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        _ = writeln!(out, "games: {}", self.games());
+        _ = writeln!(out, "won: {} ({:0.1}%)", self.wins, self.win_rate() * 100.0);
+        _ = writeln!(
+            out,
+            "highest tile: mean {:0.0}, median {}, max {}",
+            self.mean_highest_tile(),
+            self.median_highest_tile(),
+            self.max_highest_tile()
+        );
+        _ = writeln!(out, "mean moves: {:0.1}", self.mean_moves());
+        out
+    }
+}
+
+fn mean(xs: &[u32]) -> f64 {
+    if xs.is_empty() {
+        return 0.0;
+    }
+    xs.iter().map(|&x| x as f64).sum::<f64>() / xs.len() as f64
+}
+
+impl Config {
+    /// Play `games` games without printing anything, spread over all available
+    /// cores, and summarize the results.
+    ///
+    /// Games are seeded like [`Config::run_batch_csv`], so the statistics
+    /// don't depend on how the games were split between threads.
+    pub fn run_batch(&self, games: usize) -> BatchStats {
+        let first_seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let results = Mutex::new(vec![None; games]);
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        thread::scope(|scope| {
+            for _ in 0..threads.min(games) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= games {
+                        break;
+                    }
+                    let seed = first_seed.wrapping_add(i as u64);
+                    let result = self.play(Self::new_game(Some(seed)), false, |_, _, _| {});
+                    results.lock().unwrap()[i] =
+                        Some((result.won, result.highest_tile, result.moves));
+                    let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    if self.progress {
+                        report_games(done, games);
+                    }
+                });
+            }
+        });
+        let mut stats = BatchStats::new();
+        for (won, highest_tile, moves) in results.into_inner().unwrap().into_iter().flatten() {
+            stats.record(won, highest_tile, moves);
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BatchStats;
+    use crate::{Algorithm, Config};
+
+    #[test]
+    fn stats() {
+        let mut stats = BatchStats::new();
+        assert_eq!(0, stats.median_highest_tile());
+        for (won, tile, moves) in [(false, 512, 300), (true, 2048, 1000), (false, 1024, 500)] {
+            stats.record(won, tile, moves);
+        }
+        assert_eq!(3, stats.games());
+        assert_eq!(1.0 / 3.0, stats.win_rate());
+        assert_eq!(1024, stats.median_highest_tile());
+        assert_eq!(2048, stats.max_highest_tile());
+        assert_eq!(600.0, stats.mean_moves());
+        stats.record(false, 256, 200);
+        assert_eq!(512, stats.median_highest_tile());
+    }
+
+    #[test]
+    fn histogram() {
+        let mut stats = BatchStats::new();
+        assert!(stats.tile_histogram().is_empty());
+        for tile in [1024, 2048, 512, 1024, 4096, 1024, 2048] {
+            stats.record(tile >= 2048, tile, 0);
+        }
+        let histogram: Vec<_> = stats.tile_histogram().into_iter().collect();
+        assert_eq!(vec![(512, 1), (1024, 3), (2048, 2), (4096, 1)], histogram);
+        assert_eq!(
+            "   512 | ## 1\n  1024 | ###### 3\n  2048 | #### 2\n  4096 | ## 1\n",
+            stats.render_histogram(6)
+        );
+    }
+
+    #[test]
+    fn random_batch() {
+        let config = |seed| Config {
+            algorithm: Algorithm::Random,
+            target_score: Some(64),
+            seed: Some(seed),
+            ..Config::default()
+        };
+        let stats = config(1).run_batch(6);
+        assert_eq!(6, stats.games());
+        // the same seeds as the batch, one game at a time
+        let wins = (1..7).filter(|&i| config(i).run_headless().won).count();
+        assert_eq!(wins, stats.wins());
+        assert!(stats.max_highest_tile() <= 64);
+        assert_eq!(stats, config(1).run_batch(6));
+    }
+}
@@ -0,0 +1,154 @@
+//! Play games back to back and keep a live summary of how the AI is doing.
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+
+use crate::{tile_bar_chart, Config};
+
+/// Running totals over every game played, plus a win rate over only the most
+/// recent ones.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RollingStats {
+    window: usize,
+    recent: VecDeque<bool>,
+    recent_wins: usize,
+    games: u64,
+    wins: u64,
+    highest_tiles: BTreeMap<u32, u64>,
+}
+
+impl RollingStats {
+    /// Track the win rate over the last `window` games.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "the rolling window must hold at least one game");
+        Self {
+            window,
+            recent: VecDeque::with_capacity(window),
+            recent_wins: 0,
+            games: 0,
+            wins: 0,
+            highest_tiles: BTreeMap::new(),
+        }
+    }
+
+    /// Add the result of one more game.
+    pub fn record(&mut self, won: bool, highest_tile: u32) {
+        if self.recent.len() == self.window && self.recent.pop_front() == Some(true) {
+            self.recent_wins -= 1;
+        }
+        self.recent.push_back(won);
+        self.games += 1;
+        if won {
+            self.recent_wins += 1;
+            self.wins += 1;
+        }
+        *self.highest_tiles.entry(highest_tile).or_default() += 1;
+    }
+
+    pub fn games(&self) -> u64 {
+        self.games
+    }
+
+    /// The fraction of all games won (0 if none have been played).
+    pub fn win_rate(&self) -> f64 {
+        if self.games == 0 {
+            return 0.0;
+        }
+        self.wins as f64 / self.games as f64
+    }
+
+    /// The fraction of the last (up to) `window` games won.
+    pub fn rolling_win_rate(&self) -> f64 {
+        if self.recent.is_empty() {
+            return 0.0;
+        }
+        self.recent_wins as f64 / self.recent.len() as f64
+    }
+
+    /// How many games ended with each highest tile, from smallest to largest.
+    pub fn highest_tiles(&self) -> impl Iterator<Item = (u32, u64)> + '_ {
+        self.highest_tiles.iter().map(|(&tile, &n)| (tile, n))
+    }
+
+    /// Format the statistics as a few lines of text, with a bar for each
+    /// highest tile.
+    pub fn render(&self) -> String {
+        const BAR_WIDTH: u64 = 40;
+        let mut out = String::new();
+        _ = writeln!(out, "games: {}", self.games);
+        _ = writeln!(
+            out,
+            "win rate: {:0.1}% overall, {:0.1}% over the last {}",
+            self.win_rate() * 100.0,
+            self.rolling_win_rate() * 100.0,
+            self.recent.len()
+        );
+        out += &tile_bar_chart(self.highest_tiles(), BAR_WIDTH);
+        out
+    }
+}
+
+impl Config {
+    /// Play games forever (until the process is interrupted), redrawing a
+    /// summary of the results after each one, with the rolling win rate taken
+    /// over the last `window` games.
+    ///
+    /// Games are seeded like [`Config::run_batch_csv`].
+    pub fn run_dashboard(&self, window: usize) -> ! {
+        let mut stats = RollingStats::new(window);
+        let first_seed = self.seed.unwrap_or_else(rand::random);
+        loop {
+            let seed = first_seed.wrapping_add(stats.games());
+            let result = self.play(Self::new_game(Some(seed)), false, |_, _, _| {});
+            stats.record(result.won, result.highest_tile);
+            _ = clearscreen::clear();
+            print!("{}", stats.render());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RollingStats;
+
+    #[test]
+    fn rolling_win_rate() {
+        let mut stats = RollingStats::new(3);
+        assert_eq!(0.0, stats.rolling_win_rate());
+        let mut rates = Vec::new();
+        for (won, tile) in [
+            (true, 2048),
+            (false, 1024),
+            (true, 2048),
+            (true, 4096),
+            (false, 512),
+            (false, 1024),
+        ] {
+            stats.record(won, tile);
+            rates.push(stats.rolling_win_rate());
+        }
+        assert_eq!(
+            vec![1.0, 0.5, 2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 1.0 / 3.0],
+            rates
+        );
+        assert_eq!(6, stats.games());
+        assert_eq!(0.5, stats.win_rate());
+        assert_eq!(
+            vec![(512, 1), (1024, 2), (2048, 2), (4096, 1)],
+            stats.highest_tiles().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn render() {
+        let mut stats = RollingStats::new(10);
+        stats.record(true, 2048);
+        stats.record(true, 2048);
+        stats.record(false, 1024);
+        let text = stats.render();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("games: 3", lines[0]);
+        assert_eq!(4, lines.len(), "{text}");
+        assert!(lines[3].starts_with("  2048 | ########"), "{text}");
+        assert!(lines[3].ends_with(" 2"), "{text}");
+    }
+}
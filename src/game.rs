@@ -3,22 +3,45 @@
 //! The representation of rows is the most performance-critical part and is
 //! delegated to the (private) row crate.
 
+mod grid;
+mod packed;
+mod puzzle;
 mod row;
+#[cfg(feature = "serde")]
+mod save;
+#[cfg(feature = "serde")]
+mod web;
 
-use std::fmt;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::fmt;
+use core::str::FromStr;
 
+#[cfg(feature = "std")]
 use rand::prelude::ThreadRng;
 use rand::seq::SliceRandom;
-use rand::Rng;
+use rand::{Rng, RngCore, SeedableRng};
 
+pub use grid::Grid;
+pub use packed::{Board, PackedState};
 pub use row::{CachedRow, Row};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct State([CachedRow; 4]);
 
+/// The linear index on an `n`x`n` board of the cell that rotating the board
+/// right moves to index `i`: cell (r, c) comes from (n-1-c, r).
+const fn right_rotate_index(n: usize, i: usize) -> usize {
+    let (r, c) = (i / n, i % n);
+    (n - 1 - c) * n + r
+}
+
 assert_eq_size!(u64, State);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Move {
     Left,
     Right,
@@ -28,6 +51,83 @@ pub enum Move {
 
 impl Move {
     pub const ALL: [Move; 4] = [Move::Left, Move::Right, Move::Up, Move::Down];
+
+    /// The lowercase name of the move, as written by its `Display` impl.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Move::Left => "left",
+            Move::Right => "right",
+            Move::Up => "up",
+            Move::Down => "down",
+        }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The error from parsing a [`Move`] from a string that doesn't name one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownMove(pub String);
+
+impl fmt::Display for UnknownMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown move {:?} (expected left, right, up, down or their first letters)",
+            self.0
+        )
+    }
+}
+
+impl core::error::Error for UnknownMove {}
+
+impl FromStr for Move {
+    type Err = UnknownMove;
+
+    /// Parse a move from its name or first letter, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        Move::ALL
+            .into_iter()
+            .find(|m| lower == m.name() || lower == m.name()[..1])
+            .ok_or_else(|| UnknownMove(s.to_string()))
+    }
+}
+
+/// A horizontal direction, for shifting a single row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HDir {
+    Left,
+    Right,
+}
+
+/// A vertical direction, for shifting a single column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VDir {
+    Up,
+    Down,
+}
+
+/// A corner of the board, for strategies that build up tiles in one corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    pub const ALL: [Corner; 4] = [
+        Corner::TopLeft,
+        Corner::TopRight,
+        Corner::BottomLeft,
+        Corner::BottomRight,
+    ];
 }
 
 impl fmt::Display for State {
@@ -39,9 +139,118 @@ impl fmt::Display for State {
     }
 }
 
+/// Boards are serialized as the exponents of their cells in linear order (see
+/// [`State::to_tiles`]).
+#[cfg(feature = "serde")]
+impl serde::Serialize for State {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_tiles().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for State {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let tiles = <[u8; 16]>::deserialize(deserializer)?;
+        State::from_exponents(&tiles).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The largest exponent a cell can hold, for a 32768 tile.
+pub const MAX_EXPONENT: u8 = 15;
+
+/// Errors from building a board out of invalid input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The cell at `index` (in linear order) has an exponent larger than
+    /// [`MAX_EXPONENT`].
+    ExponentOutOfRange { index: usize, exponent: u8 },
+    /// A saved board (see [`State::from_web_json`]) isn't valid JSON, failing
+    /// at this line and column (both counting from 1).
+    InvalidJson { line: usize, column: usize },
+    /// A saved board is valid JSON, but isn't shaped like a board.
+    NotABoard,
+    /// The cell at `index` holds a value that isn't a tile: a power of two
+    /// from 2 up to 2 to the [`MAX_EXPONENT`]. The value is stored as the bits
+    /// of an `f64` (see [`f64::from_bits`]), so that errors can be compared.
+    InvalidTile { index: usize, value_bits: u64 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ExponentOutOfRange { index, exponent } => write!(
+                f,
+                "cell {index} has exponent {exponent}, but the maximum is {MAX_EXPONENT}"
+            ),
+            Error::InvalidJson { line, column } => {
+                write!(f, "invalid JSON at line {line}, column {column}")
+            }
+            Error::NotABoard => write!(
+                f,
+                "expected a 4x4 grid, an array of 16 tiles or a saved game"
+            ),
+            Error::InvalidTile { index, value_bits } => {
+                let value = f64::from_bits(*value_bits);
+                write!(f, "cell {index} holds {value}, which is not a valid tile")
+            }
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
 pub const FOUR_SPAWN_PROB: f64 = 0.1;
 pub const TWO_SPAWN_PROB: f64 = 1.0 - FOUR_SPAWN_PROB;
 
+/// How the value of each new tile is chosen.
+///
+/// The AI always plans for [`Spawn::Standard`], regardless of the game's
+/// actual spawns.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Spawn {
+    /// As in the original game: a 2 with probability [`TWO_SPAWN_PROB`] and
+    /// otherwise a 4.
+    #[default]
+    Standard,
+    /// Once the board has a tile of at least `threshold`, spawn an 8 with
+    /// probability `eight_prob` (and otherwise spawn as usual).
+    Progressive { threshold: u32, eight_prob: f64 },
+}
+
+impl Spawn {
+    /// Choose the exponent of a new tile to add to `s`.
+    fn exponent<Rn: Rng>(&self, s: &State, rng: &mut Rn) -> u8 {
+        if let Spawn::Progressive {
+            threshold,
+            eight_prob,
+        } = *self
+        {
+            if s.highest_tile() >= threshold && rng.gen_bool(eight_prob) {
+                return 3;
+            }
+        }
+        if rng.gen_bool(TWO_SPAWN_PROB) {
+            1 // numbers are encoded by their power of 2
+        } else {
+            2
+        }
+    }
+}
+
+/// Iterate over the bits set in `mask`, from lowest to highest.
+pub fn mask_cells(mut mask: u16) -> impl Iterator<Item = u8> {
+    core::iter::from_fn(move || {
+        if mask == 0 {
+            return None;
+        }
+        let i = mask.trailing_zeros() as u8;
+        // clear the lowest set bit
+        mask &= mask - 1;
+        Some(i)
+    })
+}
+
 impl State {
     #[cfg(test)]
     fn new(els: [[u8; 4]; 4]) -> Self {
@@ -60,6 +269,48 @@ impl State {
         self.0[i / 4].get(i % 4)
     }
 
+    /// Build a board from the exponent of every cell in linear order, with 0
+    /// for empty cells (the inverse of [`State::exponent_grid`]).
+    pub fn from_exponents(exps: &[u8; 16]) -> Result<Self, Error> {
+        let mut s = Self::default();
+        for (index, &exponent) in exps.iter().enumerate() {
+            if exponent > MAX_EXPONENT {
+                return Err(Error::ExponentOutOfRange { index, exponent });
+            }
+            s.add(index, exponent);
+        }
+        Ok(s)
+    }
+
+    /// Build a board from the exponent of every cell in linear order, with 0
+    /// for empty cells.
+    ///
+    /// Panics if an exponent is larger than [`MAX_EXPONENT`]; see
+    /// [`State::from_exponents`] to check for that instead.
+    pub fn from_tiles(tiles: [u8; 16]) -> Self {
+        Self::from_exponents(&tiles).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// The inverse of [`State::from_tiles`] (the same as
+    /// [`State::exponent_grid`]).
+    pub fn to_tiles(&self) -> [u8; 16] {
+        self.exponent_grid()
+    }
+
+    /// Replace the exponent in cell `i` (in linear order) with `x`, which can
+    /// be 0 to clear it.
+    ///
+    /// Panics if `i` is out of bounds or `x` is larger than [`MAX_EXPONENT`].
+    pub fn set(&mut self, i: usize, x: u8) {
+        let mut tiles = self.to_tiles();
+        assert!(
+            i < 16,
+            "cell index {i} is out of bounds (boards have 16 cells)"
+        );
+        tiles[i] = x;
+        *self = Self::from_tiles(tiles);
+    }
+
     /// Get a tile's value by linear index.
     ///
     /// This will be the power-of-two seen in the game.
@@ -68,31 +319,204 @@ impl State {
         2u32.pow(self.get(i).into())
     }
 
+    /// Get the exponent of every cell in linear order, with 0 for empty cells.
+    ///
+    /// This is cheaper than calling [`State::tile`] on every cell when the
+    /// power-of-two values aren't needed.
+    pub fn exponent_grid(&self) -> [u8; 16] {
+        let mut grid = [0; 16];
+        for (i, x) in grid.iter_mut().enumerate() {
+            *x = self.get(i);
+        }
+        grid
+    }
+
+    /// Measure how much tile values increase toward `corner`.
+    ///
+    /// This sums the difference between every pair of adjacent cells, signed
+    /// so that it is positive when the cell nearer to the corner holds the
+    /// larger tile (empty cells count as 0). A board sorted toward the corner
+    /// has positive pressure and one sorted away from it negative pressure.
+    pub fn corner_pressure(&self, corner: Corner) -> f64 {
+        let grid = self.exponent_grid();
+        let value = |i: usize| {
+            if grid[i] == 0 {
+                0.0
+            } else {
+                (1u32 << grid[i]) as f64
+            }
+        };
+        // the signs of a difference between a cell and the next one to the
+        // right or below it
+        let (row_sign, col_sign) = match corner {
+            Corner::TopLeft => (1.0, 1.0),
+            Corner::TopRight => (-1.0, 1.0),
+            Corner::BottomLeft => (1.0, -1.0),
+            Corner::BottomRight => (-1.0, -1.0),
+        };
+        let mut pressure = 0.0;
+        for r in 0..4 {
+            for c in 0..4 {
+                let i = r * 4 + c;
+                if c < 3 {
+                    pressure += row_sign * (value(i) - value(i + 1));
+                }
+                if r < 3 {
+                    pressure += col_sign * (value(i) - value(i + 4));
+                }
+            }
+        }
+        pressure
+    }
+
+    /// The fraction of the board's total tile value held by the largest tile,
+    /// from near 0 (value spread over many tiles) to 1 (a single tile). An
+    /// empty board has concentration 0.
+    pub fn value_concentration(&self) -> f64 {
+        let values = self.tile_values();
+        let total: f64 = values.iter().sum();
+        if total == 0.0 {
+            return 0.0;
+        }
+        values.iter().copied().fold(0.0, f64::max) / total
+    }
+
+    /// The entropy (in bits) of how the board's total value is split between
+    /// tiles: 0 for a single tile and `log2(n)` for `n` equal tiles.
+    #[cfg(feature = "std")]
+    pub fn tile_entropy(&self) -> f64 {
+        let values = self.tile_values();
+        let total: f64 = values.iter().sum();
+        values
+            .iter()
+            .filter(|&&x| x > 0.0)
+            .map(|&x| {
+                let p = x / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    // tile values with 0 for empty cells
+    fn tile_values(&self) -> [f64; 16] {
+        self.exponent_grid()
+            .map(|x| if x == 0 { 0.0 } else { (1u32 << x) as f64 })
+    }
+
+    /// Check whether every cell of this board has at least the exponent of the
+    /// same cell in `other`.
+    ///
+    /// This is a partial order: two boards can each have a larger tile than
+    /// the other somewhere, in which case neither dominates.
+    pub fn dominates(&self, other: &State) -> bool {
+        let (a, b) = (self.exponent_grid(), other.exponent_grid());
+        a.iter().zip(b.iter()).all(|(x, y)| x >= y)
+    }
+
+    /// Work out how `next` followed from this board: returns a legal move and
+    /// the linear index and exponent of the tile added after it, or `None` if
+    /// `next` isn't one move plus one new 2 or 4 away.
+    ///
+    /// If several moves could explain `next`, the first in [`Move::ALL`] is
+    /// returned.
+    pub fn diff(&self, next: &State) -> Option<(Move, usize, u8)> {
+        let after = next.exponent_grid();
+        self.legal_moves().into_iter().find_map(|(m, s)| {
+            let before = s.exponent_grid();
+            let changed: Vec<usize> = (0..16).filter(|&i| before[i] != after[i]).collect();
+            match changed[..] {
+                [i] if before[i] == 0 && (after[i] == 1 || after[i] == 2) => Some((m, i, after[i])),
+                _ => None,
+            }
+        })
+    }
+
+    /// Count the cells where this board and `other` differ.
+    pub fn cell_distance(&self, other: &State) -> u32 {
+        let (a, b) = (self.exponent_grid(), other.exponent_grid());
+        a.iter().zip(b.iter()).filter(|(x, y)| x != y).count() as u32
+    }
+
+    /// Sum the differences in tile value between this board and `other`, cell
+    /// by cell, where an empty cell counts as 0.
+    pub fn value_distance(&self, other: &State) -> u32 {
+        let value = |x: u8| if x == 0 { 0 } else { 1u32 << x };
+        let (a, b) = (self.exponent_grid(), other.exponent_grid());
+        a.iter()
+            .zip(b.iter())
+            .map(|(&x, &y)| value(x).abs_diff(value(y)))
+            .sum()
+    }
+
     /// Add a tile by linear index.
     ///
-    /// Should only be used when the tile is empty.
+    /// Panics if `i` is out of bounds, `x` is larger than [`MAX_EXPONENT`], or
+    /// the cell isn't empty.
     pub fn add(&mut self, i: usize, x: u8) {
+        assert!(
+            i < 16,
+            "cell index {i} is out of bounds (boards have 16 cells)"
+        );
         self.0[i / 4].add(i % 4, x);
     }
 
-    const RIGHT_ROTATE_IDX: [usize; 16] = [12, 8, 4, 0, 13, 9, 5, 1, 14, 10, 6, 2, 15, 11, 7, 3];
+    /// [`State::add`] without the checks, for the search's hot path. The cell
+    /// must be empty and `x` at most [`MAX_EXPONENT`].
+    pub(crate) fn add_unchecked(&mut self, i: usize, x: u8) {
+        self.0[i / 4].add_unchecked(i % 4, x);
+    }
+
+    const RIGHT_ROTATE_IDX: [usize; 16] = {
+        let mut idx = [0; 16];
+        let mut i = 0;
+        while i < 16 {
+            idx[i] = right_rotate_index(4, i);
+            i += 1;
+        }
+        idx
+    };
 
     /// rotate right
     ///
     /// internally used to implement up/down movement using only left/right
     fn rotate_right(&self) -> Self {
+        Self::from_u64(packed::mirror_bits(packed::transpose_bits(self.pack())))
+    }
+
+    /// rotate left
+    ///
+    /// internally used to implement up/down movement using only left/right
+    fn rotate_left(&self) -> Self {
+        Self::from_u64(packed::transpose_bits(packed::mirror_bits(self.pack())))
+    }
+
+    /// Swap rows and columns, so column `i` becomes row `i`.
+    pub fn transpose(&self) -> Self {
+        Self::from_u64(packed::transpose_bits(self.pack()))
+    }
+
+    /// Rotate the board clockwise by `quarter_turns` quarter turns.
+    pub fn rotate(&self, quarter_turns: u8) -> Self {
+        match quarter_turns % 4 {
+            0 => *self,
+            1 => self.rotate_right(),
+            2 => self.mirror_horizontal().mirror_vertical(),
+            _ => self.rotate_left(),
+        }
+    }
+
+    // the index-based rotations the bit manipulation above replaced
+    #[cfg(test)]
+    fn rotate_right_by_index(&self) -> Self {
         let mut new = Self::default();
-        // right rotation indices, computed by hand
         for (i, &idx) in Self::RIGHT_ROTATE_IDX.iter().enumerate() {
             new.add(i, self.get(idx));
         }
         new
     }
 
-    /// rotate left
-    ///
-    /// internally used to implement up/down movement using only left/right
-    fn rotate_left(&self) -> Self {
+    #[cfg(test)]
+    fn rotate_left_by_index(&self) -> Self {
         let mut new = Self::default();
         for (i, &idx) in Self::RIGHT_ROTATE_IDX.iter().enumerate() {
             new.add(idx, self.get(i));
@@ -100,6 +524,31 @@ impl State {
         new
     }
 
+    /// Reflect the board left to right, reversing every row.
+    pub fn mirror_horizontal(&self) -> Self {
+        Self::from_u64(packed::mirror_bits(self.pack()))
+    }
+
+    // reflect top to bottom, reversing every column
+    fn mirror_vertical(&self) -> Self {
+        let [r0, r1, r2, r3] = self.0;
+        Self([r3, r2, r1, r0])
+    }
+
+    /// A key for the board up to rotation and reflection: the smallest
+    /// [`State::pack`] of any of its eight symmetries.
+    ///
+    /// Boards related by a symmetry have the same key, so anything cached for
+    /// one (with a score that treats every symmetry alike) applies to all of
+    /// them.
+    pub fn canonical_key(&self) -> u64 {
+        (0..4)
+            .map(|turns| self.rotate(turns))
+            .flat_map(|s| [s.pack(), s.mirror_horizontal().pack()])
+            .min()
+            .unwrap()
+    }
+
     fn move_left(&self) -> Self {
         let [r0, r1, r2, r3] = self.0;
         Self([
@@ -124,9 +573,153 @@ impl State {
         match m {
             Move::Left => self.move_left(),
             Move::Right => self.move_right(),
-            Move::Up => self.rotate_left().move_left().rotate_right(),
-            Move::Down => self.rotate_right().move_left().rotate_left(),
+            // a column read top to bottom is a row of the transpose
+            Move::Up => self.transpose().move_left().transpose(),
+            Move::Down => self.transpose().move_right().transpose(),
+        }
+    }
+
+    /// Shift only row `row` (0 is the top) as a move in direction `dir` would,
+    /// leaving the other rows untouched.
+    pub fn shift_row(&self, row: usize, dir: HDir) -> Self {
+        let mut new = *self;
+        new.0[row] = match dir {
+            HDir::Left => self.0[row].shift_left(),
+            HDir::Right => self.0[row].shift_right(),
+        };
+        new
+    }
+
+    /// Shift only column `col` (0 is the leftmost) as a move in direction `dir`
+    /// would, leaving the other columns untouched.
+    pub fn shift_column(&self, col: usize, dir: VDir) -> Self {
+        assert!(
+            col < 4,
+            "column {col} is out of bounds (boards have 4 columns)"
+        );
+        // rotating left turns column c into row 3 - c, and rotating right
+        // turns it into row c
+        match dir {
+            VDir::Up => self
+                .rotate_left()
+                .shift_row(3 - col, HDir::Left)
+                .rotate_right(),
+            VDir::Down => self.rotate_right().shift_row(col, HDir::Left).rotate_left(),
+        }
+    }
+
+    /// The board after move `m` (before a tile is added), or `None` if the move
+    /// doesn't change the board and so isn't legal.
+    ///
+    /// Adding the random tile is left to [`State::rand_add`] (or any other
+    /// chance model), so this is the whole deterministic part of a turn.
+    pub fn apply_move(&self, m: Move) -> Option<Self> {
+        let next = self.make_move(m);
+        (next != *self).then_some(next)
+    }
+
+    /// Preview the result of a move, along with which cells hold a tile created
+    /// by a merge (for example, to animate them).
+    ///
+    /// Returns `None` if the move is not legal.
+    pub fn preview_move(&self, m: Move) -> Option<(Self, [bool; 16])> {
+        let mut next = Self::default();
+        let mut merged = [false; 16];
+        match m {
+            Move::Left | Move::Right => {
+                for (i, row) in self.0.iter().enumerate() {
+                    let (r, row_merged) = if m == Move::Left {
+                        row.shift_left_merged()
+                    } else {
+                        row.shift_right_merged()
+                    };
+                    next.0[i] = r;
+                    merged[i * 4..i * 4 + 4].copy_from_slice(&row_merged);
+                }
+            }
+            Move::Up => {
+                let (s, rotated) = self.rotate_left().preview_move(Move::Left)?;
+                next = s.rotate_right();
+                for (i, &idx) in Self::RIGHT_ROTATE_IDX.iter().enumerate() {
+                    merged[i] = rotated[idx];
+                }
+            }
+            Move::Down => {
+                let (s, rotated) = self.rotate_right().preview_move(Move::Left)?;
+                next = s.rotate_left();
+                for (i, &idx) in Self::RIGHT_ROTATE_IDX.iter().enumerate() {
+                    merged[idx] = rotated[i];
+                }
+            }
+        }
+        if next == *self {
+            return None;
+        }
+        Some((next, merged))
+    }
+
+    /// Points scored by making move `m`: as in the original game, every merge
+    /// scores the value of the tile it creates.
+    pub fn merge_score(&self, m: Move) -> u32 {
+        match m {
+            Move::Left => self.0.iter().map(|r| r.shift_left_score()).sum(),
+            Move::Right => self.0.iter().map(|r| r.shift_right_score()).sum(),
+            Move::Up => self.rotate_left().merge_score(Move::Left),
+            Move::Down => self.rotate_right().merge_score(Move::Left),
+        }
+    }
+
+    /// Count the merges made by move `m` (0 if it isn't legal).
+    ///
+    /// Every merge removes one tile from the board, so this is just how many
+    /// fewer tiles there are after the move.
+    pub fn merges_for_move(&self, m: Move) -> u32 {
+        let tiles = |s: &State| s.exponent_grid().iter().filter(|&&x| x > 0).count() as u32;
+        tiles(self) - tiles(&self.make_move(m))
+    }
+
+    /// Check whether no legal move merges anything, so every move only slides
+    /// tiles around. This is also true if there are no legal moves.
+    pub fn only_fillers(&self) -> bool {
+        self.legal_moves()
+            .iter()
+            .all(|&(m, _)| self.merges_for_move(m) == 0)
+    }
+
+    /// Check whether move `m` changes the board.
+    pub fn is_legal(&self, m: Move) -> bool {
+        self.make_move(m) != *self
+    }
+
+    /// Count the legal moves, without collecting them as
+    /// [`State::legal_moves`] does.
+    pub fn num_legal_moves(&self) -> u32 {
+        Move::ALL.iter().filter(|&&m| self.is_legal(m)).count() as u32
+    }
+
+    /// Check whether the game is over: no move changes the board.
+    pub fn game_over(&self) -> bool {
+        !Move::ALL.iter().any(|&m| self.is_legal(m))
+    }
+
+    /// Check whether a tile of at least `target` could still appear in a game
+    /// continuing from this board.
+    ///
+    /// This is `false` only when it's certain: the game is over without the
+    /// target, or the target is larger than any tile a cell can hold (2 to the
+    /// [`MAX_EXPONENT`]). The sum of the tiles is no bound, since every move
+    /// adds another tile to it.
+    pub fn can_possibly_reach(&self, target: u32) -> bool {
+        if self.highest_tile() >= target {
+            return true;
         }
+        target <= 1 << MAX_EXPONENT && !self.game_over()
+    }
+
+    /// Check whether every cell holds a tile. A full board isn't necessarily
+    /// the end of the game, since tiles might still merge.
+    pub fn is_full(&self) -> bool {
+        self.empty_mask() == 0
     }
 
     /// Generate legal moves and immediate next states.
@@ -146,24 +739,86 @@ impl State {
             .collect()
     }
 
+    /// Pick the legal move whose resulting board (before a tile is added)
+    /// maximizes `f`, looking only one move ahead.
+    ///
+    /// Ties go to the move that comes first in [`Move::ALL`].
+    pub fn best_move_by<F: Fn(&State) -> f64>(&self, f: F) -> Option<(Move, State)> {
+        let mut best: Option<(Move, State, f64)> = None;
+        for m in Move::ALL {
+            let s = self.make_move(m);
+            if s == *self {
+                continue;
+            }
+            let score = f(&s);
+            if best.is_none_or(|(_, _, best_score)| score > best_score) {
+                best = Some((m, s, score));
+            }
+        }
+        best.map(|(m, s, _)| (m, s))
+    }
+
+    /// How many ways a random tile can be added to this board: a 2 or a 4 in
+    /// any empty cell. This is the branching factor of a chance node in
+    /// expectimax.
+    pub fn chance_branching(&self) -> u32 {
+        2 * self.empty_count()
+    }
+
+    /// Same as [`State::chance_branching`], but counting boards that are
+    /// rotations or reflections of each other only once.
+    pub fn distinct_chance_outcomes(&self) -> u32 {
+        let mut outcomes: Vec<u64> = Vec::new();
+        for i in self.empty() {
+            for x in [1, 2] {
+                let mut next = *self;
+                next.add_unchecked(i as usize, x);
+                outcomes.push(next.canonical_key());
+            }
+        }
+        outcomes.sort_unstable();
+        outcomes.dedup();
+        outcomes.len() as u32
+    }
+
+    /// A bitmask of the empty cells: bit `i` is set if the cell with linear
+    /// index `i` is empty.
+    pub fn empty_mask(&self) -> u16 {
+        let x = self.pack();
+        // bit 4i is set if cell i holds a tile
+        let occupied = (x | x >> 1 | x >> 2 | x >> 3) & 0x1111_1111_1111_1111;
+        let mut mask = 0;
+        for i in 0..16 {
+            mask |= ((occupied >> (4 * i)) as u16 & 1) << i;
+        }
+        !mask
+    }
+
+    /// Count the empty cells.
+    pub fn empty_count(&self) -> u32 {
+        self.empty_mask().count_ones()
+    }
+
+    /// Iterate over the linear indices of empty positions in increasing order,
+    /// without allocating.
+    pub fn empty_cells(&self) -> impl Iterator<Item = u8> {
+        mask_cells(self.empty_mask())
+    }
+
     /// Returns the linear indices of empty positions.
     pub fn empty(&self) -> Vec<u8> {
-        let mut indices = Vec::new();
-        self.0.iter().enumerate().for_each(|(i, &row)| {
-            let i = i as u8;
-            indices.extend(row.empty().iter().map(|&j| i * 4 + j));
-        });
-        indices
+        self.empty_cells().collect()
     }
 
     /// Add a random tile to the board.
     pub fn rand_add<Rn: Rng>(&mut self, rng: &mut Rn) -> &mut Self {
+        self.rand_add_spawn(Spawn::Standard, rng)
+    }
+
+    /// Add a random tile to the board, with its value chosen by `spawn`.
+    pub fn rand_add_spawn<Rn: Rng>(&mut self, spawn: Spawn, rng: &mut Rn) -> &mut Self {
         if let Some(&i) = self.empty().choose(rng) {
-            let x = if rng.gen_bool(TWO_SPAWN_PROB) {
-                1 // numbers are encoded by their power of 2
-            } else {
-                2
-            };
+            let x = spawn.exponent(self, rng);
             self.add(i as usize, x);
         } else {
             // no move should leave the board this full
@@ -172,133 +827,1056 @@ impl State {
         self
     }
 
-    /// Return the highest tile, converted to the usual power of two.
+    /// Pack the whole board into a single integer, with four bits per cell.
+    ///
+    /// Distinct boards always pack to distinct integers, so this is suitable as
+    /// a hash key.
+    pub fn pack(&self) -> u64 {
+        self.0
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, row)| acc | (row.to_bits() as u64) << (16 * i))
+    }
+
+    /// Unpack a board packed by [`State::pack`]. Every `u64` is a valid board.
+    pub fn from_u64(packed: u64) -> Self {
+        let mut rows = [CachedRow::default(); 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            *row = CachedRow::from_bits((packed >> (16 * i)) as u16);
+        }
+        State(rows)
+    }
+
+    /// Encode the board as a single token of 16 hex digits (the packed board
+    /// from [`State::pack`]), for naming boards in logs and tests.
+    pub fn to_compact_hex(&self) -> String {
+        format!("{:016x}", self.pack())
+    }
+
+    /// Decode a board written by [`State::to_compact_hex`], returning `None` if
+    /// `s` isn't exactly 16 hex digits.
+    pub fn from_compact_hex(s: &str) -> Option<Self> {
+        if s.len() != 16 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        u64::from_str_radix(s, 16).ok().map(Self::from_u64)
+    }
+
+    /// Return the highest tile, converted to the usual power of two, or 0 for
+    /// an empty board.
     pub fn highest_tile(&self) -> u32 {
         let exp = (0..16).map(|i| self.get(i)).max().unwrap();
+        if exp == 0 {
+            return 0;
+        }
         (2_u32).pow(exp.into())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::game::row::cached_tests::arb_cached_row;
 
-    use super::{Move, State};
-    use proptest::prelude::*;
-
-    prop_compose! {
-        fn arb_state()(r0 in arb_cached_row(),
-                       r1 in arb_cached_row(),
-                       r2 in arb_cached_row(),
-                       r3 in arb_cached_row()) -> State {
-            State([r0, r1, r2, r3])
+    /// A lower bound on how many more moves it takes to make a tile of value
+    /// `target` (0 if there already is one).
+    ///
+    /// Merges never change the total value of the tiles, and each move adds
+    /// at most a 4, so until the total reaches `target` every move before the
+    /// one that creates the tile must add to it. This ignores whether the
+    /// tiles can actually be merged, so the real number of moves is usually
+    /// much higher.
+    pub fn min_moves_to_target(&self, target: u32) -> u32 {
+        if self.highest_tile() >= target {
+            return 0;
         }
+        let total: u32 = self
+            .exponent_grid()
+            .iter()
+            .filter(|&&x| x > 0)
+            .map(|&x| 1 << x)
+            .sum();
+        1 + target.saturating_sub(total).div_ceil(4)
     }
 
-    #[test]
-    fn rotate() {
-        let s = State::new([[0, 1, 2, 3], [4, 0, 0, 0], [8, 0, 0, 0], [12, 0, 0, 0]]);
-        assert_eq!(
-            State::new([[12, 8, 4, 0], [0, 0, 0, 1], [0, 0, 0, 2], [0, 0, 0, 3],]),
-            s.rotate_right()
-        );
-        assert_eq!(
-            State::new([[3, 0, 0, 0], [2, 0, 0, 0], [1, 0, 0, 0], [0, 4, 8, 12],]),
-            s.rotate_left()
-        )
+    /// The linear index of the highest tile, the first in linear order if
+    /// there's a tie (0 for an empty board).
+    pub fn max_tile_position(&self) -> usize {
+        let grid = self.exponent_grid();
+        (0..16).min_by_key(|&i| Reverse(grid[i])).unwrap()
     }
 
-    proptest! {
-        #[test]
-        fn prop_rotate_left3_is_right(s in arb_state()) {
-            assert_eq!(s.rotate_left().rotate_left().rotate_left(), s.rotate_right())
+    /// The linear indices of the largest and second-largest tiles, or `None`
+    /// if there are fewer than two tiles. Ties go to the first in linear order.
+    pub fn top_two_tiles(&self) -> Option<(usize, usize)> {
+        let grid = self.exponent_grid();
+        let mut cells: Vec<usize> = (0..16).filter(|&i| grid[i] > 0).collect();
+        // stable, so equal tiles stay in linear order
+        cells.sort_by_key(|&i| Reverse(grid[i]));
+        match cells[..] {
+            [first, second, ..] => Some((first, second)),
+            _ => None,
         }
     }
 
-    fn index(i: usize, j: usize) -> u8 {
-        (i * 4 + j) as u8
+    /// Check whether the two largest tiles (see [`State::top_two_tiles`]) are
+    /// orthogonally adjacent, so that they can eventually merge. Boards with
+    /// fewer than two tiles count as adjacent.
+    pub fn top_two_adjacent(&self) -> bool {
+        let Some((i, j)) = self.top_two_tiles() else {
+            return true;
+        };
+        let (ri, ci) = (i / 4, i % 4);
+        let (rj, cj) = (j / 4, j % 4);
+        ri.abs_diff(rj) + ci.abs_diff(cj) == 1
     }
+}
 
-    #[test]
-    fn empty() {
-        assert_eq!(
-            // these are linear indices, but compute them here for readability
-            vec![index(0, 1), index(0, 2), index(1, 0), index(3, 3)],
-            State::new([[1, 0, 0, 2], [0, 2, 1, 3], [3, 4, 2, 5], [1, 2, 1, 0],]).empty()
-        )
+/// Wraps an RNG to count the 32-bit words drawn from it.
+///
+/// For an RNG like [`rand::rngs::StdRng`], which hands out words from a single
+/// stream, the seed and this count are the whole state: reseeding and drawing
+/// as many words again brings it back to the same point.
+#[derive(Clone, Debug)]
+pub(crate) struct CountingRng<Rn> {
+    rng: Rn,
+    words: u64,
+}
+
+impl<Rn: RngCore> CountingRng<Rn> {
+    fn new(rng: Rn) -> Self {
+        Self { rng, words: 0 }
     }
 
-    #[test]
-    fn test_moves() {
-        let s = State::new([[0, 0, 0, 0], [0, 1, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
-        assert_eq!(
-            State::new([[0, 0, 0, 0], [1, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0],]),
-            s.make_move(Move::Left),
-        );
-        assert_eq!(
-            State::new([[0, 0, 0, 0], [0, 0, 0, 1], [0, 0, 0, 0], [0, 0, 0, 0],]),
-            s.make_move(Move::Right),
-        );
-        assert_eq!(
-            State::new([[0, 1, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0],]),
-            s.make_move(Move::Up),
-        );
-        assert_eq!(
-            State::new([[0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 1, 0, 0],]),
-            s.make_move(Move::Down),
-        );
+    /// Draw `words` words, to catch up with an RNG that had drawn that many.
+    #[cfg(feature = "serde")]
+    fn skip(&mut self, words: u64) {
+        for _ in 0..words {
+            self.next_u32();
+        }
     }
+}
 
-    #[test]
-    fn printing() {
-        assert_eq!(
-            " 0  1  2  3
- 4  5  6  7
- 8  9 10 11
-12 13 14 15
-",
-            format!(
-                "{}",
-                State::new([[0, 1, 2, 3], [4, 5, 6, 7], [8, 9, 10, 11], [12, 13, 14, 15],])
-            )
-        )
+impl<Rn: RngCore> RngCore for CountingRng<Rn> {
+    fn next_u32(&mut self) -> u32 {
+        self.words += 1;
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.words += 2;
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.words += dest.len().div_ceil(4) as u64;
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.words += dest.len().div_ceil(4) as u64;
+        self.rng.try_fill_bytes(dest)
     }
 }
 
 pub struct Game<Rn: Rng> {
-    rng: Rn,
+    rng: CountingRng<Rn>,
+    // the seed the RNG started from, if it was seeded (see Game::from_seed)
+    seed: Option<u64>,
+    spawn: Spawn,
     s: State,
     moves: u32,
+    score: u64,
 }
 
+#[cfg(feature = "std")]
 impl Game<ThreadRng> {
     pub fn new() -> Self {
         Self::from_rng(ThreadRng::default())
     }
 }
 
+#[cfg(feature = "std")]
+impl Default for Game<ThreadRng> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Rn: Rng + SeedableRng> Game<Rn> {
+    /// Start a game with an RNG seeded from `seed`. Unlike a game made with
+    /// [`Game::from_rng`], this one can be saved.
+    pub fn from_seed(seed: u64) -> Self {
+        let mut game = Self::from_rng(Rn::seed_from_u64(seed));
+        game.seed = Some(seed);
+        game
+    }
+}
+
 impl<Rn: Rng> Game<Rn> {
     pub fn from_rng(rng: Rn) -> Self {
-        let mut rng = rng;
+        let mut rng = CountingRng::new(rng);
         let mut s = State::default();
         // game starts with two tiles
         s.rand_add(&mut rng);
         s.rand_add(&mut rng);
-        Self { rng, s, moves: 0 }
+        Self {
+            rng,
+            seed: None,
+            spawn: Spawn::Standard,
+            s,
+            moves: 0,
+            score: 0,
+        }
+    }
+
+    /// Use `spawn` to choose the tiles added after each move from now on.
+    pub fn with_spawn(mut self, spawn: Spawn) -> Self {
+        self.spawn = spawn;
+        self
     }
 
     pub fn state(&self) -> &State {
         &self.s
     }
 
-    pub fn next_state(&mut self, s: State) {
+    /// Move to `s`, which should be the result of making the legal move `m` from
+    /// the current state, and add a random tile.
+    pub fn next_state(&mut self, m: Move, s: State) {
+        debug_assert_eq!(self.s.make_move(m), s);
+        self.score += self.s.merge_score(m) as u64;
         self.s = s;
-        self.s.rand_add(&mut self.rng);
+        self.s.rand_add_spawn(self.spawn, &mut self.rng);
         self.moves += 1;
     }
 
+    /// Make the move `policy` chooses for the current board, and add a random
+    /// tile.
+    ///
+    /// Returns the move made, or `None` (leaving the game as it was) if the
+    /// policy has no move, as when the game is over.
+    pub fn step_with<F: FnOnce(&State) -> Option<(Move, State)>>(
+        &mut self,
+        policy: F,
+    ) -> Option<Move> {
+        self.step_with_rng(|s, _| policy(s))
+    }
+
+    /// Like [`Game::step_with`], but the policy can also draw from the RNG
+    /// used to add tiles.
+    pub(crate) fn step_with_rng(
+        &mut self,
+        policy: impl FnOnce(&State, &mut CountingRng<Rn>) -> Option<(Move, State)>,
+    ) -> Option<Move> {
+        let (m, next) = policy(&self.s, &mut self.rng)?;
+        self.next_state(m, next);
+        Some(m)
+    }
+
+    /// Check whether no move is left (see [`State::game_over`]).
+    pub fn is_over(&self) -> bool {
+        self.s.game_over()
+    }
+
     /// Get the number of moves made so far.
     pub fn moves(&self) -> u32 {
         self.moves
     }
+
+    /// Get the points scored so far (see [`State::merge_score`]).
+    pub fn score(&self) -> u64 {
+        self.score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::row::cached_tests::arb_cached_row;
+
+    use super::{Corner, Error, Game, HDir, Move, Spawn, State, UnknownMove, VDir};
+    use crate::ai::rand_move;
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    prop_compose! {
+        fn arb_state()(r0 in arb_cached_row(),
+                       r1 in arb_cached_row(),
+                       r2 in arb_cached_row(),
+                       r3 in arb_cached_row()) -> State {
+            State([r0, r1, r2, r3])
+        }
+    }
+
+    #[test]
+    fn rotate() {
+        let s = State::new([[0, 1, 2, 3], [4, 0, 0, 0], [8, 0, 0, 0], [12, 0, 0, 0]]);
+        assert_eq!(
+            State::new([[12, 8, 4, 0], [0, 0, 0, 1], [0, 0, 0, 2], [0, 0, 0, 3],]),
+            s.rotate_right()
+        );
+        assert_eq!(
+            State::new([[3, 0, 0, 0], [2, 0, 0, 0], [1, 0, 0, 0], [0, 4, 8, 12],]),
+            s.rotate_left()
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn prop_rotate_left3_is_right(s in arb_state()) {
+            assert_eq!(s.rotate_left().rotate_left().rotate_left(), s.rotate_right())
+        }
+
+        #[test]
+        fn prop_rotate_inverses(s in arb_state()) {
+            assert_eq!(s, s.rotate_right().rotate_left());
+            assert_eq!(s, s.rotate_left().rotate_right());
+        }
+
+        #[test]
+        fn prop_rotate_right4_is_identity(s in arb_state()) {
+            assert_eq!(s, s.rotate_right().rotate_right().rotate_right().rotate_right())
+        }
+
+        #[test]
+        fn prop_rotate_bits_match_index(s in arb_state()) {
+            assert_eq!(s.rotate_right_by_index(), s.rotate_right());
+            assert_eq!(s.rotate_left_by_index(), s.rotate_left());
+            for i in 0..16 {
+                assert_eq!(s.get(i), s.transpose().get(i % 4 * 4 + i / 4));
+                assert_eq!(s.get(i), s.mirror_horizontal().get(i / 4 * 4 + 3 - i % 4));
+            }
+        }
+
+        #[test]
+        fn prop_symmetries(s in arb_state()) {
+            assert_eq!(s, s.rotate(4));
+            assert_eq!(s, s.rotate(0));
+            assert_eq!(s.rotate_right(), s.rotate(1));
+            assert_eq!(s.rotate_right().rotate_right(), s.rotate(2));
+            assert_eq!(s.rotate_left(), s.rotate(3));
+            assert_eq!(s, s.transpose().transpose());
+            // reflecting and turning counterclockwise reflects across the diagonal
+            assert_eq!(s.transpose(), s.mirror_horizontal().rotate(3));
+        }
+
+        #[test]
+        fn prop_canonical_key(s in arb_state()) {
+            let key = s.canonical_key();
+            for turns in 0..4 {
+                assert_eq!(key, s.rotate(turns).canonical_key());
+                assert_eq!(key, s.rotate(turns).mirror_horizontal().canonical_key());
+            }
+            assert_eq!(key, s.transpose().canonical_key());
+            assert!(key <= s.pack());
+        }
+
+        #[test]
+        fn prop_vertical_moves_match_rotation(s in arb_state()) {
+            let up = s.rotate_left().move_left().rotate_right();
+            let down = s.rotate_right().move_left().rotate_left();
+            assert_eq!(up, s.make_move(Move::Up));
+            assert_eq!(down, s.make_move(Move::Down));
+        }
+    }
+
+    fn index(i: usize, j: usize) -> u8 {
+        (i * 4 + j) as u8
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(
+            // these are linear indices, but compute them here for readability
+            vec![index(0, 1), index(0, 2), index(1, 0), index(3, 3)],
+            State::new([[1, 0, 0, 2], [0, 2, 1, 3], [3, 4, 2, 5], [1, 2, 1, 0],]).empty()
+        )
+    }
+
+    #[test]
+    fn test_moves() {
+        let s = State::new([[0, 0, 0, 0], [0, 1, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(
+            State::new([[0, 0, 0, 0], [1, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0],]),
+            s.make_move(Move::Left),
+        );
+        assert_eq!(
+            State::new([[0, 0, 0, 0], [0, 0, 0, 1], [0, 0, 0, 0], [0, 0, 0, 0],]),
+            s.make_move(Move::Right),
+        );
+        assert_eq!(
+            State::new([[0, 1, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0],]),
+            s.make_move(Move::Up),
+        );
+        assert_eq!(
+            State::new([[0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 1, 0, 0],]),
+            s.make_move(Move::Down),
+        );
+    }
+
+    #[test]
+    fn apply_moves() {
+        // the cases of test_moves, through the public API
+        let s = State::from_tiles([0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let moved = |i: usize| {
+            let mut tiles = [0; 16];
+            tiles[i] = 1;
+            Some(State::from_tiles(tiles))
+        };
+        assert_eq!(moved(4), s.apply_move(Move::Left));
+        assert_eq!(moved(7), s.apply_move(Move::Right));
+        assert_eq!(moved(1), s.apply_move(Move::Up));
+        assert_eq!(moved(13), s.apply_move(Move::Down));
+        // no tile is added, and a move that changes nothing isn't legal
+        let corner = State::from_tiles([1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(None, corner.apply_move(Move::Left));
+        assert_eq!(None, corner.apply_move(Move::Up));
+        assert_eq!(15, corner.apply_move(Move::Right).unwrap().empty_count());
+    }
+
+    #[test]
+    fn shift_one_line() {
+        let s = State::new([[0, 1, 0, 1], [2, 0, 2, 4], [0, 0, 4, 0], [1, 0, 0, 1]]);
+        assert_eq!(
+            State::new([[0, 1, 0, 1], [3, 4, 0, 0], [0, 0, 4, 0], [1, 0, 0, 1]]),
+            s.shift_row(1, HDir::Left)
+        );
+        assert_eq!(
+            State::new([[0, 1, 0, 1], [2, 0, 2, 4], [0, 0, 0, 4], [1, 0, 0, 1]]),
+            s.shift_row(2, HDir::Right)
+        );
+        assert_eq!(
+            State::new([[2, 1, 0, 1], [1, 0, 2, 4], [0, 0, 4, 0], [0, 0, 0, 1]]),
+            s.shift_column(0, VDir::Up)
+        );
+        assert_eq!(
+            State::new([[0, 1, 0, 0], [2, 0, 2, 1], [0, 0, 4, 4], [1, 0, 0, 1]]),
+            s.shift_column(3, VDir::Down)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_shift_lines_is_move(s in arb_state()) {
+            let rows = |dir| (0..4).fold(s, |s, row| s.shift_row(row, dir));
+            let cols = |dir| (0..4).fold(s, |s, col| s.shift_column(col, dir));
+            assert_eq!(s.make_move(Move::Left), rows(HDir::Left));
+            assert_eq!(s.make_move(Move::Right), rows(HDir::Right));
+            assert_eq!(s.make_move(Move::Up), cols(VDir::Up));
+            assert_eq!(s.make_move(Move::Down), cols(VDir::Down));
+        }
+
+        // below the largest exponent, since a merge of two of those is capped
+        #[test]
+        fn prop_moves_conserve_value(tiles in prop::array::uniform16(0u8..15)) {
+            // merges add up two tiles and sliding moves them, so the total
+            // value stays the same while tiles only disappear
+            let s = State::from_tiles(tiles);
+            let total = |s: &State| {
+                s.exponent_grid()
+                    .iter()
+                    .filter(|&&x| x > 0)
+                    .map(|&x| 1u64 << x)
+                    .sum::<u64>()
+            };
+            let tiles = |s: &State| 16 - s.empty_count();
+            for (m, next) in s.legal_moves() {
+                assert_eq!(total(&s), total(&next), "{m:?}");
+                assert!(tiles(&next) <= tiles(&s), "{m:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn can_possibly_reach() {
+        let s = State::new([[0, 0, 0, 0], [0, 1, 0, 0], [0, 0, 0, 3], [0, 0, 0, 0]]);
+        // the tiles only add up to 10, but more will be added
+        assert!(s.can_possibly_reach(8));
+        assert!(s.can_possibly_reach(2048));
+        assert!(s.can_possibly_reach(1 << 15));
+        assert!(!s.can_possibly_reach(1 << 16));
+        let dead = State::new([[1, 2, 1, 2], [2, 1, 2, 1], [1, 2, 1, 2], [2, 1, 2, 1]]);
+        assert!(dead.can_possibly_reach(4));
+        assert!(!dead.can_possibly_reach(8));
+    }
+
+    #[test]
+    fn canonical_key() {
+        let s = State::new([[0, 1, 2, 3], [4, 0, 0, 0], [8, 0, 0, 0], [12, 0, 0, 0]]);
+        let turned = State::new([[12, 8, 4, 0], [0, 0, 0, 1], [0, 0, 0, 2], [0, 0, 0, 3]]);
+        assert_eq!(s.canonical_key(), turned.canonical_key());
+        let other = State::new([[0, 1, 2, 3], [4, 0, 0, 0], [8, 0, 0, 0], [0, 12, 0, 0]]);
+        assert_ne!(s.canonical_key(), other.canonical_key());
+    }
+
+    #[test]
+    fn num_legal_moves() {
+        let dead = State::new([[1, 2, 1, 2], [2, 1, 2, 1], [1, 2, 1, 2], [2, 1, 2, 1]]);
+        assert_eq!(0, dead.num_legal_moves());
+        let open = State::new([[0, 0, 0, 0], [0, 1, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(4, open.num_legal_moves());
+        let corner = State::new([[1, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(2, corner.num_legal_moves());
+    }
+
+    #[test]
+    fn move_strings() {
+        for m in Move::ALL {
+            assert_eq!(Ok(m), m.to_string().parse());
+            assert_eq!(Ok(m), m.to_string().to_uppercase().parse());
+            assert_eq!(Ok(m), m.to_string()[..1].parse());
+        }
+        assert_eq!("left", Move::Left.to_string());
+        assert_eq!(Ok(Move::Down), "D".parse());
+        let err = "sideways".parse::<Move>().unwrap_err();
+        assert_eq!(UnknownMove("sideways".to_string()), err);
+        assert!(err.to_string().contains("\"sideways\""), "{err}");
+        assert!("".parse::<Move>().is_err());
+        assert!("le".parse::<Move>().is_err());
+    }
+
+    #[test]
+    fn empty_mask() {
+        let s = State::new([[1, 0, 0, 2], [0, 2, 1, 3], [3, 4, 2, 5], [1, 2, 1, 0]]);
+        assert_eq!(0b1000_0000_0001_0110, s.empty_mask());
+        assert_eq!(0xffff, State::default().empty_mask());
+        assert_eq!(16, State::default().empty_count());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_empty_mask(s in arb_state()) {
+            let old: Vec<u8> = (0..16).filter(|&i| s.get(i) == 0).map(|i| i as u8).collect();
+            assert_eq!(old, s.empty());
+            assert_eq!(old.len() as u32, s.empty_count());
+            for i in 0..16 {
+                assert_eq!(old.contains(&(i as u8)), s.empty_mask() & (1 << i) != 0);
+            }
+        }
+    }
+
+    #[test]
+    fn game_over() {
+        let dead = State::new([[1, 2, 1, 2], [2, 1, 2, 1], [1, 2, 1, 2], [2, 1, 2, 1]]);
+        assert!(dead.is_full());
+        assert!(dead.game_over());
+        let one_empty = State::new([[1, 2, 1, 2], [2, 1, 2, 1], [1, 2, 0, 2], [2, 1, 2, 1]]);
+        assert!(!one_empty.is_full());
+        assert!(!one_empty.game_over());
+        // full, but two tiles can still merge
+        let mergeable = State::new([[1, 2, 1, 2], [2, 1, 2, 1], [1, 2, 1, 2], [2, 1, 2, 2]]);
+        assert!(mergeable.is_full());
+        assert!(!mergeable.game_over());
+
+        let mut game = Game::from_rng(StdRng::seed_from_u64(1));
+        let mut rng = StdRng::seed_from_u64(2);
+        while let Some((m, next)) = rand_move(game.state(), &mut rng) {
+            assert!(!game.is_over());
+            game.next_state(m, next);
+        }
+        assert!(game.is_over());
+        assert!(game.state().is_full());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_num_legal_moves(s in arb_state()) {
+            assert_eq!(s.legal_moves().len() as u32, s.num_legal_moves());
+        }
+    }
+
+    #[test]
+    fn chance_branching() {
+        // one tile in the top-left corner can only be matched by a tile on the
+        // diagonal
+        let s = State::new([[1, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(30, s.chance_branching());
+        assert_eq!(18, s.distinct_chance_outcomes());
+        // a corner, an edge cell, and a middle cell, up to symmetry
+        assert_eq!(6, State::default().distinct_chance_outcomes());
+        let full = State::new([[1, 2, 1, 2], [2, 1, 2, 1], [1, 2, 1, 2], [2, 1, 2, 1]]);
+        assert_eq!(0, full.chance_branching());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_chance_branching(s in arb_state()) {
+            assert_eq!(2 * s.empty().len() as u32, s.chance_branching());
+            assert!(s.distinct_chance_outcomes() <= s.chance_branching());
+        }
+
+        #[test]
+        fn prop_mirror_involution(s in arb_state()) {
+            assert_eq!(s, s.mirror_horizontal().mirror_horizontal());
+        }
+    }
+
+    #[test]
+    fn min_moves_to_target() {
+        let s = State::new([[10, 10, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 1]]);
+        assert_eq!(0, s.min_moves_to_target(1024));
+        assert_eq!(1, s.min_moves_to_target(2048));
+        // 2050 in tiles, so at least 512 more tiles need to be added first
+        assert_eq!(513, s.min_moves_to_target(4096));
+        let s = State::new([[1, 1, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(1, s.min_moves_to_target(4));
+        assert_eq!(2, s.min_moves_to_target(8));
+        assert_eq!(512, s.min_moves_to_target(2048));
+    }
+
+    #[test]
+    fn tiles() {
+        let tiles = [0, 1, 0, 2, 3, 0, 0, 0, 0, 0, 11, 0, 15, 0, 0, 1];
+        let mut s = State::from_tiles(tiles);
+        assert_eq!(tiles, s.to_tiles());
+        assert_eq!(2048, s.tile(10));
+        s.set(10, 0);
+        s.set(0, 4);
+        s.set(1, 2);
+        assert_eq!([4, 2, 0, 2], s.to_tiles()[..4]);
+        assert_eq!(0, s.to_tiles()[10]);
+    }
+
+    #[test]
+    #[should_panic(expected = "exponent 16")]
+    fn from_tiles_too_large() {
+        State::from_tiles([16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_tiles_roundtrip(s in arb_state()) {
+            assert_eq!(s, State::from_tiles(s.to_tiles()));
+        }
+
+        #[test]
+        fn prop_apply_move(s in arb_state()) {
+            for m in Move::ALL {
+                let next = s.make_move(m);
+                assert_eq!((next != s).then_some(next), s.apply_move(m));
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_boards() {
+        let empty = State::default();
+        assert_eq!(0, empty.highest_tile());
+        assert!(empty.legal_moves().is_empty());
+        assert_eq!(0, empty.min_moves_to_target(0));
+        let corner = State::new([[1, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(2, corner.highest_tile());
+        let moves: Vec<Move> = corner.legal_moves().iter().map(|&(m, _)| m).collect();
+        assert_eq!(vec![Move::Right, Move::Down], moves);
+        let mut middle = State::default();
+        middle.add(5, 3);
+        assert_eq!(4, middle.num_legal_moves());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let mut game = Game::from_rng(StdRng::seed_from_u64(5));
+        let mut rng = StdRng::seed_from_u64(6);
+        for _ in 0..30 {
+            let (m, s) = rand_move(game.state(), &mut rng).unwrap();
+            game.next_state(m, s);
+        }
+        let s = *game.state();
+        let json = serde_json::to_string(&s).unwrap();
+        let tiles: Vec<String> = s.to_tiles().iter().map(|x| x.to_string()).collect();
+        assert_eq!(format!("[{}]", tiles.join(",")), json);
+        assert_eq!(s, serde_json::from_str(&json).unwrap());
+
+        assert!(serde_json::from_str::<State>("[1, 2, 3]").is_err());
+        assert!(serde_json::from_str::<State>(&format!("[16{}]", ",0".repeat(15))).is_err());
+        let moves = serde_json::to_string(&Move::ALL).unwrap();
+        assert_eq!(r#"["Left","Right","Up","Down"]"#, moves);
+        assert_eq!(
+            Move::ALL,
+            serde_json::from_str::<[Move; 4]>(&moves).unwrap()
+        );
+    }
+
+    #[test]
+    fn exponent_grid() {
+        let s = State::new([[0, 1, 2, 3], [4, 5, 6, 7], [8, 9, 10, 11], [12, 13, 14, 15]]);
+        assert_eq!(
+            [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+            s.exponent_grid()
+        );
+    }
+
+    #[test]
+    fn distance() {
+        let s = State::new([[1, 2, 0, 0], [0, 3, 0, 0], [0, 0, 0, 0], [0, 0, 0, 11]]);
+        assert_eq!(0, s.cell_distance(&s));
+        assert_eq!(0, s.value_distance(&s));
+
+        let one_cell = State::new([[1, 2, 0, 0], [0, 4, 0, 0], [0, 0, 0, 0], [0, 0, 0, 11]]);
+        assert_eq!(1, s.cell_distance(&one_cell));
+        assert_eq!(8, s.value_distance(&one_cell));
+        assert_eq!(8, one_cell.value_distance(&s));
+
+        let empty = State::default();
+        assert_eq!(4, s.cell_distance(&empty));
+        assert_eq!(2 + 4 + 8 + 2048, s.value_distance(&empty));
+
+        let full = State::new([[2, 1, 1, 1], [1, 1, 1, 1], [1, 1, 1, 1], [1, 1, 1, 1]]);
+        assert_eq!(16, s.cell_distance(&full));
+        assert_eq!(5 * 2 + 6 + 9 * 2 + 2046, s.value_distance(&full));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_distance_symmetric(s1 in arb_state(), s2 in arb_state()) {
+            prop_assert_eq!(s1.cell_distance(&s2), s2.cell_distance(&s1));
+            prop_assert_eq!(s1.value_distance(&s2), s2.value_distance(&s1));
+            prop_assert_eq!(s1 == s2, s1.cell_distance(&s2) == 0);
+        }
+    }
+
+    #[test]
+    fn best_move_by() {
+        let s = State::new([[1, 1, 2, 0], [0, 0, 0, 0], [3, 0, 0, 0], [3, 0, 0, 0]]);
+        let empty = |s: &State| s.empty().len() as f64;
+        // only left merges the whole first row into a single tile
+        assert_eq!(
+            Some((Move::Left, s.make_move(Move::Left))),
+            s.best_move_by(empty)
+        );
+        // with a constant objective the first legal move wins
+        assert_eq!(
+            Some((Move::Left, s.make_move(Move::Left))),
+            s.best_move_by(|_| 0.0)
+        );
+        let corner = State::new([[1, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(Move::Right, corner.best_move_by(|_| 0.0).unwrap().0);
+        assert_eq!(None, State::default().best_move_by(empty));
+    }
+
+    #[test]
+    fn corner_pressure() {
+        // sorted toward the top-left corner
+        let s = State::new([[6, 5, 4, 3], [5, 4, 3, 2], [4, 3, 2, 1], [3, 2, 1, 0]]);
+        assert!(s.corner_pressure(Corner::TopLeft) > 0.0);
+        assert!(s.corner_pressure(Corner::BottomRight) < 0.0);
+        // symmetric about the diagonal through the other two corners
+        assert_eq!(0.0, s.corner_pressure(Corner::TopRight));
+        assert_eq!(0.0, s.corner_pressure(Corner::BottomLeft));
+
+        // just the top row, with 64 on the left
+        let s = State::new([[6, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(128.0, s.corner_pressure(Corner::TopLeft));
+        assert_eq!(0.0, s.corner_pressure(Corner::TopRight));
+        assert_eq!(-128.0, s.corner_pressure(Corner::BottomRight));
+        assert_eq!(0.0, State::default().corner_pressure(Corner::TopLeft));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_opposite_corner_pressure(s in arb_state()) {
+            prop_assert_eq!(
+                s.corner_pressure(Corner::TopLeft),
+                -s.corner_pressure(Corner::BottomRight)
+            );
+            prop_assert_eq!(
+                s.corner_pressure(Corner::TopRight),
+                -s.corner_pressure(Corner::BottomLeft)
+            );
+        }
+    }
+
+    #[test]
+    fn merges_for_move() {
+        let s = State::new([[1, 1, 2, 0], [0, 0, 0, 0], [3, 0, 0, 0], [3, 0, 0, 0]]);
+        // 1+1 and then the cascading 2+2
+        assert_eq!(2, s.merges_for_move(Move::Left));
+        assert_eq!(1, s.merges_for_move(Move::Right));
+        assert_eq!(1, s.merges_for_move(Move::Up));
+        assert_eq!(0, State::default().merges_for_move(Move::Up));
+    }
+
+    #[test]
+    fn only_fillers() {
+        let s = State::new([[1, 0, 0, 0], [2, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 3]]);
+        assert!(s.only_fillers());
+        let s = State::new([[1, 0, 0, 0], [2, 0, 0, 0], [0, 0, 0, 0], [2, 0, 0, 0]]);
+        assert!(!s.only_fillers());
+    }
+
+    #[test]
+    fn from_exponents() {
+        let exps = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        let s = State::from_exponents(&exps).unwrap();
+        assert_eq!(exps, s.exponent_grid());
+        let mut exps = exps;
+        exps[9] = 16;
+        assert_eq!(
+            Err(Error::ExponentOutOfRange {
+                index: 9,
+                exponent: 16
+            }),
+            State::from_exponents(&exps)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_exponent_grid_roundtrip(s in arb_state()) {
+            prop_assert_eq!(s, State::from_exponents(&s.exponent_grid()).unwrap());
+        }
+    }
+
+    #[test]
+    fn progressive_spawns() {
+        let threshold = 64;
+        let mut game = Game::from_rng(StdRng::seed_from_u64(2)).with_spawn(Spawn::Progressive {
+            threshold,
+            eight_prob: 0.5,
+        });
+        let mut move_rng = StdRng::seed_from_u64(3);
+        let mut eights = 0;
+        while let Some((m, s)) = rand_move(game.state(), &mut move_rng) {
+            let reached = s.highest_tile() >= threshold;
+            game.next_state(m, s);
+            // the one cell that changed is the new tile
+            let (before, after) = (s.exponent_grid(), game.state().exponent_grid());
+            let i = (0..16).find(|&i| before[i] != after[i]).unwrap();
+            if reached {
+                assert!([1, 2, 3].contains(&after[i]));
+                eights += (after[i] == 3) as u32;
+            } else {
+                assert!([1, 2].contains(&after[i]), "spawned {} early", after[i]);
+            }
+        }
+        assert!(game.state().highest_tile() >= threshold);
+        assert!(eights > 0);
+    }
+
+    #[test]
+    fn compact_hex() {
+        let s = State::new([[1, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 11]]);
+        assert_eq!("b000000000000001", s.to_compact_hex());
+        assert_eq!(Some(s), State::from_compact_hex("b000000000000001"));
+        assert_eq!(None, State::from_compact_hex("b00000000000001"));
+        assert_eq!(None, State::from_compact_hex("+b00000000000001"));
+        assert_eq!(None, State::from_compact_hex("g000000000000001"));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_compact_hex_roundtrip(s in arb_state()) {
+            let hex = s.to_compact_hex();
+            prop_assert_eq!(16, hex.len());
+            prop_assert_eq!(Some(s), State::from_compact_hex(&hex));
+            prop_assert_eq!(s, State::from_u64(s.pack()));
+        }
+    }
+
+    #[test]
+    fn top_two_tiles() {
+        let s = State::new([[0, 3, 0, 0], [0, 0, 0, 0], [0, 0, 0, 5], [0, 0, 0, 1]]);
+        assert_eq!(11, s.max_tile_position());
+        assert_eq!(Some((11, 1)), s.top_two_tiles());
+        assert!(!s.top_two_adjacent());
+
+        let s = State::new([[0, 3, 0, 0], [0, 0, 0, 4], [0, 0, 0, 5], [0, 0, 0, 1]]);
+        assert_eq!(Some((11, 7)), s.top_two_tiles());
+        assert!(s.top_two_adjacent());
+
+        // ties go to the first tile in linear order
+        let s = State::new([[0, 2, 0, 0], [0, 0, 0, 0], [0, 0, 0, 2], [2, 0, 0, 1]]);
+        assert_eq!(1, s.max_tile_position());
+        assert_eq!(Some((1, 11)), s.top_two_tiles());
+
+        let s = State::new([[0, 0, 0, 0], [0, 0, 0, 0], [0, 6, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(9, s.max_tile_position());
+        assert_eq!(None, s.top_two_tiles());
+        assert!(s.top_two_adjacent());
+    }
+
+    #[test]
+    fn value_concentration() {
+        let one_tile = State::new([[0, 0, 0, 0], [0, 11, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(1.0, one_tile.value_concentration());
+        assert_eq!(0.0, one_tile.tile_entropy());
+
+        let big_tile = State::new([[0, 0, 0, 0], [0, 11, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]]);
+        assert!(big_tile.value_concentration() > 0.99);
+
+        let equal_tiles = State::new([[3, 3, 3, 3], [3, 3, 3, 3], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(1.0 / 8.0, equal_tiles.value_concentration());
+        assert!((equal_tiles.tile_entropy() - 3.0).abs() < 1e-9);
+        assert!(equal_tiles.tile_entropy() > big_tile.tile_entropy());
+
+        assert_eq!(0.0, State::default().value_concentration());
+        assert_eq!(0.0, State::default().tile_entropy());
+    }
+
+    #[test]
+    #[should_panic(expected = "cell index 16 is out of bounds")]
+    fn add_out_of_bounds() {
+        State::default().add(16, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "exponent 200 is too large")]
+    fn add_too_large() {
+        State::default().add(5, 200);
+    }
+
+    #[test]
+    fn dominates() {
+        let s = State::new([[1, 2, 0, 0], [0, 3, 0, 0], [0, 0, 0, 0], [0, 0, 0, 11]]);
+        assert!(s.dominates(&s));
+        let smaller = State::new([[1, 1, 0, 0], [0, 3, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert!(s.dominates(&smaller));
+        assert!(!smaller.dominates(&s));
+        let other = State::new([[0, 2, 0, 0], [0, 3, 0, 0], [0, 0, 0, 0], [0, 0, 1, 11]]);
+        assert!(!s.dominates(&other));
+        assert!(!other.dominates(&s));
+        assert!(s.dominates(&State::default()));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_dominates_antisymmetric(s1 in arb_state(), s2 in arb_state()) {
+            prop_assert_eq!(s1 == s2, s1.dominates(&s2) && s2.dominates(&s1));
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let s = State::new([[1, 1, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        let next = State::new([[2, 0, 0, 0], [0, 0, 0, 0], [0, 0, 2, 0], [0, 0, 0, 0]]);
+        assert_eq!(Some((Move::Left, 10, 2)), s.diff(&next));
+        // the move alone, with no new tile
+        let moved = State::new([[2, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(None, s.diff(&moved));
+        // an 8 can't be added
+        let eight = State::new([[2, 0, 0, 0], [0, 0, 0, 0], [0, 0, 3, 0], [0, 0, 0, 0]]);
+        assert_eq!(None, s.diff(&eight));
+        assert_eq!(None, s.diff(&s));
+    }
+
+    #[test]
+    fn pack() {
+        let s = State::new([[1, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 15]]);
+        assert_eq!(0xf000_0000_0000_0001, s.pack());
+        assert_eq!(0, State::default().pack());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_pack_injective(s1 in arb_state(), s2 in arb_state()) {
+            assert_eq!(s1 == s2, s1.pack() == s2.pack())
+        }
+    }
+
+    #[test]
+    fn preview_move() {
+        let s = State::new([[0, 0, 0, 0], [1, 1, 2, 0], [0, 0, 0, 0], [0, 0, 3, 0]]);
+        let (next, merged) = s.preview_move(Move::Left).unwrap();
+        assert_eq!(s.make_move(Move::Left), next);
+        // 2+2 merges into the first cell of the second row
+        let mut expected = [false; 16];
+        expected[4] = true;
+        assert_eq!(expected, merged);
+
+        let (next, merged) = s.preview_move(Move::Right).unwrap();
+        assert_eq!(s.make_move(Move::Right), next);
+        // 1+1 merges next to the 2 without cascading into it
+        let mut expected = [false; 16];
+        expected[6] = true;
+        assert_eq!(expected, merged);
+
+        // nothing merges vertically
+        for m in [Move::Up, Move::Down] {
+            let (next, merged) = s.preview_move(m).unwrap();
+            assert_eq!(s.make_move(m), next);
+            assert_eq!([false; 16], merged);
+        }
+    }
+
+    #[test]
+    fn preview_vertical_merge() {
+        let s = State::new([[0, 2, 0, 0], [0, 0, 0, 0], [0, 2, 0, 0], [0, 1, 0, 0]]);
+        let (next, merged) = s.preview_move(Move::Up).unwrap();
+        assert_eq!(s.make_move(Move::Up), next);
+        let mut expected = [false; 16];
+        expected[1] = true;
+        assert_eq!(expected, merged);
+
+        let (next, merged) = s.preview_move(Move::Down).unwrap();
+        assert_eq!(s.make_move(Move::Down), next);
+        let mut expected = [false; 16];
+        expected[9] = true;
+        assert_eq!(expected, merged);
+    }
+
+    #[test]
+    fn preview_illegal_move() {
+        let s = State::new([[1, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(None, s.preview_move(Move::Left));
+        assert_eq!(None, s.preview_move(Move::Up));
+    }
+
+    #[test]
+    fn merge_score() {
+        let s = State::new([[1, 1, 0, 0], [2, 0, 2, 0], [2, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(4 + 8, s.merge_score(Move::Left));
+        assert_eq!(4 + 8, s.merge_score(Move::Right));
+        assert_eq!(8, s.merge_score(Move::Up));
+        assert_eq!(8, s.merge_score(Move::Down));
+    }
+
+    #[test]
+    fn step_with() {
+        let mut game = Game::from_rng(StdRng::seed_from_u64(6));
+        let mut move_rng = StdRng::seed_from_u64(7);
+        loop {
+            let (moves, before) = (game.moves(), *game.state());
+            match game.step_with(|s| rand_move(s, &mut move_rng)) {
+                Some(m) => {
+                    assert_eq!(moves + 1, game.moves());
+                    assert!(before.apply_move(m).is_some());
+                }
+                None => break,
+            }
+        }
+        assert!(game.is_over());
+        let (moves, end) = (game.moves(), *game.state());
+        assert_eq!(None, game.step_with(|s| rand_move(s, &mut move_rng)));
+        assert_eq!((moves, end), (game.moves(), *game.state()));
+    }
+
+    #[test]
+    fn game_score() {
+        let mut g = Game::from_rng(StdRng::seed_from_u64(0));
+        let mut move_rng = StdRng::seed_from_u64(1);
+        let mut expected = 0;
+        while let Some((m, s)) = rand_move(g.state(), &mut move_rng) {
+            expected += g.state().merge_score(m) as u64;
+            g.next_state(m, s);
+        }
+        assert_eq!(expected, g.score());
+        assert!(g.score() > 0);
+
+        // merging two 2s scores 4, and a full row of 4s scores two 8s
+        for (row, points) in [([1, 1, 0, 0], 4), ([2, 2, 2, 2], 16)] {
+            let mut g = Game::from_rng(StdRng::seed_from_u64(0));
+            g.s = State::new([row, [0; 4], [0; 4], [0; 4]]);
+            g.next_state(Move::Left, g.state().make_move(Move::Left));
+            assert_eq!(points, g.score());
+        }
+    }
+
+    #[test]
+    fn printing() {
+        assert_eq!(
+            " 0  1  2  3
+ 4  5  6  7
+ 8  9 10 11
+12 13 14 15
+",
+            format!(
+                "{}",
+                State::new([[0, 1, 2, 3], [4, 5, 6, 7], [8, 9, 10, 11], [12, 13, 14, 15],])
+            )
+        )
+    }
 }
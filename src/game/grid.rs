@@ -0,0 +1,328 @@
+//! Boards of any square size, for variants of the game on larger grids.
+//!
+//! [`State`] is specialized to 4x4 boards so that every row shift is a table
+//! lookup. A [`Grid`] stores one exponent per cell and shifts rows the way
+//! the row module's `ArrayRow` does, which works for rows of any length.
+//! The rules (including how merges cascade) are the same, so a `Grid<4>`
+//! moves exactly like the equivalent [`State`], except that two tiles with
+//! exponent [`MAX_EXPONENT`] don't merge, since the result couldn't be stored.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use super::{right_rotate_index, Move, Spawn, State, MAX_EXPONENT};
+
+/// An `N`x`N` board, holding the exponent of each tile (0 for empty cells).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Grid<const N: usize>([[u8; N]; N]);
+
+impl<const N: usize> Default for Grid<N> {
+    fn default() -> Self {
+        Self([[0; N]; N])
+    }
+}
+
+impl<const N: usize> fmt::Display for Grid<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in &self.0 {
+            let cells: Vec<String> = row.iter().map(|x| format!("{x:>2}")).collect();
+            writeln!(f, "{}", cells.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+// Shift a row left in place, like ArrayRow::shift_left: each tile moves as far
+// as it can, and a merged tile can merge again with the next one to arrive.
+// Tiles at MAX_EXPONENT stay apart, so every exponent stays in range.
+fn shift_line_left(line: &mut [u8]) {
+    let mut out = 0;
+    for j in 0..line.len() {
+        let x = line[j];
+        if x == 0 {
+            continue;
+        }
+        line[j] = 0;
+        line[out] = x;
+        if out > 0 && line[out] == line[out - 1] && x < MAX_EXPONENT {
+            line[out - 1] += 1;
+            line[out] = 0;
+        } else {
+            out += 1;
+        }
+    }
+}
+
+impl<const N: usize> Grid<N> {
+    /// Build a board from rows of exponents.
+    ///
+    /// Panics if an exponent is larger than [`MAX_EXPONENT`].
+    pub fn new(rows: [[u8; N]; N]) -> Self {
+        for (index, &x) in rows.iter().flatten().enumerate() {
+            assert!(
+                x <= MAX_EXPONENT,
+                "exponent {x} in cell {index} is too large (the maximum is {MAX_EXPONENT})"
+            );
+        }
+        Self(rows)
+    }
+
+    /// The exponents of every row, top to bottom.
+    pub fn rows(&self) -> &[[u8; N]; N] {
+        &self.0
+    }
+
+    /// Get a cell's exponent by linear index (in `0..N*N`).
+    pub fn get(&self, i: usize) -> u8 {
+        self.0[i / N][i % N]
+    }
+
+    /// Put a tile with exponent `x` in the empty cell `i`.
+    pub fn add(&mut self, i: usize, x: u8) {
+        assert!(
+            i < N * N,
+            "cell index {i} is out of bounds (boards have {} cells)",
+            N * N
+        );
+        assert!(
+            x <= MAX_EXPONENT,
+            "exponent {x} is too large (the maximum is {MAX_EXPONENT})"
+        );
+        let cell = &mut self.0[i / N][i % N];
+        assert!(*cell == 0, "cannot add to cell {i}, which holds {cell}");
+        *cell = x;
+    }
+
+    /// The linear indices of the empty cells.
+    pub fn empty(&self) -> Vec<usize> {
+        (0..N * N).filter(|&i| self.get(i) == 0).collect()
+    }
+
+    /// The value of the highest tile, or 0 for an empty board.
+    pub fn highest_tile(&self) -> u32 {
+        match self.0.iter().flatten().copied().max().unwrap_or(0) {
+            0 => 0,
+            exp => 1 << exp,
+        }
+    }
+
+    pub fn rotate_right(&self) -> Self {
+        let mut new = Self::default();
+        for i in 0..N * N {
+            new.0[i / N][i % N] = self.get(right_rotate_index(N, i));
+        }
+        new
+    }
+
+    pub fn rotate_left(&self) -> Self {
+        let mut new = Self::default();
+        for i in 0..N * N {
+            let j = right_rotate_index(N, i);
+            new.0[j / N][j % N] = self.get(i);
+        }
+        new
+    }
+
+    fn move_left(&self) -> Self {
+        let mut new = *self;
+        for row in new.0.iter_mut() {
+            shift_line_left(row);
+        }
+        new
+    }
+
+    fn move_right(&self) -> Self {
+        let mut new = *self;
+        for row in new.0.iter_mut() {
+            row.reverse();
+            shift_line_left(row);
+            row.reverse();
+        }
+        new
+    }
+
+    /// The board after moving in direction `m`, before a tile is added (the
+    /// same board if the move isn't legal).
+    pub fn make_move(&self, m: Move) -> Self {
+        match m {
+            Move::Left => self.move_left(),
+            Move::Right => self.move_right(),
+            Move::Up => self.rotate_left().move_left().rotate_right(),
+            Move::Down => self.rotate_right().move_left().rotate_left(),
+        }
+    }
+
+    /// Every move that changes the board, with the board after it.
+    pub fn legal_moves(&self) -> Vec<(Move, Self)> {
+        Move::ALL
+            .iter()
+            .filter_map(|&m| {
+                let s = self.make_move(m);
+                (s != *self).then_some((m, s))
+            })
+            .collect()
+    }
+
+    /// Add a random tile to an empty cell, chosen as in the original game.
+    ///
+    /// Panics if the board is full.
+    pub fn rand_add<Rn: Rng>(&mut self, rng: &mut Rn) -> &mut Self {
+        let &i = self
+            .empty()
+            .choose(rng)
+            .expect("attempt to add to a full board");
+        let x = Spawn::Standard.exponent(&State::default(), rng);
+        self.add(i, x);
+        self
+    }
+}
+
+impl From<State> for Grid<4> {
+    fn from(s: State) -> Self {
+        let tiles = s.to_tiles();
+        Self(core::array::from_fn(|r| {
+            core::array::from_fn(|c| tiles[r * 4 + c])
+        }))
+    }
+}
+
+impl From<Grid<4>> for State {
+    fn from(g: Grid<4>) -> Self {
+        let mut tiles = [0; 16];
+        for (i, x) in g.0.iter().flatten().enumerate() {
+            tiles[i] = *x;
+        }
+        State::from_tiles(tiles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::Grid;
+    use crate::game::{Move, State};
+
+    #[test]
+    fn shift_5x5() {
+        let g = Grid::new([
+            [1, 1, 2, 0, 3],
+            [0, 0, 0, 0, 4],
+            [2, 0, 2, 0, 2],
+            [0, 0, 0, 0, 0],
+            [5, 4, 3, 2, 1],
+        ]);
+        assert_eq!(
+            &[
+                [4, 0, 0, 0, 0],
+                [4, 0, 0, 0, 0],
+                [3, 2, 0, 0, 0],
+                [0, 0, 0, 0, 0],
+                [5, 4, 3, 2, 1],
+            ],
+            g.make_move(Move::Left).rows()
+        );
+        assert_eq!(
+            &[
+                [0, 0, 2, 2, 3],
+                [0, 0, 0, 0, 4],
+                [0, 0, 0, 2, 3],
+                [0, 0, 0, 0, 0],
+                [5, 4, 3, 2, 1],
+            ],
+            g.make_move(Move::Right).rows()
+        );
+        assert_eq!(
+            &[
+                [1, 1, 4, 2, 3],
+                [2, 4, 0, 0, 4],
+                [5, 0, 0, 0, 2],
+                [0, 0, 0, 0, 1],
+                [0, 0, 0, 0, 0],
+            ],
+            g.make_move(Move::Up).rows()
+        );
+        assert_eq!(5, g.highest_tile().trailing_zeros());
+        assert_eq!(12, g.empty().len());
+    }
+
+    #[test]
+    fn rotate_5x5() {
+        let mut rows = [[0; 5]; 5];
+        for (i, x) in rows.iter_mut().flatten().enumerate() {
+            *x = (i % 15) as u8;
+        }
+        let g = Grid::new(rows);
+        let r = g.rotate_right();
+        // the left column, bottom to top, becomes the top row
+        assert_eq!([5, 0, 10, 5, 0], r.rows()[0]);
+        assert_eq!(g, r.rotate_left());
+        assert_eq!(r, g.rotate_left().rotate_left().rotate_left());
+    }
+
+    #[test]
+    fn rand_add_fills_board() {
+        let mut g = Grid::<6>::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..36 {
+            g.rand_add(&mut rng);
+        }
+        assert!(g.empty().is_empty());
+        assert!(g.rows().iter().flatten().all(|&x| x == 1 || x == 2));
+    }
+
+    #[test]
+    fn largest_tiles_dont_merge() {
+        let g = Grid::new([[15, 15, 14, 14], [0; 4], [0; 4], [0; 4]]);
+        let left = g.make_move(Move::Left);
+        assert_eq!([15, 15, 15, 0], left.rows()[0]);
+        // the new 2^15 doesn't merge either
+        assert_eq!([15, 15, 15, 0], left.make_move(Move::Left).rows()[0]);
+        assert_eq!(
+            vec![Move::Right, Move::Down],
+            left.legal_moves()
+                .into_iter()
+                .map(|(m, _)| m)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn every_row_matches_state() {
+        // every row of tiles State can merge, shifted both ways
+        for bits in 0..=u16::MAX {
+            let row: [u8; 4] = std::array::from_fn(|i| (bits >> (4 * i)) as u8 & 0xf);
+            if row.contains(&15) {
+                continue;
+            }
+            let s = State::new([row, [0; 4], [0; 4], [0; 4]]);
+            let g = Grid::from(s);
+            for m in [Move::Left, Move::Right] {
+                assert_eq!(s.make_move(m), State::from(g.make_move(m)), "{row:?} {m}");
+            }
+        }
+    }
+
+    proptest! {
+        // tiles are below the largest exponent, which State can't merge
+        #[test]
+        fn prop_4x4_matches_state(tiles in prop::array::uniform16(0u8..15)) {
+            let s = State::from_tiles(tiles);
+            let g = Grid::from(s);
+            assert_eq!(s, State::from(g));
+            let legal: Vec<_> = g
+                .legal_moves()
+                .into_iter()
+                .map(|(m, next)| (m, State::from(next)))
+                .collect();
+            assert_eq!(s.legal_moves(), legal);
+            assert_eq!(s.highest_tile(), g.highest_tile());
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! A board packed into a single `u64`, with moves made by bit manipulation.
+//!
+//! [`State`] stores its rows as [`CachedRow`]s and moves up and down by
+//! rotating the board one cell at a time. [`PackedState`] has the same
+//! layout as [`State::pack`] (row `i` in bits `16*i..16*(i+1)`, four bits per
+//! cell), looks up every row shift in the same tables, but transposes the
+//! whole board with a handful of masks and shifts instead.
+
+use alloc::vec::Vec;
+
+use super::row::{CachedRow, Row};
+use super::{Move, State};
+
+/// The operations shared by the board representations.
+pub trait Board: Copy + Eq {
+    /// The board after moving in direction `m`, before a tile is added (the
+    /// same board if the move isn't legal).
+    fn make_move(&self, m: Move) -> Self;
+
+    /// The linear indices of the empty cells.
+    fn empty(&self) -> Vec<u8>;
+
+    /// The value of the highest tile, or 0 for an empty board.
+    fn highest_tile(&self) -> u32;
+
+    /// Every move that changes the board, with the board after it.
+    fn legal_moves(&self) -> Vec<(Move, Self)> {
+        Move::ALL
+            .iter()
+            .filter_map(|&m| {
+                let s = self.make_move(m);
+                (s != *self).then_some((m, s))
+            })
+            .collect()
+    }
+}
+
+impl Board for State {
+    fn make_move(&self, m: Move) -> Self {
+        State::make_move(self, m)
+    }
+
+    fn empty(&self) -> Vec<u8> {
+        State::empty(self)
+    }
+
+    fn highest_tile(&self) -> u32 {
+        State::highest_tile(self)
+    }
+
+    fn legal_moves(&self) -> Vec<(Move, Self)> {
+        State::legal_moves(self)
+    }
+}
+
+/// A board with all 16 cells in one `u64`, in the layout of [`State::pack`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PackedState(pub u64);
+
+impl PackedState {
+    #[inline]
+    fn cell(&self, i: usize) -> u8 {
+        ((self.0 >> (4 * i)) & 0xf) as u8
+    }
+
+    // apply a row shift to each of the four rows
+    #[inline]
+    fn map_rows(&self, f: impl Fn(CachedRow) -> CachedRow) -> Self {
+        let mut packed = 0;
+        for i in 0..4 {
+            let row = CachedRow::from_bits((self.0 >> (16 * i)) as u16);
+            packed |= (f(row).to_bits() as u64) << (16 * i);
+        }
+        Self(packed)
+    }
+
+    /// Swap rows and columns, so column `i` becomes row `i`.
+    #[inline]
+    pub fn transpose(&self) -> Self {
+        Self(transpose_bits(self.0))
+    }
+}
+
+/// Transpose a packed board, so column `i` becomes row `i`.
+#[inline]
+pub(super) fn transpose_bits(x: u64) -> u64 {
+    // swap cells across the diagonal within each 2x2 block
+    let a1 = x & 0xF0F0_0F0F_F0F0_0F0F;
+    let a2 = x & 0x0000_F0F0_0000_F0F0;
+    let a3 = x & 0x0F0F_0000_0F0F_0000;
+    let a = a1 | (a2 << 12) | (a3 >> 12);
+    // then swap the off-diagonal 2x2 blocks
+    let b1 = a & 0xFF00_FF00_00FF_00FF;
+    let b2 = a & 0x00FF_00FF_0000_0000;
+    let b3 = a & 0x0000_0000_FF00_FF00;
+    b1 | (b2 >> 24) | (b3 << 24)
+}
+
+/// Reverse the order of the cells in each row of a packed board.
+#[inline]
+pub(super) fn mirror_bits(x: u64) -> u64 {
+    // swap neighboring cells, then neighboring pairs of cells
+    let x = ((x & 0x0F0F_0F0F_0F0F_0F0F) << 4) | ((x >> 4) & 0x0F0F_0F0F_0F0F_0F0F);
+    ((x & 0x00FF_00FF_00FF_00FF) << 8) | ((x >> 8) & 0x00FF_00FF_00FF_00FF)
+}
+
+impl From<State> for PackedState {
+    fn from(s: State) -> Self {
+        Self(s.pack())
+    }
+}
+
+impl From<PackedState> for State {
+    fn from(s: PackedState) -> Self {
+        State::from_u64(s.0)
+    }
+}
+
+impl Board for PackedState {
+    fn make_move(&self, m: Move) -> Self {
+        match m {
+            Move::Left => self.map_rows(|r| r.shift_left()),
+            Move::Right => self.map_rows(|r| r.shift_right()),
+            Move::Up => self.transpose().map_rows(|r| r.shift_left()).transpose(),
+            Move::Down => self.transpose().map_rows(|r| r.shift_right()).transpose(),
+        }
+    }
+
+    fn empty(&self) -> Vec<u8> {
+        (0..16)
+            .filter(|&i| self.cell(i) == 0)
+            .map(|i| i as u8)
+            .collect()
+    }
+
+    fn highest_tile(&self) -> u32 {
+        match (0..16).map(|i| self.cell(i)).max().unwrap() {
+            0 => 0,
+            exp => 1 << exp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::{Board, PackedState};
+    use crate::game::{Move, State};
+
+    fn tiles(s: PackedState) -> [u8; 16] {
+        State::from(s).to_tiles()
+    }
+
+    #[test]
+    fn transpose() {
+        let mut grid = [0; 16];
+        for (i, x) in grid.iter_mut().enumerate() {
+            *x = i as u8;
+        }
+        let s = PackedState::from(State::from_tiles(grid));
+        let t = tiles(s.transpose());
+        for r in 0..4 {
+            for c in 0..4 {
+                assert_eq!(grid[r * 4 + c], t[c * 4 + r]);
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_transpose_twice(x in any::<u64>()) {
+            let s = PackedState(x);
+            assert_eq!(s, s.transpose().transpose());
+        }
+
+        #[test]
+        fn prop_same_as_state(x in any::<u64>()) {
+            let s = State::from_u64(x);
+            let packed = PackedState(x);
+            for m in Move::ALL {
+                assert_eq!(s.make_move(m).pack(), packed.make_move(m).0, "{:?}", m);
+            }
+            let legal: Vec<_> = packed
+                .legal_moves()
+                .into_iter()
+                .map(|(m, next)| (m, State::from(next)))
+                .collect();
+            assert_eq!(s.legal_moves(), legal);
+            assert_eq!(s.empty(), packed.empty());
+            assert_eq!(s.highest_tile(), packed.highest_tile());
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Puzzles: boards where a target tile can be built just by sliding, with no
+//! new tiles spawning in between moves.
+
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use rand::prelude::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use super::{Move, State};
+use crate::hash::HashSet;
+
+/// Generated puzzles are rejected until one takes exactly the requested number
+/// of moves; this bounds how many attempts are made.
+const MAX_PUZZLE_ATTEMPTS: u32 = 100_000;
+
+impl State {
+    /// Find a shortest sequence of at most `max_moves` moves that creates a
+    /// `target` tile, if spawns are disabled.
+    ///
+    /// Returns an empty sequence if the board already has the target.
+    pub fn solve_to(&self, target: u32, max_moves: u32) -> Option<Vec<Move>> {
+        if self.highest_tile() >= target {
+            return Some(vec![]);
+        }
+        // breadth-first search, so the first solution found is a shortest one
+        let mut seen: HashSet<u64> = [self.pack()].into_iter().collect();
+        let mut queue = VecDeque::from([(*self, vec![])]);
+        while let Some((s, moves)) = queue.pop_front() {
+            if moves.len() as u32 == max_moves {
+                continue;
+            }
+            for (m, next_s) in s.legal_moves() {
+                if !seen.insert(next_s.pack()) {
+                    continue;
+                }
+                let mut next_moves = moves.clone();
+                next_moves.push(m);
+                if next_s.highest_tile() >= target {
+                    return Some(next_moves);
+                }
+                queue.push_back((next_s, next_moves));
+            }
+        }
+        None
+    }
+
+    /// Generate a random puzzle whose shortest solution to build `target` takes
+    /// exactly `difficulty` moves (see [`State::solve_to`]).
+    ///
+    /// The board is built by repeatedly splitting a `target` tile into halves,
+    /// so the total value on the board is exactly `target`. The same seed
+    /// always produces the same puzzle.
+    ///
+    /// Panics if `target` is not a power of two between 4 and 32768, if
+    /// `difficulty` is 0, or if no puzzle is found after many attempts (which
+    /// happens when `difficulty` is too large for a small target, since a small
+    /// target can only be split into a few tiles).
+    pub fn random_puzzle(target: u32, difficulty: u32, seed: u64) -> Self {
+        assert!(
+            target.is_power_of_two() && (4..=1 << 15).contains(&target),
+            "puzzle target {target} is not a power of two in 4..=32768"
+        );
+        assert!(difficulty > 0, "puzzle difficulty must be positive");
+        let target_exp = target.trailing_zeros() as u8;
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 0..MAX_PUZZLE_ATTEMPTS {
+            let splits = rng.gen_range(1..=2 * difficulty);
+            let s = Self::split_tile(target_exp, splits, &mut rng);
+            if let Some(moves) = s.solve_to(target, difficulty) {
+                if moves.len() as u32 == difficulty {
+                    return s;
+                }
+            }
+        }
+        panic!("could not generate a puzzle for {target} in {difficulty} moves");
+    }
+
+    // a board with a single tile of exponent `exp`, split into halves `splits`
+    // times (or until there are no more tiles that can be split)
+    fn split_tile<Rn: Rng>(exp: u8, splits: u32, rng: &mut Rn) -> Self {
+        let mut cells = [0u8; 16];
+        cells[rng.gen_range(0..16)] = exp;
+        for _ in 0..splits {
+            // the smallest tile is a 2 (exponent 1), which can't be split
+            let splittable: Vec<usize> = (0..16).filter(|&i| cells[i] > 1).collect();
+            let empty: Vec<usize> = (0..16).filter(|&i| cells[i] == 0).collect();
+            let (Some(&i), Some(&j)) = (splittable.choose(rng), empty.choose(rng)) else {
+                break;
+            };
+            cells[i] -= 1;
+            cells[j] = cells[i];
+        }
+        let mut s = Self::default();
+        for (i, &x) in cells.iter().enumerate() {
+            s.add(i, x);
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Move, State};
+
+    #[test]
+    fn solve_one_move() {
+        let s = State::new([[5, 5, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        let moves = s.solve_to(64, 3).unwrap();
+        assert_eq!(1, moves.len());
+        assert!(moves == vec![Move::Left] || moves == vec![Move::Right]);
+        assert_eq!(Some(vec![]), s.solve_to(32, 3));
+    }
+
+    #[test]
+    fn solve_unreachable() {
+        let s = State::new([[5, 4, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]);
+        assert_eq!(None, s.solve_to(64, 5));
+    }
+
+    #[test]
+    fn puzzles_are_solvable() {
+        for target in [16, 256, 2048] {
+            for difficulty in 1..=4 {
+                for seed in 0..3 {
+                    let s = State::random_puzzle(target, difficulty, seed);
+                    assert!(s.highest_tile() < target);
+                    let moves = s
+                        .solve_to(target, difficulty)
+                        .unwrap_or_else(|| panic!("unsolvable puzzle\n{s}"));
+                    assert_eq!(difficulty as usize, moves.len(), "puzzle\n{s}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn puzzles_are_deterministic() {
+        assert_eq!(
+            State::random_puzzle(512, 3, 7),
+            State::random_puzzle(512, 3, 7)
+        );
+    }
+}
@@ -6,7 +6,10 @@
 //! further compacted to a single u16 with four bits per cell (note that this
 //! limits us to tiles worth 2^15 = 32,768) and then the left and right shift
 //! operations are fully pre-computed for all 2^16 possible rows.
-use std::fmt;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
 
 pub trait Row: Copy + Clone + PartialEq + Eq + Default {
     /// Shift the row's elements to the left and collapse tiles together.
@@ -23,10 +26,25 @@ pub trait Row: Copy + Clone + PartialEq + Eq + Default {
 
     /// Add a tile
     ///
-    /// Should only be used to add tiles to empty cells.
+    /// Panics if `i` is out of bounds, `x` is larger than 15, or the cell
+    /// isn't empty.
     fn add(&mut self, i: usize, x: u8);
 }
 
+// Shared checks for Row::add, so that every row reports bad input the same
+// way. `current` is the value in cell `i`, or None if `i` is out of bounds.
+#[inline]
+fn check_add(i: usize, x: u8, current: Option<u8>) {
+    let Some(current) = current else {
+        panic!("row index {i} is out of bounds (rows have 4 cells)");
+    };
+    assert!(x < 16, "exponent {x} is too large (the maximum is 15)");
+    assert!(
+        current == 0,
+        "cannot add to row cell {i}, which holds {current}"
+    );
+}
+
 /// ArrowRow implements rows with a fixed-size array of bytes and a fairly
 /// efficient shift algorithm.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -47,10 +65,10 @@ impl ArrayRow {
         let row = self.0;
         ArrayRow([row[3], row[2], row[1], row[0]])
     }
-}
 
-impl Row for ArrayRow {
-    fn shift_left(&self) -> Self {
+    /// Shift left, also returning the points scored (the value of every tile
+    /// created by a merge) and which cells hold a tile created by a merge.
+    fn shift_left_tracked(&self) -> (Self, u32, [bool; 4]) {
         // This is extremely performance-critical and is thus written imperatively
         // with no allocations.
         //
@@ -58,6 +76,9 @@ impl Row for ArrayRow {
         // the logic and indices until the tests passed (which compare against the
         // spec above).
         let mut els = self.0;
+        // points for every tile created by a merge
+        let mut score = 0;
+        let mut merged = [false; 4];
         // current index
         let mut i = 0;
         // next non-zero
@@ -76,6 +97,8 @@ impl Row for ArrayRow {
             if i > 0 && els[i] == els[i - 1] {
                 els[i - 1] += 1;
                 els[i] = 0;
+                score += 1 << els[i - 1];
+                merged[i - 1] = true;
                 // re-merge at same position
                 i -= 1;
             }
@@ -84,7 +107,18 @@ impl Row for ArrayRow {
             }
             i += 1;
         }
-        ArrayRow(els)
+        (ArrayRow(els), score, merged)
+    }
+
+    fn shift_right_tracked(&self) -> (Self, u32, [bool; 4]) {
+        let (r, score, [m0, m1, m2, m3]) = self.reverse().shift_left_tracked();
+        (r.reverse(), score, [m3, m2, m1, m0])
+    }
+}
+
+impl Row for ArrayRow {
+    fn shift_left(&self) -> Self {
+        self.shift_left_tracked().0
     }
 
     fn shift_right(&self) -> Self {
@@ -108,7 +142,7 @@ impl Row for ArrayRow {
     }
 
     fn add(&mut self, i: usize, x: u8) {
-        debug_assert_eq!(0, self.0[i]);
+        check_add(i, x, self.0.get(i).copied());
         self.0[i] = x;
     }
 }
@@ -119,6 +153,24 @@ mod tests {
 
     use super::{ArrayRow, Row};
 
+    #[test]
+    #[should_panic(expected = "row index 4 is out of bounds")]
+    fn add_out_of_bounds() {
+        ArrayRow::default().add(4, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "exponent 16 is too large")]
+    fn add_too_large() {
+        ArrayRow::default().add(0, 16);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot add to row cell 1")]
+    fn add_to_full_cell() {
+        ArrayRow([0, 2, 0, 0]).add(1, 1);
+    }
+
     prop_compose! {
         fn arb_array_row()(r0 in 0u8..16, r1 in 0u8..16, r2 in 0u8..16, r3 in 0u8..16) -> ArrayRow {
             ArrayRow([r0, r1, r2, r3])
@@ -132,6 +184,12 @@ mod tests {
 
         /// The logic that shift_left is supposed to implement.
         fn shift_left_spec(&self) -> Self {
+            self.shift_left_spec_scored().0
+        }
+
+        /// The spec for shift_left, along with the points the shift scores:
+        /// the value of each tile created by a merge.
+        pub(super) fn shift_left_spec_scored(&self) -> (Self, u32) {
             // move the non-zero elements to the front
             let mut els: Vec<u8> = self
                 .0
@@ -142,6 +200,7 @@ mod tests {
             while els.len() < 4 {
                 els.push(0);
             }
+            let mut score = 0;
             // look at i and i+1 in a sliding window
             let mut i = 0;
             while i + 1 < 4 {
@@ -149,6 +208,7 @@ mod tests {
                 if els[i] != 0 && els[i] == els[i + 1] {
                     // increment i (adding two equal powers of two means incrementing the exponent)
                     els[i] += 1;
+                    score += 1 << els[i];
                     // remove the extra copy and add a 0 to the end
                     els.remove(i + 1);
                     els.push(0);
@@ -161,7 +221,7 @@ mod tests {
             // turn els into an array
             let mut new_row = [0u8; 4];
             new_row.clone_from_slice(&els);
-            ArrayRow(new_row)
+            (ArrayRow(new_row), score)
         }
     }
 
@@ -229,6 +289,84 @@ mod tests {
         }
     }
 
+    // whether the tiles of a row are packed at its left end
+    fn left_packed(r: ArrayRow) -> bool {
+        r.0.windows(2).all(|w| w[0] != 0 || w[1] == 0)
+    }
+
+    // whether any two neighboring tiles of a row are equal
+    fn has_equal_neighbors(r: ArrayRow) -> bool {
+        r.0.windows(2).any(|w| w[0] != 0 && w[0] == w[1])
+    }
+
+    #[test]
+    fn shift_not_idempotent() {
+        // each merged tile only merges with the tile that arrives next, so the
+        // two 2s made from 1s are left next to each other
+        let r = ArrayRow([1, 1, 1, 1]).shift_left();
+        assert_eq!(ArrayRow([2, 2, 0, 0]), r);
+        assert_eq!(ArrayRow([3, 0, 0, 0]), r.shift_left());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_shift_left_packed(r in arb_array_row()) {
+            let shifted = r.shift_left();
+            assert!(left_packed(shifted), "{shifted:?}");
+            // shifting again only changes the row by merging equal neighbors
+            // (see shift_not_idempotent)
+            assert_eq!(
+                has_equal_neighbors(shifted),
+                shifted.shift_left() != shifted,
+                "{shifted:?}"
+            );
+        }
+
+        #[test]
+        fn prop_shift_right_packed(r in arb_array_row()) {
+            let shifted = r.shift_right();
+            assert!(left_packed(shifted.reverse()), "{shifted:?}");
+            assert_eq!(
+                has_equal_neighbors(shifted),
+                shifted.shift_right() != shifted,
+                "{shifted:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn shift_scores() {
+        for (score, r) in [
+            (0, ArrayRow([0, 1, 2, 3])),
+            (4, ArrayRow([0, 1, 0, 1])),
+            (8, ArrayRow([1, 1, 1, 1])),
+            // cascading merges score each merge
+            (4 + 8, ArrayRow([1, 1, 2, 2])),
+            (64, ArrayRow([4, 5, 0, 5])),
+        ] {
+            assert_eq!(score, r.shift_left_tracked().1, "{:?} scored wrong", r);
+            assert_eq!(r.shift_left(), r.shift_left_tracked().0);
+            assert_eq!(r.shift_right(), r.shift_right_tracked().0);
+        }
+    }
+
+    #[test]
+    fn merged_cells() {
+        for (merged, r) in [
+            ([false; 4], ArrayRow([0, 1, 2, 3])),
+            ([true, false, false, false], ArrayRow([0, 1, 0, 1])),
+            ([true, true, false, false], ArrayRow([1, 1, 1, 1])),
+            ([false, true, false, false], ArrayRow([4, 5, 0, 5])),
+            ([true, false, false, false], ArrayRow([1, 1, 2, 2])),
+        ] {
+            assert_eq!(merged, r.shift_left_tracked().2, "{:?} merges wrong", r);
+        }
+        assert_eq!(
+            [false, false, false, true],
+            ArrayRow([1, 0, 1, 0]).shift_right_tracked().2
+        );
+    }
+
     #[test]
     fn empty() {
         assert_eq!(vec![0, 1, 2, 3], ArrayRow([0, 0, 0, 0]).empty());
@@ -251,6 +389,14 @@ impl CachedRow {
         ((self.num >> (i * 4)) & 0xf) as U4
     }
 
+    // Row::add without the checks, for searches that only add 2s and 4s to
+    // cells they know are empty
+    #[inline]
+    pub(crate) fn add_unchecked(&mut self, i: usize, x: u8) {
+        debug_assert!(i < 4 && x < 16 && self.geti(i) == 0);
+        self.num |= (x as u16) << (4 * i);
+    }
+
     fn to_array(self) -> ArrayRow {
         ArrayRow([self.geti(0), self.geti(1), self.geti(2), self.geti(3)])
     }
@@ -262,6 +408,50 @@ impl CachedRow {
         Self { num }
     }
 
+    /// Points scored by shifting this row left (see [`State::merge_score`]).
+    ///
+    /// [`State::merge_score`]: super::State::merge_score
+    pub fn shift_left_score(&self) -> u32 {
+        CACHED_ROWS.shift_left_score[self.num as usize]
+    }
+
+    /// Points scored by shifting this row right.
+    pub fn shift_right_score(&self) -> u32 {
+        CACHED_ROWS.shift_right_score[self.num as usize]
+    }
+
+    /// Shift left, also returning the points scored.
+    pub fn shift_left_scored(&self) -> (Self, u32) {
+        (self.shift_left(), self.shift_left_score())
+    }
+
+    /// Shift right, also returning the points scored.
+    pub fn shift_right_scored(&self) -> (Self, u32) {
+        (self.shift_right(), self.shift_right_score())
+    }
+
+    /// Shift left, also returning which cells hold a tile created by a merge.
+    pub fn shift_left_merged(&self) -> (Self, [bool; 4]) {
+        let (r, _, merged) = self.to_array().shift_left_tracked();
+        (Self::from_array(r), merged)
+    }
+
+    /// Shift right, also returning which cells hold a tile created by a merge.
+    pub fn shift_right_merged(&self) -> (Self, [bool; 4]) {
+        let (r, _, merged) = self.to_array().shift_right_tracked();
+        (Self::from_array(r), merged)
+    }
+
+    /// The packed representation of this row, with four bits per cell.
+    pub fn to_bits(self) -> u16 {
+        self.num
+    }
+
+    /// The inverse of [`CachedRow::to_bits`]; every `u16` is a valid row.
+    pub fn from_bits(num: u16) -> Self {
+        Self { num }
+    }
+
     #[cfg(test)]
     pub fn from_arr(xs: [u8; 4]) -> Self {
         Self::from_array(ArrayRow::from_arr(xs))
@@ -275,35 +465,51 @@ impl fmt::Display for CachedRow {
     }
 }
 
-type CacheTable = Box<[CachedRow; 65536]>;
+type CacheTable<T> = Box<[T; 65536]>;
 
 struct CachedRowTable {
-    shift_left: CacheTable,
-    shift_right: CacheTable,
+    shift_left: CacheTable<CachedRow>,
+    shift_right: CacheTable<CachedRow>,
+    // points scored by each shift, in parallel with the shifted rows (as u32,
+    // since merging two 2^15 tiles scores 2^16)
+    shift_left_score: CacheTable<u32>,
+    shift_right_score: CacheTable<u32>,
 }
 
+#[cfg(feature = "std")]
 lazy_static! {
     static ref CACHED_ROWS: CachedRowTable = CachedRowTable::new();
 }
 
+// std's Once is noticeably faster to check on every lookup, so the spinning
+// version is only for no_std
+#[cfg(not(feature = "std"))]
+static CACHED_ROWS: spin::Lazy<CachedRowTable> = spin::Lazy::new(CachedRowTable::new);
+
 impl CachedRowTable {
-    fn vec_to_table(v: Vec<CachedRow>) -> CacheTable {
+    fn vec_to_table<T: fmt::Debug>(v: Vec<T>) -> CacheTable<T> {
         assert_eq!(65536, v.len(), "vector is not of cache length");
         v.into_boxed_slice().try_into().unwrap()
     }
     fn new() -> Self {
         let mut shift_left = vec![CachedRow::default(); 65536];
         let mut shift_right = vec![CachedRow::default(); 65536];
+        let mut shift_left_score = vec![0; 65536];
+        let mut shift_right_score = vec![0; 65536];
         for i in 0..65536 {
             let r = CachedRow { num: i as u16 }.to_array();
-            shift_left[i] = CachedRow::from_array(r.shift_left());
-            shift_right[i] = CachedRow::from_array(r.shift_right());
+            let (left, left_score, _) = r.shift_left_tracked();
+            let (right, right_score, _) = r.shift_right_tracked();
+            shift_left[i] = CachedRow::from_array(left);
+            shift_right[i] = CachedRow::from_array(right);
+            shift_left_score[i] = left_score;
+            shift_right_score[i] = right_score;
         }
-        let shift_left = Self::vec_to_table(shift_left);
-        let shift_right = Self::vec_to_table(shift_right);
         Self {
-            shift_left,
-            shift_right,
+            shift_left: Self::vec_to_table(shift_left),
+            shift_right: Self::vec_to_table(shift_right),
+            shift_left_score: Self::vec_to_table(shift_left_score),
+            shift_right_score: Self::vec_to_table(shift_right_score),
         }
     }
 }
@@ -333,8 +539,7 @@ impl Row for CachedRow {
     }
 
     fn add(&mut self, i: usize, x: u8) {
-        debug_assert!(x < 16, "{} will not fit in a CachedRow", x);
-        debug_assert!(i < 4, "add is out-of-bounds {}", i);
+        check_add(i, x, (i < 4).then(|| self.geti(i)));
         self.num |= (x as u16) << (4 * i);
     }
 }
@@ -344,6 +549,24 @@ pub(crate) mod cached_tests {
     use super::{ArrayRow, CachedRow, Row};
     use proptest::prelude::*;
 
+    #[test]
+    #[should_panic(expected = "row index 4 is out of bounds")]
+    fn add_out_of_bounds() {
+        CachedRow::default().add(4, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "exponent 16 is too large")]
+    fn add_too_large() {
+        CachedRow::default().add(0, 16);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot add to row cell 3")]
+    fn add_to_full_cell() {
+        CachedRow::from_arr([0, 0, 0, 2]).add(3, 1);
+    }
+
     prop_compose! {
         pub fn arb_cached_row()(num in 0u16..=65535) -> CachedRow {
             CachedRow {num}
@@ -363,6 +586,27 @@ pub(crate) mod cached_tests {
         }
     }
 
+    #[test]
+    fn all_scores() {
+        for i in 0..=65535 {
+            let r = CachedRow { num: i };
+            let (_, left_score) = r.to_array().shift_left_spec_scored();
+            let (_, right_score) = r.to_array().reverse().shift_left_spec_scored();
+            assert_eq!(left_score, r.shift_left_score(), "{r} scored wrong");
+            assert_eq!(right_score, r.shift_right_score(), "{r} scored wrong");
+            assert_eq!((r.shift_left(), left_score), r.shift_left_scored());
+            assert_eq!((r.shift_right(), right_score), r.shift_right_scored());
+        }
+        assert_eq!(4, CachedRow::from_arr([1, 1, 0, 0]).shift_left_score());
+        // two merges of 4s into 8s
+        assert_eq!(16, CachedRow::from_arr([2, 2, 2, 2]).shift_right_score());
+        // too much for a u16
+        assert_eq!(
+            1 << 16,
+            CachedRow::from_arr([15, 15, 0, 0]).shift_left_score()
+        );
+    }
+
     #[test]
     fn test_add_get() {
         let mut r = CachedRow::default();
@@ -376,7 +620,7 @@ pub(crate) mod cached_tests {
 
     #[test]
     fn compare_to_array_row() {
-        let rs: Vec<ArrayRow> = vec![
+        let rs: Vec<ArrayRow> = [
             [3, 4, 10, 0],
             [4, 3, 0, 0],
             [0, 0, 1, 0],
@@ -0,0 +1,240 @@
+//! Save a game in progress to a file and load it back.
+//!
+//! A saved game is a small JSON object:
+//!
+//! ```json
+//! {"format":"r2048-ai game","version":2,"board":"0000000000001021",
+//!  "moves":2,"score":4,"seed":"5f0e6a1c2b3d4e8f","draws":9,"spawn":"standard"}
+//! ```
+//!
+//! The board is written with [`State::to_compact_hex`] and the seed as a hex
+//! string, since JSON numbers can't hold every `u64` in most readers. `draws`
+//! counts the 32-bit words drawn from the RNG since it was seeded, so a loaded
+//! game picks up the random stream exactly where the saved one was.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::{CountingRng, Game, Spawn, State};
+
+const FORMAT: &str = "r2048-ai game";
+const VERSION: u32 = 2;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid saved game: {msg}"),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SavedSpawn {
+    Standard,
+    Progressive { threshold: u32, eight_prob: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SavedGame {
+    format: String,
+    version: u32,
+    board: String,
+    moves: u32,
+    score: u64,
+    seed: String,
+    draws: u64,
+    spawn: SavedSpawn,
+}
+
+impl<Rn: Rng + SeedableRng> Game<Rn> {
+    /// Serialize the game, or `None` if its RNG wasn't seeded.
+    fn save_string(&self) -> Option<String> {
+        let seed = self.seed?;
+        let saved = SavedGame {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            board: self.s.to_compact_hex(),
+            moves: self.moves,
+            score: self.score,
+            seed: format!("{seed:016x}"),
+            draws: self.rng.words,
+            spawn: match self.spawn {
+                Spawn::Standard => SavedSpawn::Standard,
+                Spawn::Progressive {
+                    threshold,
+                    eight_prob,
+                } => SavedSpawn::Progressive {
+                    threshold,
+                    eight_prob,
+                },
+            },
+        };
+        Some(serde_json::to_string(&saved).expect("a saved game is always valid JSON") + "\n")
+    }
+
+    fn from_save_string(text: &str) -> Result<Self, String> {
+        let saved: SavedGame = serde_json::from_str(text).map_err(|err| err.to_string())?;
+        if saved.format != FORMAT {
+            return Err("not a saved game".to_string());
+        }
+        if saved.version != VERSION {
+            return Err(format!("unsupported version {}", saved.version));
+        }
+        let board = &saved.board;
+        let s = State::from_compact_hex(board).ok_or_else(|| format!("bad board {board:?}"))?;
+        let seed = &saved.seed;
+        let seed = match seed.len() {
+            1..=16 => u64::from_str_radix(seed, 16).ok(),
+            _ => None,
+        }
+        .ok_or_else(|| format!("bad seed {seed:?}"))?;
+        let spawn = match saved.spawn {
+            SavedSpawn::Standard => Spawn::Standard,
+            SavedSpawn::Progressive {
+                threshold,
+                eight_prob,
+            } => {
+                if !(0.0..=1.0).contains(&eight_prob) {
+                    return Err(format!("\"eight_prob\" {eight_prob} is not a probability"));
+                }
+                Spawn::Progressive {
+                    threshold,
+                    eight_prob,
+                }
+            }
+        };
+        let mut rng = CountingRng::new(Rn::seed_from_u64(seed));
+        rng.skip(saved.draws);
+        Ok(Self {
+            rng,
+            seed: Some(seed),
+            spawn,
+            s,
+            moves: saved.moves,
+            score: saved.score,
+        })
+    }
+
+    /// Write the game to `path` so it can be continued with [`Game::load`],
+    /// which adds the same tiles from here on as this game will.
+    ///
+    /// Only a game started with [`Game::from_seed`] can be saved, since an
+    /// RNG's internal state can't be written out in general. The file is
+    /// replaced all at once, so an interrupted save leaves the last one intact.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = self.save_string().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only games started from a seed can be saved",
+            )
+        })?;
+        let mut tmp = OsString::from(path);
+        tmp.push(".tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Read a game written by [`Game::save`], failing with
+    /// [`io::ErrorKind::InvalidData`] if the file isn't a valid saved game.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::from_save_string(&text).map_err(invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::super::{Game, Spawn};
+    use crate::ai::rand_move;
+
+    fn play(game: &mut Game<StdRng>, moves: u32, rng: &mut StdRng) {
+        for _ in 0..moves {
+            let (m, next) = rand_move(game.state(), rng).expect("game ended early");
+            game.next_state(m, next);
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("r2048-save-{}.json", std::process::id()));
+        let mut game = Game::from_seed(3).with_spawn(Spawn::Progressive {
+            threshold: 4,
+            eight_prob: 0.1,
+        });
+        let mut moves_rng = StdRng::seed_from_u64(4);
+        play(&mut game, 20, &mut moves_rng);
+        game.save(&path).unwrap();
+        let mut loaded: Game<StdRng> = Game::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(game.state(), loaded.state());
+        assert_eq!(20, loaded.moves());
+        assert_eq!(game.score(), loaded.score());
+        assert_eq!(game.spawn, loaded.spawn);
+
+        // both continue with the same tiles, and saving didn't change the game
+        let mut unsaved = Game::from_seed(3).with_spawn(game.spawn);
+        play(&mut unsaved, 20, &mut StdRng::seed_from_u64(4));
+        play(&mut unsaved, 10, &mut moves_rng.clone());
+        play(&mut game, 10, &mut moves_rng.clone());
+        play(&mut loaded, 10, &mut moves_rng);
+        assert_eq!(game.state(), loaded.state());
+        assert_eq!(unsaved.state(), game.state());
+    }
+
+    #[test]
+    fn unseeded_games_are_not_saved() {
+        let path = std::env::temp_dir().join(format!("r2048-unseeded-{}.json", std::process::id()));
+        let game = Game::from_rng(StdRng::seed_from_u64(3));
+        let err = game.save(&path).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn corrupt_files() {
+        let game = Game::<StdRng>::from_seed(3);
+        let saved = game.save_string().unwrap();
+        assert!(Game::<StdRng>::from_save_string(&saved).is_ok());
+        for end in [0, 1, saved.len() / 2, saved.len() - 3] {
+            assert!(
+                Game::<StdRng>::from_save_string(&saved[..end]).is_err(),
+                "{:?} should be invalid",
+                &saved[..end]
+            );
+        }
+        for (from, to) in [
+            ("\"version\":2", "\"version\":1"),
+            ("\"version\":2", "\"version\":2.5"),
+            ("\"draws\":", "\"drawn\":"),
+            ("\"moves\":0", "\"moves\":-1"),
+            ("\"moves\":0", "\"moves\":0.5"),
+            ("\"score\":0", "\"score\":\"0\""),
+            ("\"spawn\":\"standard\"", "\"spawn\":\"fast\""),
+            ("r2048-ai game", "r2048-ai replay"),
+        ] {
+            let changed = saved.replace(from, to);
+            assert_ne!(saved, changed);
+            assert!(
+                Game::<StdRng>::from_save_string(&changed).is_err(),
+                "{changed} should be invalid"
+            );
+        }
+
+        let path = std::env::temp_dir().join(format!("r2048-corrupt-{}.json", std::process::id()));
+        std::fs::write(&path, &saved[..10]).unwrap();
+        let err = Game::<StdRng>::load(&path).map(|_| ()).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert!(err.to_string().starts_with("invalid saved game"), "{err}");
+    }
+}
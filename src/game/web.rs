@@ -0,0 +1,160 @@
+//! Import boards saved by web versions of the game.
+
+use serde_json::Value;
+
+use super::{Error, State, MAX_EXPONENT};
+
+// the tile values in a saved board, in linear order (null for empty cells)
+fn tile_values(json: &Value) -> Result<Vec<&Value>, Error> {
+    // the original game saves {"grid": {"size": 4, "cells": [...]}}, where
+    // cells are listed column by column and each is null or an object with a
+    // "value"
+    if let Some(cells) = json.get("grid").and_then(|grid| grid.get("cells")) {
+        let columns = cells.as_array().ok_or(Error::NotABoard)?;
+        let mut values = vec![&Value::Null; 16];
+        if columns.len() != 4 {
+            return Err(Error::NotABoard);
+        }
+        for (x, column) in columns.iter().enumerate() {
+            match column.as_array() {
+                Some(column) if column.len() == 4 => {
+                    for (y, cell) in column.iter().enumerate() {
+                        values[y * 4 + x] = match cell {
+                            Value::Null => cell,
+                            _ => cell.get("value").ok_or(Error::NotABoard)?,
+                        };
+                    }
+                }
+                _ => return Err(Error::NotABoard),
+            }
+        }
+        return Ok(values);
+    }
+    let items = json.as_array().ok_or(Error::NotABoard)?;
+    if items.len() == 16 {
+        return Ok(items.iter().collect());
+    }
+    if items.len() != 4 {
+        return Err(Error::NotABoard);
+    }
+    let mut values = Vec::new();
+    for row in items {
+        match row.as_array() {
+            Some(row) if row.len() == 4 => values.extend(row),
+            _ => return Err(Error::NotABoard),
+        }
+    }
+    Ok(values)
+}
+
+impl State {
+    /// Parse a board saved as JSON by a web version of the game.
+    ///
+    /// This accepts a grid of tile values (an array of four rows, or a flat
+    /// array of 16 tiles in linear order) with `null` or 0 for empty cells, as
+    /// well as the original game's saved state, which has the grid under
+    /// `"grid"` and `"cells"`.
+    pub fn from_web_json(s: &str) -> Result<Self, Error> {
+        let json: Value = serde_json::from_str(s).map_err(|err| Error::InvalidJson {
+            line: err.line(),
+            column: err.column(),
+        })?;
+        let values = tile_values(&json)?;
+        let mut exponents = [0; 16];
+        for (index, value) in values.into_iter().enumerate() {
+            let value = match value {
+                Value::Null => 0.0,
+                Value::Number(x) => x.as_f64().ok_or(Error::NotABoard)?,
+                _ => return Err(Error::NotABoard),
+            };
+            if value == 0.0 {
+                continue;
+            }
+            let exponent = value.log2();
+            let is_tile = value.fract() == 0.0 && exponent.fract() == 0.0 && exponent >= 1.0;
+            if !is_tile || exponent > MAX_EXPONENT as f64 {
+                return Err(Error::InvalidTile {
+                    index,
+                    value_bits: value.to_bits(),
+                });
+            }
+            exponents[index] = exponent as u8;
+        }
+        Self::from_exponents(&exponents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Error, State};
+
+    #[test]
+    fn grid() {
+        let s = State::from_web_json("[[2, 0, null, 4], [0, 8, 0, 0], [], [0, 0, 0, 2048]]");
+        assert_eq!(Err(Error::NotABoard), s);
+        let s = State::from_web_json(
+            "[[2, 0, null, 4],\n [0, 8, 0, 0],\n [0, 0, 0, 0],\n [0, 0, 0, 2048]]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            State::new([[1, 0, 0, 2], [0, 3, 0, 0], [0, 0, 0, 0], [0, 0, 0, 11]]),
+            s
+        );
+        let flat = State::from_web_json("[2,0,0,4,0,8,0,0,0,0,0,0,0,0,0,2048]").unwrap();
+        assert_eq!(s, flat);
+    }
+
+    #[test]
+    fn original_game_state() {
+        let cell = |x, y, value| format!(r#"{{"position":{{"x":{x},"y":{y}}},"value":{value}}}"#);
+        let saved = format!(
+            r#"{{"grid":{{"size":4,"cells":[[{},null,null,null],[null,null,null,null],[null,{},null,null],[null,null,null,{}]]}},"score":12,"over":false,"won":false,"keepPlaying":false}}"#,
+            cell(0, 0, 2),
+            cell(2, 1, 4),
+            cell(3, 3, 8),
+        );
+        assert_eq!(
+            State::new([[1, 0, 0, 0], [0, 0, 2, 0], [0, 0, 0, 0], [0, 0, 0, 3]]),
+            State::from_web_json(&saved).unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let invalid_tile = |json: &str| match State::from_web_json(json) {
+            Err(Error::InvalidTile { index, value_bits }) => (index, f64::from_bits(value_bits)),
+            other => panic!("{json}: expected an invalid tile, got {other:?}"),
+        };
+        assert_eq!((1, 3.0), invalid_tile("[2,3,0,0,0,0,0,0,0,0,0,0,0,0,0,0]"));
+        assert_eq!((0, 1.0), invalid_tile("[1,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0]"));
+        assert_eq!(
+            (2, 2.5),
+            invalid_tile("[0,0,2.5,0,0,0,0,0,0,0,0,0,0,0,0,0]")
+        );
+        assert_eq!(
+            (0, 65536.0),
+            invalid_tile("[65536,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0]")
+        );
+        for json in ["", "[2, 4", "[2, 4] x"] {
+            assert!(
+                matches!(State::from_web_json(json), Err(Error::InvalidJson { .. })),
+                "{json:?} should be invalid"
+            );
+        }
+        assert_eq!(
+            Err(Error::InvalidJson { line: 2, column: 4 }),
+            State::from_web_json("[2,\n 4 x]")
+        );
+        for json in [
+            "[[2, 4], [8]]",
+            r#"{"board": []}"#,
+            r#"["2",0,0,0,0,0,0,0,0,0,0,0,0,0,0,0]"#,
+        ] {
+            assert_eq!(
+                Err(Error::NotABoard),
+                State::from_web_json(json),
+                "{json:?} should be invalid"
+            );
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Play the game yourself, moving with the arrow keys or WASD.
+//!
+//! Without the `interactive` feature the terminal stays line-buffered, so
+//! each key has to be followed by Enter. With it (on Unix), the terminal is
+//! put in non-canonical mode while playing so every key press moves at once.
+use std::io::{self, Read};
+
+use crate::{print_state, Config, Move};
+
+/// What a key press asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Move(Move),
+    Quit,
+}
+
+/// Decode the keys in `input`, skipping anything that isn't a move or a way
+/// to quit (q, Escape or Ctrl-C).
+pub fn parse_keys(input: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < input.len() {
+        // arrow keys send ESC [ A through ESC [ D
+        if input[i..].starts_with(b"\x1b[") && i + 2 < input.len() {
+            let m = match input[i + 2] {
+                b'A' => Some(Move::Up),
+                b'B' => Some(Move::Down),
+                b'C' => Some(Move::Right),
+                b'D' => Some(Move::Left),
+                _ => None,
+            };
+            keys.extend(m.map(Key::Move));
+            i += 3;
+            continue;
+        }
+        let key = match input[i].to_ascii_lowercase() {
+            b'w' => Some(Key::Move(Move::Up)),
+            b'a' => Some(Key::Move(Move::Left)),
+            b's' => Some(Key::Move(Move::Down)),
+            b'd' => Some(Key::Move(Move::Right)),
+            b'q' | b'\x1b' | b'\x03' => Some(Key::Quit),
+            _ => None,
+        };
+        keys.extend(key);
+        i += 1;
+    }
+    keys
+}
+
+#[cfg(all(unix, feature = "interactive"))]
+mod terminal {
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+
+    use nix::sys::termios::{
+        tcgetattr, tcsetattr, LocalFlags, SetArg, SpecialCharacterIndices, Termios,
+    };
+
+    /// Reads from stdin return after every key press (without echoing it)
+    /// until this is dropped, which restores the original settings.
+    pub struct KeyMode(Termios);
+
+    impl KeyMode {
+        pub fn enable() -> io::Result<Self> {
+            let fd = io::stdin().as_raw_fd();
+            let original = tcgetattr(fd)?;
+            let mut keys = original.clone();
+            keys.local_flags
+                .remove(LocalFlags::ICANON | LocalFlags::ECHO | LocalFlags::ISIG);
+            keys.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+            keys.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+            tcsetattr(fd, SetArg::TCSANOW, &keys)?;
+            Ok(Self(original))
+        }
+    }
+
+    impl Drop for KeyMode {
+        fn drop(&mut self) {
+            _ = tcsetattr(io::stdin().as_raw_fd(), SetArg::TCSANOW, &self.0);
+        }
+    }
+}
+
+impl Config {
+    /// Play a game from the keyboard until no moves are left or the player
+    /// quits, returning whether the target was reached.
+    ///
+    /// Moves that don't change the board are ignored.
+    pub fn run_interactive(&self) -> io::Result<bool> {
+        // stdin might not be a terminal, in which case it's read as is
+        #[cfg(all(unix, feature = "interactive"))]
+        let _key_mode = terminal::KeyMode::enable().ok();
+        let mut game = Self::new_game(self.seed);
+        let mut stdin = io::stdin().lock();
+        let mut input = [0; 64];
+        loop {
+            _ = clearscreen::clear();
+            println!("  {:>4} moves", game.moves());
+            print_state(game.state(), self.style);
+            if let Some(target) = self.target_score {
+                if self.won(&game) {
+                    println!("reached {target}!");
+                }
+            }
+            if game.is_over() {
+                println!("game over");
+                return Ok(self.won(&game));
+            }
+            println!("move with the arrow keys or WASD, q to quit");
+            let n = stdin.read(&mut input)?;
+            if n == 0 {
+                return Ok(self.won(&game));
+            }
+            for key in parse_keys(&input[..n]) {
+                match key {
+                    Key::Quit => return Ok(self.won(&game)),
+                    Key::Move(m) => {
+                        if let Some(next) = game.state().apply_move(m) {
+                            game.next_state(m, next);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_keys, Key};
+    use crate::Move;
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            vec![
+                Key::Move(Move::Up),
+                Key::Move(Move::Left),
+                Key::Move(Move::Down),
+                Key::Move(Move::Right),
+            ],
+            parse_keys(b"wAs\nD")
+        );
+        assert_eq!(
+            vec![
+                Key::Move(Move::Up),
+                Key::Move(Move::Down),
+                Key::Move(Move::Right),
+                Key::Move(Move::Left),
+            ],
+            parse_keys(b"\x1b[A\x1b[B\x1b[C\x1b[D")
+        );
+        assert_eq!(vec![Key::Quit], parse_keys(b"x\x1b"));
+        assert_eq!(vec![Key::Move(Move::Left), Key::Quit], parse_keys(b"aq"));
+    }
+}
@@ -1,148 +1,47 @@
 #![allow(clippy::needless_return)]
-use std::{io, time::Instant};
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use ai::{expectimax_sum_move, expectimax_weight_move, rand_move, smart_depth};
-use game::{Move, State};
-use rand::{prelude::ThreadRng, Rng};
-use std::io::Write;
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+extern crate alloc;
 
-use crate::game::Game;
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+compile_error!("either the std or the alloc feature must be enabled");
 
 #[macro_use]
 extern crate static_assertions;
 
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate lazy_static;
 
 pub mod ai;
+#[cfg(feature = "std")]
+pub mod background;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod dashboard;
 pub mod game;
-
-fn gray_write<S: AsRef<str>>(stream: &mut StandardStream, s: S) -> io::Result<()> {
-    _ = stream.set_color(ColorSpec::new().set_fg(Some(Color::Rgb(200, 200, 200))));
-    write!(stream, "{}", s.as_ref())?;
-    stream.reset()?;
-    Ok(())
-}
-
-// convenience that cleans up the code
-fn gray_writeln<S: AsRef<str>>(stream: &mut StandardStream, s: S) -> io::Result<()> {
-    gray_write(stream, s)?;
-    writeln!(stream)?;
-    Ok(())
-}
-
-fn write_state(s: &State, stream: &mut StandardStream) -> io::Result<()> {
-    let sep = format!("+{bar}+{bar}+{bar}+{bar}+", bar = "------");
-    gray_writeln(stream, &sep)?;
-    for i in 0..4 {
-        gray_write(stream, "|")?;
-        for j in 0..4 {
-            let tile = s.tile(i * 4 + j);
-            if tile == 1 {
-                write!(stream, "      ")?;
-                stream.reset()?;
-            } else {
-                write!(stream, "{:>5} ", tile)?;
-            }
-            gray_write(stream, "|")?;
-        }
-        writeln!(stream)?;
-        gray_writeln(stream, &sep)?;
-    }
-    Ok(())
-}
-
-fn print_state(s: &State) {
-    write_state(s, &mut StandardStream::stdout(ColorChoice::Always))
-        .expect("could not print colored state");
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum Depth {
-    Smart,
-    Fixed(u32),
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum Algorithm {
-    ExpectimaxSum(Depth),
-    ExpectimaxWeight(Depth),
-    Random,
-}
-
-#[derive(Clone, Copy, Debug)]
-pub struct Config {
-    pub algorithm: Algorithm,
-    pub target_score: Option<u32>,
-    pub print: bool,
-}
-
-impl Config {
-    fn choose_depth(d: Depth, s: &State) -> u32 {
-        match d {
-            Depth::Smart => smart_depth(s),
-            Depth::Fixed(d) => d,
-        }
-    }
-
-    fn next_move(&self, s: &State) -> Option<(Move, State)> {
-        match self.algorithm {
-            Algorithm::ExpectimaxSum(d) => expectimax_sum_move(s, Self::choose_depth(d, s)),
-            Algorithm::ExpectimaxWeight(d) => expectimax_weight_move(s, Self::choose_depth(d, s)),
-            Algorithm::Random => rand_move(s, &mut ThreadRng::default()),
-        }
-    }
-
-    /// Run runs the game and returns a score and whether or not this is a win.
-    pub fn run(&self) -> bool {
-        let mut mgr = Game::new();
-        if self.print {
-            print_state(mgr.state());
-        }
-        let start = Instant::now();
-        // current estimate
-        let mut moves_per_s = 0.0;
-        while let Some((_, s)) = self.next_move(mgr.state()) {
-            mgr.next_state(s);
-
-            let moves = mgr.moves();
-            // generate an estimate early on, and then periodically
-            if moves == 10 || moves % 50 == 0 {
-                let elapsed_s = start.elapsed().as_secs_f64();
-                moves_per_s = moves as f64 / elapsed_s;
-            }
-            if self.print {
-                _ = clearscreen::clear();
-                println!("  {:>4} {:0.0} moves/s", moves, moves_per_s);
-                print_state(mgr.state());
-            }
-            if let Some(target) = self.target_score {
-                if mgr.state().highest_tile() == target {
-                    break;
-                }
-            }
-        }
-        // if not printing intermediate state, show the final board
-        if !self.print {
-            print_state(mgr.state());
-        }
-        let score = mgr.state().highest_tile();
-        let elapsed_s = start.elapsed().as_secs_f64();
-        let moves_per_s = mgr.moves() as f64 / elapsed_s;
-        println!("score: {score}");
-        println!(
-            "{moves} moves in {elapsed_s:0.0}s ({moves_per_s:0.0} moves/s)",
-            moves = mgr.moves(),
-            moves_per_s = moves_per_s,
-        );
-        return self.won(&mgr);
-    }
-
-    pub fn won<Rn: Rng>(&self, g: &Game<Rn>) -> bool {
-        match self.target_score {
-            Some(target) => g.state().highest_tile() >= target,
-            None => true,
-        }
-    }
+#[cfg(feature = "std")]
+pub mod interactive;
+#[cfg(feature = "std")]
+pub mod mcts;
+#[cfg(feature = "std")]
+mod play;
+#[cfg(feature = "std")]
+pub mod render;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use game::{Move, State};
+#[cfg(feature = "std")]
+pub use play::*;
+
+// HashMap and HashSet from std, or from hashbrown without it
+mod hash {
+    #[cfg(not(feature = "std"))]
+    pub(crate) use hashbrown::{HashMap, HashSet};
+    #[cfg(feature = "std")]
+    pub(crate) use std::collections::{HashMap, HashSet};
 }
@@ -1,7 +1,17 @@
-use clap::Parser;
+use clap::{CommandFactory, ErrorKind, Parser};
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
 use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 
-use r2048_ai::{Algorithm, Config, Depth};
+use r2048_ai::ai::{self, MoveFilter, TieBreak, WeightConfig};
+use r2048_ai::game::Game;
+use r2048_ai::mcts::Mcts;
+use r2048_ai::replay::{show_replay, Replay};
+use r2048_ai::{Agent, Algorithm, Config, Depth, StallLimit, Style, Theme};
+use termcolor::ColorChoice;
 
 #[derive(Parser, Debug)]
 struct Args {
@@ -11,6 +21,10 @@ struct Args {
     #[clap(short, long)]
     depth: Option<u32>,
 
+    /// Search with iterative deepening for this many milliseconds per move.
+    #[clap(long, conflicts_with = "depth")]
+    time_ms: Option<u64>,
+
     #[clap(short, long, default_value_t = 2048)]
     score: u32,
 
@@ -19,20 +33,209 @@ struct Args {
 
     #[clap(short, long)]
     no_print: bool,
+
+    /// Stop if the score improves by less than --stall-gain over this many moves.
+    #[clap(long)]
+    stall_moves: Option<u32>,
+
+    #[clap(long, default_value_t = 1, requires = "stall-moves")]
+    stall_gain: u64,
+
+    /// Show the line of play the AI expects, this many moves long.
+    #[clap(long, default_value_t = 0)]
+    pv: u32,
+
+    /// Don't search moves that take the highest tile out of its corner.
+    #[clap(long)]
+    keep_corner: bool,
+
+    /// Score boards with a named heuristic (corner, snake or balanced) instead
+    /// of --algorithm.
+    #[clap(long)]
+    heuristic: Option<String>,
+
+    /// Read the weight matrix for --algorithm weight from this file, as 16
+    /// numbers separated by whitespace or commas.
+    #[clap(long)]
+    weights: Option<String>,
+
+    /// Add this much to the weight score for each empty cell.
+    #[clap(long, default_value_t = 0.0)]
+    empty_weight: f64,
+
+    /// Play to keep moves available once the board is nearly full.
+    #[clap(long)]
+    survival: bool,
+
+    /// How to choose between equally scored moves: in the order left, right,
+    /// up, down, the one leaving the most empty cells, or at random.
+    #[clap(long, default_value = "order", possible_values = &["order", "empties", "random"])]
+    tie_break: String,
+
+    /// The UCT exploration constant for --algorithm uct.
+    #[clap(long, default_value_t = std::f64::consts::SQRT_2)]
+    exploration: f64,
+
+    /// For --algorithm mcts, the number of random games played after each
+    /// move (default 20); for --algorithm uct, the total number of rollouts
+    /// (default 200).
+    #[clap(long)]
+    rollouts: Option<usize>,
+
+    /// For --algorithm sampled, the number of random tiles each chance node
+    /// averages over.
+    #[clap(long, default_value_t = 8)]
+    samples: usize,
+
+    /// Seed the random number generator, to replay the same game.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Play --games games without printing and write their results as CSV to
+    /// this file ("-" for stdout).
+    #[clap(long)]
+    csv: Option<String>,
+
+    /// Play this many games. Without --csv, the games are played without
+    /// printing and a summary of how they went is printed at the end.
+    #[clap(long, default_value_t = 1, conflicts_with_all = &["save", "resume", "record"])]
+    games: u32,
+
+    /// Play games until interrupted, showing the win rate over this many
+    /// recent games.
+    #[clap(long, conflicts_with = "csv")]
+    dashboard: Option<usize>,
+
+    /// Save the game to this file after every move.
+    #[clap(long, conflicts_with_all = &["csv", "dashboard"])]
+    save: Option<PathBuf>,
+
+    /// Continue the game saved in this file (see --save).
+    #[clap(long, conflicts_with_all = &["csv", "dashboard", "seed"])]
+    resume: Option<PathBuf>,
+
+    /// Log every move to this file, to watch it later with --replay.
+    #[clap(long, conflicts_with_all = &["csv", "dashboard"])]
+    record: Option<PathBuf>,
+
+    /// Play back a game logged with --record instead of playing one.
+    #[clap(long)]
+    replay: Option<PathBuf>,
+
+    /// How long to show each move for with --replay, in milliseconds.
+    #[clap(long, default_value_t = 200)]
+    replay_delay_ms: u64,
+
+    /// Play the game yourself with the arrow keys or WASD.
+    #[clap(long, conflicts_with_all = &["csv", "dashboard", "save", "resume"])]
+    interactive: bool,
+
+    /// Print every board after the last instead of redrawing the screen, to
+    /// keep the whole game in the scrollback.
+    #[clap(long)]
+    no_clear: bool,
+
+    /// Show progress toward the target score and the time elapsed while
+    /// playing, or the number of games finished in a batch.
+    #[clap(long)]
+    progress: bool,
+
+    /// Draw boards without color, even on a terminal.
+    #[clap(long)]
+    no_color: bool,
+
+    /// Colors for the board: "plain" for a gray border, or "warm" to also
+    /// color tiles from cool to warm as they grow.
+    #[clap(long, default_value = "plain", possible_values = &["plain", "warm"])]
+    theme: String,
+
+    /// How to show the game: "text" to draw the board, "json" to play
+    /// without printing and then write a summary as a JSON object, or
+    /// "histogram" to play --games games and chart their highest tiles.
+    #[clap(long, default_value = "text", possible_values = &["text", "json", "histogram"])]
+    format: String,
 }
 
 fn main() {
     let args = Args::parse();
-    let depth = match args.depth {
-        Option::Some(d) => Depth::Fixed(d),
-        Option::None => Depth::Smart,
+    // clap can make arguments conflict, but not with one value of another
+    if args.format == "json" {
+        let given = [
+            ("--csv", args.csv.is_some()),
+            ("--dashboard", args.dashboard.is_some()),
+            ("--interactive", args.interactive),
+            ("--games", args.games != 1),
+            ("--save", args.save.is_some()),
+            ("--resume", args.resume.is_some()),
+            ("--record", args.record.is_some()),
+        ];
+        if let Some((name, _)) = given.iter().find(|&&(_, given)| given) {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!("--format json can't be used with {name}"),
+                )
+                .exit();
+        }
+    }
+    let style = Style {
+        color: if args.no_color {
+            ColorChoice::Never
+        } else {
+            ColorChoice::Auto
+        },
+        theme: match args.theme.as_str() {
+            "warm" => Theme::Warm,
+            _ => Theme::Plain,
+        },
+    };
+    if let Some(path) = &args.replay {
+        let log = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| Replay::parse(&text).map_err(|err| err.to_string()))
+            .unwrap_or_else(|err| {
+                eprintln!("could not read {}: {err}", path.display());
+                process::exit(1);
+            });
+        if let Err(err) = show_replay(&log, Duration::from_millis(args.replay_delay_ms), style) {
+            eprintln!("replay diverged: {err}");
+            process::exit(1);
+        }
+        return;
+    }
+    let depth = match (args.depth, args.time_ms) {
+        (Option::Some(d), _) => Depth::Fixed(d),
+        (_, Option::Some(ms)) => Depth::Timed(Duration::from_millis(ms)),
+        (Option::None, Option::None) => Depth::Smart,
     };
+    let mut agent: Option<Arc<dyn Agent>> = None;
     let algorithm = if args.algorithm == "weight" {
         Algorithm::ExpectimaxWeight(depth)
     } else if args.algorithm == "sum" {
         Algorithm::ExpectimaxSum(depth)
+    } else if args.algorithm == "monotonic" {
+        Algorithm::ExpectimaxMonotonic(depth)
+    } else if args.algorithm == "worst" {
+        Algorithm::MinimaxWorst(depth)
     } else if args.algorithm == "random" {
         Algorithm::Random
+    } else if args.algorithm == "sampled" {
+        Algorithm::ExpectimaxSampled {
+            depth: args.depth.unwrap_or(2),
+            samples: args.samples,
+        }
+    } else if args.algorithm == "mcts" {
+        Algorithm::Mcts {
+            iterations: args.rollouts.unwrap_or(20),
+        }
+    } else if args.algorithm == "uct" {
+        let default = Mcts::default();
+        agent = Some(Arc::new(Mcts {
+            exploration: args.exploration,
+            rollouts: args.rollouts.map_or(default.rollouts, |n| n as u32),
+            ..default
+        }));
+        Algorithm::Agent
     } else {
         eprintln!("unknown algorithm {}", args.algorithm);
         process::exit(1);
@@ -43,13 +246,113 @@ fn main() {
         Some(args.score)
     };
     let print = !args.no_print;
+    let stall = args.stall_moves.map(|moves| StallLimit {
+        moves,
+        min_gain: args.stall_gain,
+    });
 
-    let config = Config {
+    let weights = args.weights.as_ref().map(|path| {
+        WeightConfig::load(path).unwrap_or_else(|err| {
+            eprintln!("could not read weights from {path}: {err}");
+            process::exit(1);
+        })
+    });
+
+    let mut config = Config {
         algorithm,
+        agent,
         target_score,
         print,
+        style,
+        clear: !args.no_clear,
+        progress: args.progress,
+        stall,
+        record_history: false,
+        pv_plies: args.pv,
+        prefilter: args.keep_corner.then_some(ai::keeps_corner as MoveFilter),
+        weights,
+        empty_weight: args.empty_weight,
+        survival: args.survival,
+        tie_break: match args.tie_break.as_str() {
+            "empties" => TieBreak::PreferMoreEmpties,
+            "random" => TieBreak::Random,
+            _ => TieBreak::PreferOrder,
+        },
+        seed: args.seed,
+    };
+    if let Some(name) = &args.heuristic {
+        config = config.with_preset(name).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1);
+        });
+    }
+    // survival play needs a score for boards
+    if config.survival
+        && matches!(
+            config.algorithm,
+            Algorithm::Random | Algorithm::Mcts { .. } | Algorithm::Agent
+        )
+    {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "--survival can't be used with --algorithm {}",
+                    args.algorithm
+                ),
+            )
+            .exit();
+    }
+    if let Some(window) = args.dashboard {
+        config.run_dashboard(window);
+    }
+    if let Some(path) = &args.csv {
+        let result = if path == "-" {
+            config.run_batch_csv(args.games, io::stdout().lock())
+        } else {
+            File::create(path).and_then(|f| config.run_batch_csv(args.games, BufWriter::new(f)))
+        };
+        if let Err(err) = result {
+            eprintln!("could not write {path}: {err}");
+            process::exit(1);
+        }
+        return;
+    }
+    if args.interactive {
+        if let Err(err) = config.run_interactive() {
+            eprintln!("could not read keys: {err}");
+            process::exit(1);
+        }
+        return;
+    }
+    if args.format == "histogram" {
+        print!(
+            "{}",
+            config.run_batch(args.games as usize).render_histogram(50)
+        );
+        return;
+    }
+    if args.games > 1 {
+        print!("{}", config.run_batch(args.games as usize).render());
+        return;
+    }
+    if args.format == "json" {
+        config.print = false;
+        let result = config.run_headless();
+        println!("{}", result.to_json(&args.algorithm));
+        if !result.won {
+            process::exit(1);
+        }
+        return;
+    }
+    let game = match &args.resume {
+        Some(path) => Game::load(path).unwrap_or_else(|err| {
+            eprintln!("could not resume from {}: {err}", path.display());
+            process::exit(1);
+        }),
+        None => Config::new_game(args.seed),
     };
-    let win = config.run();
+    let win = config.run_game(game, args.save.as_deref(), args.record.as_deref());
     if !win {
         eprintln!("failed to get to {}", config.target_score.unwrap());
         process::exit(1);
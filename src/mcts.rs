@@ -0,0 +1,257 @@
+//! Monte Carlo search: choose a move by playing out many random games.
+//!
+//! [`Mcts`] spreads rollouts over the legal moves at the root with the UCT rule
+//! (upper confidence bounds applied to trees), which balances playing more
+//! rollouts from the moves that look best so far against trying the others.
+//! [`mcts_move`] is the simpler flat search, which plays the same number of
+//! complete games after every move.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::ai::rand_move;
+use crate::game::{Move, State};
+use crate::Agent;
+
+/// An [`Agent`] that picks moves by random rollouts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mcts {
+    /// Total number of rollouts to play for each move.
+    pub rollouts: u32,
+    /// Rollouts stop after this many moves even if the game isn't over.
+    pub rollout_moves: u32,
+    /// The UCT exploration constant: 0 always plays rollouts from the move
+    /// with the best mean so far, and larger values spread rollouts more
+    /// evenly.
+    pub exploration: f64,
+    /// Rollouts from a board are seeded by this and the board, so a search is
+    /// reproducible.
+    pub seed: u64,
+}
+
+impl Default for Mcts {
+    fn default() -> Self {
+        Self {
+            rollouts: 200,
+            rollout_moves: 50,
+            exploration: std::f64::consts::SQRT_2,
+            seed: 0,
+        }
+    }
+}
+
+/// Rollout statistics for one move at the root of the search.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MoveStats {
+    pub m: Move,
+    pub next: State,
+    pub visits: u32,
+    /// Mean reward, the fraction of `rollout_moves` that the rollouts lasted.
+    pub mean: f64,
+}
+
+impl Mcts {
+    /// Play random moves from `s` (after adding a tile) and return the
+    /// fraction of `rollout_moves` survived.
+    fn rollout(&self, s: &State, rng: &mut StdRng) -> f64 {
+        let mut s = *s;
+        s.rand_add(rng);
+        let mut moves = 0;
+        while moves < self.rollout_moves {
+            let Some((_, next)) = rand_move(&s, rng) else {
+                break;
+            };
+            s = next;
+            s.rand_add(rng);
+            moves += 1;
+        }
+        moves as f64 / self.rollout_moves as f64
+    }
+
+    // the move UCT would play the next rollout from
+    fn select(&self, stats: &[MoveStats], total_visits: u32) -> usize {
+        if let Some(i) = stats.iter().position(|st| st.visits == 0) {
+            return i;
+        }
+        let ln_total = (total_visits as f64).ln();
+        let uct =
+            |st: &MoveStats| st.mean + self.exploration * (ln_total / st.visits as f64).sqrt();
+        let mut best = 0;
+        for i in 1..stats.len() {
+            if uct(&stats[i]) > uct(&stats[best]) {
+                best = i;
+            }
+        }
+        best
+    }
+
+    /// Run the search from `s`, returning statistics for every legal move in
+    /// the order of [`State::legal_moves`].
+    pub fn search(&self, s: &State) -> Vec<MoveStats> {
+        let mut stats: Vec<MoveStats> = s
+            .legal_moves()
+            .into_iter()
+            .map(|(m, next)| MoveStats {
+                m,
+                next,
+                visits: 0,
+                mean: 0.0,
+            })
+            .collect();
+        if stats.is_empty() {
+            return stats;
+        }
+        let mut rng = StdRng::seed_from_u64(self.seed ^ s.pack());
+        for total_visits in 0..self.rollouts {
+            let i = self.select(&stats, total_visits);
+            let reward = self.rollout(&stats[i].next, &mut rng);
+            let st = &mut stats[i];
+            st.visits += 1;
+            st.mean += (reward - st.mean) / st.visits as f64;
+        }
+        stats
+    }
+}
+
+impl Agent for Mcts {
+    fn next_move(&self, s: &State) -> Option<(Move, State)> {
+        let stats = self.search(s);
+        let mut best: Option<&MoveStats> = None;
+        for st in stats.iter().filter(|st| st.visits > 0) {
+            if best.is_none_or(|b| st.mean > b.mean) {
+                best = Some(st);
+            }
+        }
+        best.map(|st| (st.m, st.next))
+    }
+}
+
+// the total value of the tiles at the end of a random game from `s` (after
+// adding a tile)
+fn rollout_to_end(s: &State, rng: &mut impl Rng) -> f64 {
+    let mut s = *s;
+    s.rand_add(rng);
+    while let Some((_, next)) = rand_move(&s, rng) {
+        s = next;
+        s.rand_add(rng);
+    }
+    s.exponent_grid()
+        .iter()
+        .filter(|&&x| x > 0)
+        .map(|&x| (1u32 << x) as f64)
+        .sum()
+}
+
+/// Choose a move by playing `iterations` random games to the end after each
+/// legal move from `s`, picking the move whose games finish with the highest
+/// mean total tile value.
+///
+/// Ties go to the move that comes first in [`Move::ALL`].
+pub fn mcts_move(s: &State, iterations: usize, rng: &mut impl Rng) -> Option<(Move, State)> {
+    let mut best: Option<(Move, State, f64)> = None;
+    for (m, next) in s.legal_moves() {
+        let total: f64 = (0..iterations).map(|_| rollout_to_end(&next, rng)).sum();
+        let mean = total / iterations.max(1) as f64;
+        if best.is_none_or(|(_, _, best_mean)| mean > best_mean) {
+            best = Some((m, next, mean));
+        }
+    }
+    best.map(|(m, next, _)| (m, next))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::game::Game;
+    use crate::{Algorithm, Config};
+
+    fn test_state() -> State {
+        *Game::from_rng(StdRng::seed_from_u64(8)).state()
+    }
+
+    fn min_visits(mcts: &Mcts, s: &State) -> u32 {
+        mcts.search(s).iter().map(|st| st.visits).min().unwrap()
+    }
+
+    #[test]
+    fn visits_add_up() {
+        let mcts = Mcts::default();
+        let s = test_state();
+        let stats = mcts.search(&s);
+        assert_eq!(s.legal_moves().len(), stats.len());
+        assert_eq!(mcts.rollouts, stats.iter().map(|st| st.visits).sum::<u32>());
+        for st in stats {
+            assert!((0.0..=1.0).contains(&st.mean));
+        }
+        assert!(mcts.next_move(&State::default()).is_none());
+    }
+
+    #[test]
+    fn no_exploration_exploits() {
+        let mcts = Mcts {
+            exploration: 0.0,
+            ..Mcts::default()
+        };
+        let s = test_state();
+        let mut stats = mcts.search(&s);
+        // a rollout always goes to the best mean, however often it was visited
+        for (i, st) in stats.iter_mut().enumerate() {
+            st.visits = 1 + 100 * i as u32;
+            st.mean = 0.1 * i as f64;
+        }
+        let total = stats.iter().map(|st| st.visits).sum();
+        assert_eq!(stats.len() - 1, mcts.select(&stats, total));
+        // unvisited moves still get one rollout first
+        stats[0].visits = 0;
+        assert_eq!(0, mcts.select(&stats, total));
+
+        let explore = Mcts {
+            exploration: 10.0,
+            ..mcts
+        };
+        stats[0].visits = 1;
+        assert_eq!(0, explore.select(&stats, total));
+    }
+
+    #[test]
+    fn flat_search_beats_random() {
+        let mean_score = |algorithm| {
+            let total: u64 = (1..=3)
+                .map(|seed| {
+                    Config {
+                        algorithm,
+                        target_score: Some(256),
+                        seed: Some(seed),
+                        ..Config::default()
+                    }
+                    .run_headless()
+                    .score
+                })
+                .sum();
+            total as f64 / 3.0
+        };
+        let random = mean_score(Algorithm::Random);
+        let mcts = mean_score(Algorithm::Mcts { iterations: 5 });
+        assert!(mcts > random, "{mcts} <= {random}");
+    }
+
+    #[test]
+    fn exploration_spreads_rollouts() {
+        let s = test_state();
+        let visits = |exploration| {
+            min_visits(
+                &Mcts {
+                    exploration,
+                    ..Mcts::default()
+                },
+                &s,
+            )
+        };
+        assert!(visits(0.0) < visits(0.5));
+        assert!(visits(0.5) <= visits(5.0));
+        // with a huge constant the means hardly matter
+        let n = s.legal_moves().len() as u32;
+        assert!(visits(1000.0) >= Mcts::default().rollouts / n - 1);
+    }
+}
@@ -0,0 +1,1893 @@
+//! Playing whole games with one of the built-in [`Algorithm`]s or a custom
+//! [`Agent`], and reporting how they went.
+use std::{io, time::Instant};
+
+use crate::ai::{
+    endgame_move, expectimax_monotonic_move, expectimax_monotonic_timed_move, expectimax_sum_move,
+    expectimax_sum_timed_move, expectimax_weight_move, expectimax_weight_timed_move, near_tie,
+    rand_move, score_moves, smart_depth, sum_tiles_score, weight_corner, weight_score, Heuristic,
+    MoveFilter, NearTie, Scorer, TieBreak, UnknownHeuristic, WeightConfig,
+};
+use crate::game::{Corner, Move, State};
+use rand::{prelude::ThreadRng, rngs::StdRng, Rng};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+use crate::game::Game;
+use crate::render::{AppendingRenderer, ClearingRenderer, Renderer};
+use crate::replay::Recorder;
+use crate::{ai, mcts};
+
+/// The color of the lines around and between tiles, unless a [`Theme`]
+/// overrides it.
+pub const BORDER_COLOR: Color = Color::Rgb(200, 200, 200);
+
+// tile colors for Theme::Warm, indexed by exponent - 1, from 2 (cool) up to
+// 2048 (warm); larger tiles share the last color
+const WARM_TILE_COLORS: [Color; 11] = [
+    Color::Rgb(110, 160, 255),
+    Color::Rgb(90, 190, 240),
+    Color::Rgb(80, 210, 200),
+    Color::Rgb(90, 210, 140),
+    Color::Rgb(150, 210, 90),
+    Color::Rgb(210, 210, 70),
+    Color::Rgb(240, 190, 60),
+    Color::Rgb(250, 160, 50),
+    Color::Rgb(250, 120, 50),
+    Color::Rgb(240, 80, 50),
+    Color::Rgb(230, 40, 60),
+];
+
+/// Colors for drawing boards in the terminal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Theme {
+    /// A gray border around tiles in the terminal's own color.
+    #[default]
+    Plain,
+    /// Tiles colored from cool to warm as they get bigger, with a darker
+    /// border.
+    Warm,
+}
+
+impl Theme {
+    pub fn border_color(&self) -> Color {
+        match self {
+            Theme::Plain => BORDER_COLOR,
+            Theme::Warm => Color::Rgb(120, 120, 120),
+        }
+    }
+
+    /// The color for a tile with exponent `exp` (`None` for the terminal's
+    /// default).
+    pub fn tile_color(&self, exp: u8) -> Option<Color> {
+        match self {
+            Theme::Plain => None,
+            Theme::Warm if exp == 0 => None,
+            Theme::Warm => Some(WARM_TILE_COLORS[(exp as usize).min(11) - 1]),
+        }
+    }
+}
+
+/// How boards are drawn on standard output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Style {
+    /// With [`ColorChoice::Auto`], colors are only used when standard output
+    /// is a terminal.
+    pub color: ColorChoice,
+    pub theme: Theme,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            color: ColorChoice::Auto,
+            theme: Theme::Plain,
+        }
+    }
+}
+
+impl Style {
+    // termcolor's Auto only looks at the environment, so check for a terminal
+    // here to keep escape codes out of files and pipes
+    pub(crate) fn color_choice(&self) -> ColorChoice {
+        match self.color {
+            ColorChoice::Auto if !io::stdout().is_terminal() => ColorChoice::Never,
+            choice => choice,
+        }
+    }
+}
+
+fn border_write<S: AsRef<str>>(stream: &mut impl WriteColor, theme: Theme, s: S) -> io::Result<()> {
+    _ = stream.set_color(ColorSpec::new().set_fg(Some(theme.border_color())));
+    write!(stream, "{}", s.as_ref())?;
+    stream.reset()?;
+    Ok(())
+}
+
+// convenience that cleans up the code
+fn border_writeln<S: AsRef<str>>(
+    stream: &mut impl WriteColor,
+    theme: Theme,
+    s: S,
+) -> io::Result<()> {
+    border_write(stream, theme, s)?;
+    writeln!(stream)?;
+    Ok(())
+}
+
+fn board_separator() -> String {
+    format!("+{bar}+{bar}+{bar}+{bar}+", bar = "------")
+}
+
+/// Render a board as a grid of tile values, without any color.
+pub fn render_board(s: &State) -> String {
+    let sep = board_separator();
+    let mut out = format!("{sep}\n");
+    for i in 0..4 {
+        out.push('|');
+        for j in 0..4 {
+            let tile = s.tile(i * 4 + j);
+            if tile == 1 {
+                out.push_str("      ");
+            } else {
+                out.push_str(&format!("{:>5} ", tile));
+            }
+            out.push('|');
+        }
+        out.push_str(&format!("\n{sep}\n"));
+    }
+    out
+}
+
+pub(crate) fn write_state(s: &State, theme: Theme, stream: &mut impl WriteColor) -> io::Result<()> {
+    let sep = board_separator();
+    let tiles = s.exponent_grid();
+    border_writeln(stream, theme, &sep)?;
+    for i in 0..4 {
+        border_write(stream, theme, "|")?;
+        for j in 0..4 {
+            let exp = tiles[i * 4 + j];
+            if exp == 0 {
+                write!(stream, "      ")?;
+            } else {
+                _ = stream.set_color(ColorSpec::new().set_fg(theme.tile_color(exp)));
+                write!(stream, "{:>5} ", 1u32 << exp)?;
+                stream.reset()?;
+            }
+            border_write(stream, theme, "|")?;
+        }
+        writeln!(stream)?;
+        border_writeln(stream, theme, &sep)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn print_state(s: &State, style: Style) {
+    write_state(
+        s,
+        style.theme,
+        &mut StandardStream::stdout(style.color_choice()),
+    )
+    .expect("could not print colored state");
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Depth {
+    /// A depth based on how full the board is, switching to an exact search
+    /// once the end of the game is in sight (see [`ai::endgame_move`]).
+    Smart,
+    Fixed(u32),
+    /// Iterative deepening until the time budget for a move runs out.
+    Timed(Duration),
+}
+
+/// Draw how far `highest_tile` has come toward `target` as a bar `width`
+/// characters wide, like `[#####     ] 512/2048`.
+///
+/// The bar fills in by exponent rather than by value, since each new tile is
+/// double the last one and just as hard to reach.
+pub fn progress_bar(highest_tile: u32, target: u32, width: usize) -> String {
+    let exponent = |tile: u32| if tile <= 1 { 0 } else { tile.ilog2() as usize };
+    let filled = match exponent(target) {
+        0 => width,
+        goal => (exponent(highest_tile) * width / goal).min(width),
+    };
+    format!(
+        "[{}{}] {highest_tile}/{target}",
+        "#".repeat(filled),
+        " ".repeat(width - filled)
+    )
+}
+
+/// Draw how many games ended with each highest tile as a bar chart, one line
+/// per tile like `  2048 | ##### 12`, with the longest bar `width` characters
+/// long.
+pub(crate) fn tile_bar_chart(counts: impl IntoIterator<Item = (u32, u64)>, width: u64) -> String {
+    let counts: Vec<(u32, u64)> = counts.into_iter().collect();
+    let most = counts.iter().map(|&(_, n)| n).max().unwrap_or(0);
+    let mut out = String::new();
+    for (tile, n) in counts {
+        // round up so every tile that occurred gets at least one mark
+        let bar = "#".repeat((n * width).div_ceil(most) as usize);
+        out += &format!("{tile:>6} | {bar} {n}\n");
+    }
+    out
+}
+
+// show a batch's progress on stderr, keeping stdout clean for the results
+pub(crate) fn report_games(done: usize, total: usize) {
+    eprint!("\r{done}/{total} games");
+    if done == total {
+        eprintln!();
+    }
+}
+
+// the moves in a line of play, separated by spaces
+fn format_line(line: &[(State, Move)]) -> String {
+    let moves: Vec<String> = line.iter().map(|(_, m)| format!("{m:?}")).collect();
+    moves.join(" ")
+}
+
+/// Moves whose scores are within this fraction of each other are shown as ties
+/// while watching a game.
+const TIE_TOLERANCE: f32 = 1e-3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Algorithm {
+    ExpectimaxSum(Depth),
+    ExpectimaxWeight(Depth),
+    /// Expectimax scoring boards with one of the named [`Heuristic`]s.
+    ExpectimaxHeuristic(Depth, Heuristic),
+    /// Expectimax scoring boards with [`ai::monotonicity_score`].
+    ExpectimaxMonotonic(Depth),
+    /// Expectimax scoring boards with [`ai::weight_score`] (or
+    /// [`Config::weights`]) to a fixed depth, averaging each chance node over this many random tiles (see
+    /// [`ai::expectimax_sampled_move`]). It ignores [`Config::prefilter`].
+    ExpectimaxSampled {
+        depth: u32,
+        samples: usize,
+    },
+    /// Search scoring boards with [`ai::weight_score`] (or [`Config::weights`]),
+    /// as if every tile were placed where it hurts most (see
+    /// [`ai::minimax_worst_move`]). It ignores [`Config::prefilter`].
+    MinimaxWorst(Depth),
+    /// Flat Monte Carlo search with this many random games per move (see
+    /// [`mcts::mcts_move`]).
+    Mcts {
+        iterations: usize,
+    },
+    Random,
+    /// Play [`Config::agent`]. On its own, as an [`Agent`], this makes no
+    /// moves.
+    Agent,
+}
+
+/// A policy for playing the game.
+///
+/// The built-in [`Algorithm`]s implement this, and other agents can be played
+/// by setting [`Config::agent`] along with [`Algorithm::Agent`].
+pub trait Agent: Send + Sync {
+    /// Choose a legal move from `s` and return it along with the resulting
+    /// board (before a new tile is added), or `None` to end the game.
+    fn next_move(&self, s: &State) -> Option<(Move, State)>;
+}
+
+impl fmt::Debug for dyn Agent + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<agent>")
+    }
+}
+
+impl Algorithm {
+    fn expectimax_move(
+        d: Depth,
+        s: &State,
+        filter: Option<MoveFilter>,
+        score: &impl Fn(&State) -> f32,
+        fixed_move: impl Fn(&State, u32) -> Option<(Move, State)>,
+        timed_move: impl Fn(&State, Duration) -> Option<(Move, State, u32)>,
+    ) -> Option<(Move, State, u32)> {
+        let depth = match d {
+            Depth::Smart => {
+                if let Some(found) = endgame_move(s) {
+                    return Some(found);
+                }
+                smart_depth(s)
+            }
+            Depth::Fixed(d) => d,
+            Depth::Timed(budget) => return timed_move(s, budget),
+        };
+        match filter {
+            Some(filter) => ai::expectimax_move_prefiltered(s, depth, score, filter),
+            None => fixed_move(s, depth),
+        }
+        .map(|(m, s)| (m, s, depth))
+    }
+
+    fn minimax_worst_move(
+        d: Depth,
+        s: &State,
+        score: &impl Fn(&State) -> f32,
+    ) -> Option<(Move, State, u32)> {
+        let depth = match d {
+            Depth::Smart => smart_depth(s),
+            Depth::Fixed(d) => d,
+            Depth::Timed(budget) => return ai::minimax_worst_timed_move(s, budget, score),
+        };
+        ai::minimax_worst_move(s, depth, score).map(|(m, s)| (m, s, depth))
+    }
+
+    // the terminal score this algorithm searches with, if it searches
+    fn scorer(&self) -> Option<Scorer> {
+        match *self {
+            Algorithm::ExpectimaxSum(_) => Some(sum_tiles_score),
+            Algorithm::ExpectimaxWeight(_)
+            | Algorithm::ExpectimaxSampled { .. }
+            | Algorithm::MinimaxWorst(_) => Some(weight_score),
+            Algorithm::ExpectimaxHeuristic(_, h) => Some(h.scorer()),
+            Algorithm::ExpectimaxMonotonic(_) => Some(|s| ai::monotonicity_score(s) as f32),
+            Algorithm::Mcts { .. } | Algorithm::Random | Algorithm::Agent => None,
+        }
+    }
+
+    // the depth setting of the expectimax searches that can score every move
+    fn expectimax_depth(&self) -> Option<Depth> {
+        match *self {
+            Algorithm::ExpectimaxSum(d)
+            | Algorithm::ExpectimaxWeight(d)
+            | Algorithm::ExpectimaxHeuristic(d, _)
+            | Algorithm::ExpectimaxMonotonic(d) => Some(d),
+            Algorithm::ExpectimaxSampled { .. }
+            | Algorithm::MinimaxWorst(_)
+            | Algorithm::Mcts { .. }
+            | Algorithm::Random
+            | Algorithm::Agent => None,
+        }
+    }
+
+    // the depth and terminal score for analyzing a move from `s`, if this
+    // algorithm searches to a single depth
+    fn fixed_search(&self, s: &State) -> Option<(u32, Scorer)> {
+        let depth = match self.expectimax_depth()? {
+            Depth::Smart => smart_depth(s),
+            Depth::Fixed(d) => d,
+            Depth::Timed(_) => return None,
+        };
+        Some((depth, self.scorer()?))
+    }
+
+    /// Choose the next move, returning the depth searched along with it.
+    ///
+    /// Random play draws from `rng`. Fixed-depth searches only consider moves
+    /// that pass `filter` (if any do).
+    fn search<Rn: Rng>(
+        &self,
+        s: &State,
+        rng: &mut Rn,
+        filter: Option<MoveFilter>,
+    ) -> Option<(Move, State, u32)> {
+        match *self {
+            Algorithm::ExpectimaxSum(d) => Self::expectimax_move(
+                d,
+                s,
+                filter,
+                &sum_tiles_score,
+                expectimax_sum_move,
+                expectimax_sum_timed_move,
+            ),
+            Algorithm::ExpectimaxWeight(d) => Self::expectimax_move(
+                d,
+                s,
+                filter,
+                &weight_score,
+                expectimax_weight_move,
+                expectimax_weight_timed_move,
+            ),
+            Algorithm::ExpectimaxHeuristic(d, h) => {
+                let score = h.scorer();
+                Self::expectimax_move(
+                    d,
+                    s,
+                    filter,
+                    &score,
+                    |s, depth| ai::expectimax_move_cached(s, depth, &score),
+                    |s, budget| ai::expectimax_timed_move(s, budget, &score),
+                )
+            }
+            Algorithm::ExpectimaxMonotonic(d) => Self::expectimax_move(
+                d,
+                s,
+                filter,
+                &|s| ai::monotonicity_score(s) as f32,
+                expectimax_monotonic_move,
+                expectimax_monotonic_timed_move,
+            ),
+            Algorithm::ExpectimaxSampled { depth, samples } => {
+                ai::expectimax_sampled_move(s, depth, &weight_score, samples, rng)
+                    .map(|(m, s)| (m, s, depth))
+            }
+            Algorithm::MinimaxWorst(d) => Self::minimax_worst_move(d, s, &weight_score),
+            Algorithm::Mcts { iterations } => {
+                mcts::mcts_move(s, iterations, rng).map(|(m, s)| (m, s, 0))
+            }
+            Algorithm::Random => rand_move(s, rng).map(|(m, s)| (m, s, 0)),
+            // Config::next_move plays the agent itself
+            Algorithm::Agent => None,
+        }
+    }
+}
+
+impl Agent for Algorithm {
+    fn next_move(&self, s: &State) -> Option<(Move, State)> {
+        self.search(s, &mut ThreadRng::default(), None)
+            .map(|(m, s, _)| (m, s))
+    }
+}
+
+/// Stop a game once its score improves by less than `min_gain` over `moves`
+/// consecutive moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StallLimit {
+    pub moves: u32,
+    pub min_gain: u64,
+}
+
+/// Tracks a game's recent score history to detect a [`StallLimit`].
+#[derive(Clone, Debug)]
+struct StallDetector {
+    limit: StallLimit,
+    // scores after each of the last `limit.moves` moves, plus one before them
+    scores: VecDeque<u64>,
+}
+
+impl StallDetector {
+    fn new(limit: StallLimit, score: u64) -> Self {
+        Self {
+            limit,
+            scores: VecDeque::from([score]),
+        }
+    }
+
+    /// Record the score after a move and check whether the game has stalled.
+    fn observe(&mut self, score: u64) -> bool {
+        self.scores.push_back(score);
+        if self.scores.len() as u32 > self.limit.moves + 1 {
+            self.scores.pop_front();
+        }
+        self.scores.len() as u32 == self.limit.moves + 1
+            && score - self.scores[0] < self.limit.min_gain
+    }
+}
+
+/// Counts how often the corner that [`ai::weight_score`] favors changes over a
+/// game, which usually means the AI lost its grip on the board.
+#[derive(Clone, Debug, Default)]
+struct CornerTracker {
+    corner: Option<Corner>,
+    switches: u32,
+}
+
+impl CornerTracker {
+    fn observe(&mut self, s: &State) {
+        let corner = weight_corner(s);
+        if self.corner.is_some_and(|c| c != corner) {
+            self.switches += 1;
+        }
+        self.corner = Some(corner);
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Config {
+    pub algorithm: Algorithm,
+    /// The agent to play when `algorithm` is [`Algorithm::Agent`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub agent: Option<Arc<dyn Agent>>,
+    pub target_score: Option<u32>,
+    pub print: bool,
+    /// How boards are drawn, whether or not `print` is set.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub style: Style,
+    /// While printing, show how close the game is to `target_score` and how
+    /// long it's been going; batches report how many games are done.
+    pub progress: bool,
+    /// While printing, redraw the board in place rather than printing every
+    /// board after the last (only on a terminal; see [`crate::render`]).
+    pub clear: bool,
+    /// Give up on a game whose score stops improving.
+    pub stall: Option<StallLimit>,
+    /// Keep every board from the game in [`GameResult::history`].
+    pub record_history: bool,
+    /// While printing, also show the line of play the AI expects, this many
+    /// moves long (0 to not show it).
+    pub pv_plies: u32,
+    /// Skip moves that fail this test before searching (see
+    /// [`ai::expectimax_move_prefiltered`]). Timed searches ignore it.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub prefilter: Option<MoveFilter>,
+    /// Replace the weights used by [`Algorithm::ExpectimaxWeight`],
+    /// [`Algorithm::ExpectimaxSampled`] and [`Algorithm::MinimaxWorst`], both
+    /// to choose moves and in the analysis shown while watching.
+    pub weights: Option<WeightConfig>,
+    /// Add this much to the score of the same algorithms as
+    /// [`Config::weights`] for each empty cell (see
+    /// [`ai::free_space_weight_score`]).
+    pub empty_weight: f64,
+    /// Once the board is nearly full, play to keep moves available rather
+    /// than for the heuristic (see [`ai::survival_move`]). The exhaustive
+    /// endgame search still takes priority when it can finish. Algorithms
+    /// that don't score boards (random play, Monte Carlo and custom agents)
+    /// can't play for survival.
+    pub survival: bool,
+    /// How fixed-depth searches choose between equally scored moves. Any
+    /// policy but the default scores every move without pruning (and without
+    /// the endgame search of [`Depth::Smart`]), so it's slower.
+    pub tie_break: TieBreak,
+    /// Seed for both the tiles added to the board and random moves, so that
+    /// games can be reproduced (unless the search depth depends on timing).
+    pub seed: Option<u64>,
+}
+
+impl Config {
+    /// Search with the [`Heuristic`] called `name`, keeping the current search
+    /// depth (or [`Depth::Smart`] if the algorithm doesn't search).
+    pub fn with_preset(mut self, name: &str) -> Result<Self, UnknownHeuristic> {
+        let heuristic: Heuristic = name.parse()?;
+        let depth = match self.algorithm {
+            Algorithm::ExpectimaxSum(d)
+            | Algorithm::ExpectimaxWeight(d)
+            | Algorithm::ExpectimaxHeuristic(d, _)
+            | Algorithm::ExpectimaxMonotonic(d)
+            | Algorithm::MinimaxWorst(d) => d,
+            Algorithm::ExpectimaxSampled { depth, .. } => Depth::Fixed(depth),
+            Algorithm::Mcts { .. } | Algorithm::Random | Algorithm::Agent => Depth::Smart,
+        };
+        self.algorithm = Algorithm::ExpectimaxHeuristic(depth, heuristic);
+        Ok(self)
+    }
+}
+
+impl Default for Config {
+    /// The command-line defaults, except that nothing is printed while playing.
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::ExpectimaxWeight(Depth::Smart),
+            agent: None,
+            target_score: Some(2048),
+            print: false,
+            style: Style::default(),
+            clear: true,
+            progress: false,
+            stall: None,
+            record_history: false,
+            pv_plies: 0,
+            prefilter: None,
+            weights: None,
+            empty_weight: 0.0,
+            survival: false,
+            tie_break: TieBreak::PreferOrder,
+            seed: None,
+        }
+    }
+}
+
+/// Why a game ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The target tile was reached.
+    ReachedTarget,
+    /// There were no legal moves left (or a custom [`Agent`] stopped moving).
+    /// A game with a target that runs out of moves is
+    /// [`Outcome::Unreachable`] instead.
+    GameOver,
+    /// The score stopped improving (see [`StallLimit`]).
+    Stalled,
+    /// The target tile could no longer be reached (see
+    /// [`State::can_possibly_reach`]): it's larger than any tile, or the game
+    /// ran out of moves without it.
+    Unreachable,
+}
+
+/// Summary of the search depths completed over a game, one per move.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DepthStats {
+    pub min: u32,
+    pub max: u32,
+    total: u64,
+    count: u32,
+}
+
+impl DepthStats {
+    fn record(&mut self, depth: u32) {
+        if self.count == 0 || depth < self.min {
+            self.min = depth;
+        }
+        self.max = self.max.max(depth);
+        self.total += depth as u64;
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.total as f64 / self.count as f64
+    }
+}
+
+/// Percentiles of how long it took to choose each move in a game.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Latency {
+    /// The number of moves timed.
+    pub count: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    /// The slowest move, which on a nearly full board can take far longer
+    /// than the percentiles suggest when the search depth goes up.
+    pub max: Duration,
+}
+
+impl Latency {
+    /// Compute the percentiles of `durations` (all zero if there are none),
+    /// using the nearest-rank method.
+    fn from_durations(mut durations: Vec<Duration>) -> Self {
+        if durations.is_empty() {
+            return Self::default();
+        }
+        durations.sort_unstable();
+        let percentile = |p: usize| {
+            let rank = (p * durations.len()).div_ceil(100);
+            durations[rank.max(1) - 1]
+        };
+        Self {
+            count: durations.len(),
+            p50: percentile(50),
+            p90: percentile(90),
+            p95: percentile(95),
+            p99: percentile(99),
+            max: durations[durations.len() - 1],
+        }
+    }
+}
+
+/// Tile values worth reporting as having been reached in a game.
+pub const MILESTONES: [u32; 7] = [128, 256, 512, 1024, 2048, 4096, 8192];
+
+/// The outcome of a single game.
+#[derive(Clone, Debug)]
+pub struct GameResult {
+    /// The board when the game ended.
+    pub state: State,
+    pub highest_tile: u32,
+    pub moves: u32,
+    /// Points scored over the game (see [`Game::score`]).
+    pub score: u64,
+    /// The highest of the [`MILESTONES`] reached, if any.
+    pub milestone: Option<u32>,
+    /// Whether the game ended with no empty cells.
+    pub board_full: bool,
+    /// The fewest empty cells on the board at any point in the game, which
+    /// shows how close it came to ending.
+    pub min_empty: u32,
+    /// How many different tile values were on the final board.
+    pub distinct_tiles: u32,
+    /// How many times the corner favored by [`ai::weight_score`] changed.
+    pub corner_switches: u32,
+    pub won: bool,
+    pub outcome: Outcome,
+    pub elapsed: Duration,
+    /// Depths the search actually completed (always 0 for random play).
+    pub depth: DepthStats,
+    /// How many times each move was made, in the order of [`Move::ALL`].
+    pub move_counts: [u32; 4],
+    /// How long choosing a move took, for the moves that were made.
+    pub latency: Latency,
+    /// The mean [`State::chance_branching`] of the boards moved from, which
+    /// drives how expensive each search was.
+    pub branching: f64,
+    /// The board after each move and the tile added after it, if
+    /// [`Config::record_history`] is set (otherwise this is empty).
+    pub history: Vec<State>,
+}
+
+impl GameResult {
+    /// How many times move `m` was made.
+    pub fn move_count(&self, m: Move) -> u32 {
+        self.move_counts[Move::ALL.iter().position(|&x| x == m).unwrap()]
+    }
+
+    pub fn moves_per_s(&self) -> f64 {
+        self.moves as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Summarize the result as a single-line JSON object, labeled with the
+    /// name of the `algorithm` that played.
+    pub fn to_json(&self, algorithm: &str) -> String {
+        // a game too short to time has no meaningful rate, and JSON has no
+        // infinity
+        let moves_per_s = self.moves_per_s();
+        let moves_per_s = if moves_per_s.is_finite() {
+            moves_per_s
+        } else {
+            0.0
+        };
+        // JSON strings can't contain quotes, backslashes or control characters
+        let mut escaped = String::with_capacity(algorithm.len());
+        for c in algorithm.chars() {
+            match c {
+                '"' | '\\' => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                }
+                c if c < ' ' => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+                c => escaped.push(c),
+            }
+        }
+        format!(
+            r#"{{"highest_tile":{},"moves":{},"won":{},"elapsed_secs":{},"moves_per_sec":{},"algorithm":"{}"}}"#,
+            self.highest_tile,
+            self.moves,
+            self.won,
+            self.elapsed.as_secs_f64(),
+            moves_per_s,
+            escaped
+        )
+    }
+}
+
+// a terminal score that can depend on the Config
+type ConfigScore<'a> = Box<dyn Fn(&State) -> f32 + 'a>;
+
+/// A move chosen by [`Config`]'s algorithm.
+#[derive(Clone, Debug)]
+pub(crate) struct Choice {
+    pub(crate) m: Move,
+    /// The board after the move, before a tile is added.
+    pub(crate) next: State,
+    /// The depth searched (0 for random play and custom agents).
+    pub(crate) depth: u32,
+    /// Every move the search chose between and its score, for searches that
+    /// score them all to a single depth.
+    pub(crate) scored: Option<Vec<(Move, State, f32)>>,
+}
+
+impl Choice {
+    fn unscored((m, next, depth): (Move, State, u32)) -> Self {
+        Choice {
+            m,
+            next,
+            depth,
+            scored: None,
+        }
+    }
+
+    /// The top two moves this was chosen from, if they scored too closely to
+    /// call. Only searches that score every move know, so random play, timed
+    /// searches and the like never report ties.
+    fn near_tie(&self) -> Option<NearTie> {
+        let scored = self.scored.as_ref()?;
+        let (_, _, best) = ai::top_scored_move(scored)?;
+        near_tie(scored, TIE_TOLERANCE * best.abs())
+    }
+}
+
+impl Config {
+    // Choose the next move. With `analyze`, fixed-depth expectimax scores every
+    // move (rather than pruning), and the Choice keeps the scores.
+    pub(crate) fn choose<Rn: Rng>(&self, s: &State, rng: &mut Rn, analyze: bool) -> Option<Choice> {
+        if let Some(found) = self.survival_move(s) {
+            return Some(Choice::unscored(found));
+        }
+        if analyze || self.tie_break != TieBreak::PreferOrder {
+            if let Some((depth, score)) = self.fixed_search(s) {
+                // as in Algorithm::expectimax_move
+                if self.algorithm.expectimax_depth() == Some(Depth::Smart) {
+                    if let Some(found) = endgame_move(s) {
+                        return Some(Choice::unscored(found));
+                    }
+                }
+                return self.tie_broken_move(s, depth, &score, rng);
+            }
+        }
+        let found = match self.algorithm {
+            Algorithm::Agent => self.agent.as_ref()?.next_move(s).map(|(m, s)| (m, s, 0)),
+            Algorithm::ExpectimaxWeight(d) if self.custom_weight_score() => {
+                let score = |s: &State| self.weight_score(s);
+                Algorithm::expectimax_move(
+                    d,
+                    s,
+                    self.prefilter,
+                    &score,
+                    |s, depth| ai::expectimax_move_cached(s, depth, &score),
+                    |s, budget| ai::expectimax_timed_move(s, budget, &score),
+                )
+            }
+            Algorithm::MinimaxWorst(d) if self.custom_weight_score() => {
+                Algorithm::minimax_worst_move(d, s, &|s: &State| self.weight_score(s))
+            }
+            Algorithm::ExpectimaxSampled { depth, samples } if self.custom_weight_score() => {
+                let score = |s: &State| self.weight_score(s);
+                ai::expectimax_sampled_move(s, depth, &score, samples, rng)
+                    .map(|(m, s)| (m, s, depth))
+            }
+            _ => self.algorithm.search(s, rng, self.prefilter),
+        };
+        found.map(Choice::unscored)
+    }
+
+    // score every move (that passes the prefilter, if any do) and pick the
+    // best with self.tie_break
+    fn tie_broken_move<Rn: Rng>(
+        &self,
+        s: &State,
+        depth: u32,
+        score: &impl Fn(&State) -> f32,
+        rng: &mut Rn,
+    ) -> Option<Choice> {
+        let mut scored = score_moves(s, depth, &ai::memoize_leaves(score));
+        if let Some(filter) = self.prefilter {
+            if scored.iter().any(|(_, next_s, _)| filter(s, next_s)) {
+                scored.retain(|(_, next_s, _)| filter(s, next_s));
+            }
+        }
+        let (m, next, _) = self.tie_break.pick(&scored, rng)?;
+        Some(Choice {
+            m,
+            next,
+            depth,
+            scored: Some(scored),
+        })
+    }
+
+    // whether the algorithms that score with the built-in weight_score should
+    // use Config::weight_score instead
+    pub(crate) fn custom_weight_score(&self) -> bool {
+        self.weights.is_some() || self.empty_weight != 0.0
+    }
+
+    // weight_score with the custom weights and empty-cell bonus
+    pub(crate) fn weight_score(&self, s: &State) -> f32 {
+        match &self.weights {
+            Some(weights) => {
+                (weights.score(s) as f64 + self.empty_weight * ai::empty_cells_score(s)) as f32
+            }
+            None => ai::free_space_weight_score(s, self.empty_weight) as f32,
+        }
+    }
+
+    // the terminal score the algorithm searches with, if it searches, using
+    // Config::weight_score in place of the built-in weight_score
+    fn scorer(&self) -> Option<ConfigScore<'_>> {
+        let scorer = self.algorithm.scorer()?;
+        let weighted = matches!(
+            self.algorithm,
+            Algorithm::ExpectimaxWeight(_)
+                | Algorithm::ExpectimaxSampled { .. }
+                | Algorithm::MinimaxWorst(_)
+        );
+        if weighted && self.custom_weight_score() {
+            return Some(Box::new(|s| self.weight_score(s)));
+        }
+        Some(Box::new(scorer))
+    }
+
+    // the depth and terminal score for analyzing a move from `s`, if the
+    // algorithm searches to a single depth
+    fn fixed_search(&self, s: &State) -> Option<(u32, ConfigScore<'_>)> {
+        let (depth, _) = self.algorithm.fixed_search(s)?;
+        Some((depth, self.scorer()?))
+    }
+
+    /// The line of play the algorithm expects from `s` after the move it
+    /// chose, `m` (see [`ai::principal_variation_after`]). Only algorithms that
+    /// search to a single depth can continue the line.
+    fn principal_variation(&self, s: &State, m: Move, plies: u32) -> Option<Vec<(State, Move)>> {
+        let (depth, score) = self.fixed_search(s)?;
+        let score = ai::memoize_leaves(&score);
+        Some(ai::principal_variation_after(s, m, depth, plies, &score))
+    }
+
+    // the move to play for survival, if that's turned on and applies to `s`
+    fn survival_move(&self, s: &State) -> Option<(Move, State, u32)> {
+        if !self.survival {
+            return None;
+        }
+        let scorer = self.scorer()?;
+        if s.empty_count() as usize > ai::SURVIVAL_MAX_EMPTY {
+            return None;
+        }
+        if let Some(found) = endgame_move(s) {
+            return Some(found);
+        }
+        let found = ai::survival_move(s, &scorer);
+        // the search looks at the move and the tile after it
+        found.map(|(m, next_s)| (m, next_s, 1))
+    }
+
+    /// Start a new game from `seed` (or a random seed), so that it can be
+    /// saved. Its RNG is used both for adding tiles and for random moves.
+    pub fn new_game(seed: Option<u64>) -> Game<StdRng> {
+        Game::from_seed(seed.unwrap_or_else(|| rand::thread_rng().gen()))
+    }
+
+    /// Make one move in `game` and add a tile, returning how the move was
+    /// chosen (see [`Config::choose`] for `analyze`).
+    fn step<Rn: Rng>(&self, game: &mut Game<Rn>, analyze: bool) -> Option<Choice> {
+        let mut choice = None;
+        game.step_with_rng(|s, rng| {
+            let found = self.choose(s, rng, analyze)?;
+            let next = (found.m, found.next);
+            choice = Some(found);
+            Some(next)
+        })?;
+        choice
+    }
+
+    /// Play `game` to the end (or until the target is reached), calling
+    /// `on_move` after each move with how the move was chosen and the board it
+    /// was made from. With `analyze`, the choices carry the scores of every
+    /// move where the algorithm can provide them.
+    pub(crate) fn play<Rn: Rng>(
+        &self,
+        mut game: Game<Rn>,
+        analyze: bool,
+        mut on_move: impl FnMut(&Choice, &State, &mut Game<Rn>),
+    ) -> GameResult {
+        let start = Instant::now();
+        let mut depth = DepthStats::default();
+        let mut history = Vec::new();
+        let mut corners = CornerTracker::default();
+        corners.observe(game.state());
+        let empty_count = |s: &State| s.empty_count();
+        let mut min_empty = empty_count(game.state());
+        let mut total_branching = 0u64;
+        let mut move_counts = [0; 4];
+        let mut durations = Vec::new();
+        let mut stall = self
+            .stall
+            .map(|limit| StallDetector::new(limit, game.score()));
+        let outcome = loop {
+            // checked before every move, so that a game stops as soon as it
+            // can't win
+            let state = game.state();
+            if self
+                .target_score
+                .is_some_and(|target| !state.can_possibly_reach(target))
+            {
+                break Outcome::Unreachable;
+            }
+            let before = *game.state();
+            let move_start = Instant::now();
+            let Some(choice) = self.step(&mut game, analyze) else {
+                break Outcome::GameOver;
+            };
+            durations.push(move_start.elapsed());
+            let m = choice.m;
+            depth.record(choice.depth);
+            move_counts[Move::ALL.iter().position(|&x| x == m).unwrap()] += 1;
+            total_branching += before.chance_branching() as u64;
+            min_empty = min_empty.min(empty_count(game.state()));
+            if self.record_history {
+                history.push(*game.state());
+            }
+            corners.observe(game.state());
+            on_move(&choice, &before, &mut game);
+            if let Some(target) = self.target_score {
+                if game.state().highest_tile() == target {
+                    break Outcome::ReachedTarget;
+                }
+            }
+            if let Some(stall) = stall.as_mut() {
+                if stall.observe(game.score()) {
+                    break Outcome::Stalled;
+                }
+            }
+        };
+        let s = *game.state();
+        let mut tiles: Vec<u8> = s.exponent_grid().into_iter().filter(|&x| x > 0).collect();
+        tiles.sort_unstable();
+        tiles.dedup();
+        GameResult {
+            state: s,
+            highest_tile: s.highest_tile(),
+            moves: game.moves(),
+            score: game.score(),
+            milestone: MILESTONES
+                .iter()
+                .rev()
+                .copied()
+                .find(|&m| s.highest_tile() >= m),
+            board_full: s.is_full(),
+            min_empty,
+            distinct_tiles: tiles.len() as u32,
+            corner_switches: corners.switches,
+            won: self.won(&game),
+            outcome,
+            elapsed: start.elapsed(),
+            depth,
+            move_counts,
+            latency: Latency::from_durations(durations),
+            branching: if game.moves() == 0 {
+                0.0
+            } else {
+                total_branching as f64 / game.moves() as f64
+            },
+            history,
+        }
+    }
+
+    /// Run a game without printing anything.
+    pub fn run_headless(&self) -> GameResult {
+        self.play(Self::new_game(self.seed), false, |_, _, _| {})
+    }
+
+    /// Run `games` games without printing anything and write a CSV row with
+    /// the results of each one to `out`, after a header row.
+    ///
+    /// Games are seeded with consecutive seeds starting from [`Config::seed`]
+    /// (or a random seed if it isn't set), so any game can be replayed.
+    pub fn run_batch_csv(&self, games: u32, mut out: impl Write) -> io::Result<()> {
+        let first_seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        writeln!(out, "seed,highest_tile,score,moves,won,moves_per_s")?;
+        for i in 0..games {
+            let seed = first_seed.wrapping_add(i as u64);
+            let result = self.play(Self::new_game(Some(seed)), false, |_, _, _| {});
+            writeln!(
+                out,
+                "{},{},{},{},{},{:.1}",
+                seed,
+                result.highest_tile,
+                result.score,
+                result.moves,
+                result.won,
+                result.moves_per_s()
+            )?;
+            if self.progress {
+                report_games(i as usize + 1, games as usize);
+            }
+        }
+        Ok(())
+    }
+
+    /// Run runs the game and returns a score and whether or not this is a win.
+    pub fn run(&self) -> bool {
+        self.run_game(Self::new_game(self.seed), None, None)
+    }
+
+    // clear the screen for each board only if asked to and it's a terminal
+    fn renderer(&self) -> Box<dyn Renderer> {
+        if self.clear && io::stdout().is_terminal() {
+            Box::new(ClearingRenderer::new(self.style))
+        } else {
+            let stdout = StandardStream::stdout(self.style.color_choice());
+            Box::new(AppendingRenderer::new(stdout, self.style.theme))
+        }
+    }
+
+    /// Like [`Config::run`], but continue `game` (for example, one from
+    /// [`Game::load`]) rather than starting a new one. With `save` (and the
+    /// `serde` feature), the game is written there after every move so it can
+    /// be resumed if interrupted.
+    /// With `record`, every move is logged there by a [`Recorder`] so the game
+    /// can be replayed.
+    pub fn run_game(&self, game: Game<StdRng>, save: Option<&Path>, record: Option<&Path>) -> bool {
+        let mut renderer = self.renderer();
+        if self.print {
+            renderer
+                .board("start", game.state())
+                .expect("could not print the board");
+        }
+        let mut recorder = record.and_then(|path| {
+            File::create(path)
+                .and_then(|f| Recorder::new(BufWriter::new(f), game.state()))
+                .map_err(|err| eprintln!("could not record to {}: {err}", path.display()))
+                .ok()
+        });
+        let start = Instant::now();
+        // current estimate
+        let mut moves_per_s = 0.0;
+        #[cfg(not(feature = "serde"))]
+        if let Some(path) = save {
+            eprintln!(
+                "could not save to {}: saving needs the serde feature",
+                path.display()
+            );
+        }
+        // the analysis shown with each move comes from the search that chose it
+        let result = self.play(game, self.print, |choice, before, game| {
+            let m = choice.m;
+            #[cfg(feature = "serde")]
+            if let Some(path) = save {
+                if let Err(err) = game.save(path) {
+                    eprintln!("could not save to {}: {err}", path.display());
+                }
+            }
+            if let Some(rec) = recorder.as_mut() {
+                if let Err(err) = rec.record(m, game.state()) {
+                    eprintln!("could not record move: {err}");
+                    recorder = None;
+                }
+            }
+            let moves = game.moves();
+            // generate an estimate early on, and then periodically
+            if moves == 10 || moves % 50 == 0 {
+                let elapsed_s = start.elapsed().as_secs_f64();
+                moves_per_s = moves as f64 / elapsed_s;
+            }
+            if self.print {
+                let heading = format!("  {:>4} {:0.0} moves/s", moves, moves_per_s);
+                renderer
+                    .board(&heading, game.state())
+                    .expect("could not print the board");
+                if self.progress {
+                    let elapsed = start.elapsed().as_secs_f64();
+                    let bar = match self.target_score {
+                        Some(target) => progress_bar(game.state().highest_tile(), target, 20) + " ",
+                        None => String::new(),
+                    };
+                    _ = renderer.note(&format!("{bar}{elapsed:0.1}s"));
+                }
+                if let Some(tie) = choice.near_tie() {
+                    _ = renderer.note(&format!(
+                        "near tie: {:?} ({:0.3}) vs {:?} ({:0.3})",
+                        tie.best.0, tie.best.1, tie.runner_up.0, tie.runner_up.1
+                    ));
+                }
+                if self.pv_plies > 0 {
+                    if let Some(line) = self.principal_variation(before, m, self.pv_plies) {
+                        _ = renderer.note(&format!("expected line: {}", format_line(&line)));
+                    }
+                }
+            }
+        });
+        // if not printing intermediate state, show the final board
+        if !self.print {
+            print_state(&result.state, self.style);
+        }
+        if result.outcome == Outcome::Stalled {
+            println!("stopped after the score stalled");
+        }
+        if result.outcome == Outcome::Unreachable {
+            println!("the target can no longer be reached");
+        }
+        println!("score: {}", result.highest_tile);
+        println!(
+            "{points} points, {distinct} distinct tiles, board {fullness}",
+            points = result.score,
+            distinct = result.distinct_tiles,
+            fullness = if result.board_full {
+                "full"
+            } else {
+                "not full"
+            },
+        );
+        println!("fewest empty cells: {}", result.min_empty);
+        if let Some(milestone) = result.milestone {
+            println!("reached {milestone}");
+        }
+        if result.corner_switches > 0 {
+            println!("target corner changed {} times", result.corner_switches);
+        }
+        println!(
+            "{moves} moves in {elapsed_s:0.0}s ({moves_per_s:0.0} moves/s)",
+            moves = result.moves,
+            elapsed_s = result.elapsed.as_secs_f64(),
+            moves_per_s = result.moves_per_s(),
+        );
+        let counts: Vec<String> = Move::ALL
+            .iter()
+            .map(|&m| format!("{m:?} {}", result.move_count(m)))
+            .collect();
+        println!("moves by direction: {}", counts.join(", "));
+        if result.depth.max > 0 {
+            println!(
+                "search depth {}-{} (mean {:0.1})",
+                result.depth.min,
+                result.depth.max,
+                result.depth.mean()
+            );
+            println!("mean chance branching {:0.1}", result.branching);
+        }
+        println!(
+            "move latency: p50 {:?}, p90 {:?}, p95 {:?}, p99 {:?}, max {:?}",
+            result.latency.p50,
+            result.latency.p90,
+            result.latency.p95,
+            result.latency.p99,
+            result.latency.max
+        );
+        return result.won;
+    }
+
+    pub fn won<Rn: Rng>(&self, g: &Game<Rn>) -> bool {
+        match self.target_score {
+            Some(target) => g.state().highest_tile() >= target,
+            None => true,
+        }
+    }
+}
+
+impl<Rn: Rng> Game<Rn> {
+    /// Make a single move chosen by `config`, and add a random tile.
+    ///
+    /// Returns the move made, or `None` if the game is over. This only stops
+    /// when there are no moves left; checking for the target tile or a stall is
+    /// up to the caller.
+    pub fn step_ai(&mut self, config: &Config) -> Option<Move> {
+        config.step(self, false).map(|c| c.m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    // draw a board the way print_state would with `color`
+    fn styled_board(s: &State, color: ColorChoice, theme: Theme) -> String {
+        let mut buffer = termcolor::BufferWriter::stdout(color).buffer();
+        write_state(s, theme, &mut buffer).unwrap();
+        String::from_utf8(buffer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn progress() {
+        assert_eq!("[          ] 0/2048", progress_bar(0, 2048, 10));
+        assert_eq!("[##        ] 8/2048", progress_bar(8, 2048, 10));
+        assert_eq!("[##########] 2048/2048", progress_bar(2048, 2048, 10));
+        assert_eq!("[#####] 4096/2048", progress_bar(4096, 2048, 5));
+    }
+
+    #[test]
+    fn no_color() {
+        let s = State::from_tiles([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 0, 0, 0, 0]);
+        for theme in [Theme::Plain, Theme::Warm] {
+            let plain = styled_board(&s, ColorChoice::Never, theme);
+            assert!(!plain.contains('\x1b'), "{plain:?}");
+            assert_eq!(render_board(&s), plain);
+            let colored = styled_board(&s, ColorChoice::AlwaysAnsi, theme);
+            assert!(colored.contains('\x1b'));
+        }
+    }
+
+    #[test]
+    fn fixed_depth_is_always_reached() {
+        let config = Config {
+            algorithm: Algorithm::ExpectimaxWeight(Depth::Fixed(1)),
+            target_score: Some(64),
+            ..Config::default()
+        };
+        let result = config.run_headless();
+        assert!(result.moves > 0);
+        assert_eq!(1, result.depth.min);
+        assert_eq!(1, result.depth.max);
+        assert_eq!(1.0, result.depth.mean());
+        // every board moved from has at least two tiles
+        assert!(result.branching > 0.0 && result.branching <= 28.0);
+    }
+
+    #[test]
+    fn timed_depth_is_reported() {
+        let config = Config {
+            algorithm: Algorithm::ExpectimaxSum(Depth::Timed(Duration::from_millis(1))),
+            target_score: Some(32),
+            ..Config::default()
+        };
+        let result = config.run_headless();
+        assert!(result.depth.min >= 1);
+        assert!(result.depth.max <= ai::MAX_TIMED_DEPTH);
+    }
+
+    #[test]
+    fn random_has_no_depth() {
+        let config = Config {
+            algorithm: Algorithm::Random,
+            target_score: None,
+            ..Config::default()
+        };
+        let depth = config.run_headless().depth;
+        assert_eq!((0, 0), (depth.min, depth.max));
+    }
+
+    /// Always moves left, and gives up when it can't.
+    struct LeftAgent;
+
+    impl Agent for LeftAgent {
+        fn next_move(&self, s: &State) -> Option<(Move, State)> {
+            s.legal_moves().into_iter().find(|&(m, _)| m == Move::Left)
+        }
+    }
+
+    #[test]
+    fn custom_agent() {
+        // seeded so that the starting board can move left at all
+        let config = Config {
+            algorithm: Algorithm::Agent,
+            agent: Some(Arc::new(LeftAgent)),
+            target_score: None,
+            seed: Some(1),
+            ..Config::default()
+        };
+        let result = config.run_headless();
+        assert!(result.moves > 0);
+        assert_eq!(Outcome::GameOver, result.outcome);
+        assert!(!result
+            .state
+            .legal_moves()
+            .iter()
+            .any(|&(m, _)| m == Move::Left));
+        assert_eq!([result.moves, 0, 0, 0], result.move_counts);
+        // a copy plays with the same agent
+        assert_eq!(result.state, config.clone().run_headless().state);
+        let no_agent = Config {
+            agent: None,
+            ..config
+        };
+        assert_eq!(0, no_agent.run_headless().moves);
+    }
+
+    #[test]
+    fn move_counts() {
+        let config = Config {
+            algorithm: Algorithm::Random,
+            target_score: None,
+            seed: Some(4),
+            ..Config::default()
+        };
+        let result = config.run_headless();
+        assert_eq!(result.moves, result.move_counts.iter().sum::<u32>());
+        for m in Move::ALL {
+            assert!(result.move_count(m) > 0, "{m:?} never played");
+        }
+    }
+
+    #[test]
+    fn algorithm_is_an_agent() {
+        let agent: &dyn Agent = &Algorithm::ExpectimaxWeight(Depth::Fixed(1));
+        let s = *Game::new().state();
+        assert_eq!(ai::expectimax_weight_move(&s, 1), agent.next_move(&s));
+    }
+
+    #[test]
+    fn seeded_runs_repeat() {
+        let config = |seed| Config {
+            algorithm: Algorithm::Random,
+            target_score: None,
+            seed: Some(seed),
+            ..Config::default()
+        };
+        let result1 = config(1).run_headless();
+        let result2 = config(1).run_headless();
+        assert_eq!(result1.state, result2.state);
+        assert_eq!(result1.moves, result2.moves);
+        assert_eq!(result1.score, result2.score);
+
+        let other = config(2).run_headless();
+        assert!((result1.state, result1.moves) != (other.state, other.moves));
+
+        // every board along the way repeats too, including for searches that
+        // draw from the game's RNG
+        for algorithm in [
+            Algorithm::Random,
+            Algorithm::Mcts { iterations: 2 },
+            Algorithm::ExpectimaxSampled {
+                depth: 1,
+                samples: 2,
+            },
+        ] {
+            let config = Config {
+                algorithm,
+                target_score: Some(64),
+                record_history: true,
+                seed: Some(3),
+                ..Config::default()
+            };
+            assert_eq!(
+                config.run_headless().history,
+                config.run_headless().history,
+                "{algorithm:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn minimax_worst_wins() {
+        for depth in [Depth::Fixed(1), Depth::Timed(Duration::from_millis(1))] {
+            let config = Config {
+                algorithm: Algorithm::MinimaxWorst(depth),
+                target_score: Some(64),
+                seed: Some(4),
+                ..Config::default()
+            };
+            assert!(config.run_headless().won, "{depth:?}");
+        }
+    }
+
+    #[test]
+    fn json_result() {
+        let config = Config {
+            algorithm: Algorithm::Random,
+            target_score: Some(32),
+            seed: Some(2),
+            ..Config::default()
+        };
+        let result = config.run_headless();
+        assert!(result.moves > 0);
+        assert!(result.highest_tile >= 4);
+        let json: serde_json::Value =
+            serde_json::from_str(&result.to_json("ran\"d\\o\n\u{1}m")).unwrap();
+        assert_eq!(result.highest_tile, json["highest_tile"]);
+        assert_eq!(result.moves, json["moves"]);
+        assert_eq!(result.won, json["won"]);
+        assert_eq!("ran\"d\\o\n\u{1}m", json["algorithm"]);
+        assert!(json["elapsed_secs"].is_number());
+        assert!(json["moves_per_sec"].is_number());
+    }
+
+    #[test]
+    fn game_summary() {
+        let config = Config {
+            algorithm: Algorithm::ExpectimaxWeight(Depth::Fixed(1)),
+            target_score: None,
+            seed: Some(5),
+            ..Config::default()
+        };
+        let result = config.run_headless();
+        assert_eq!(Outcome::GameOver, result.outcome);
+        // a game only ends when the board fills up
+        assert!(result.board_full);
+        assert!(result.score > 0);
+        let grid = result.state.exponent_grid();
+        let distinct = (1..16).filter(|x| grid.contains(x)).count();
+        assert_eq!(distinct as u32, result.distinct_tiles);
+        // even a depth-1 search easily gets a 128
+        let milestone = result.milestone.expect("no milestone reached");
+        assert!(milestone <= result.highest_tile);
+        assert!(2 * milestone > result.highest_tile);
+    }
+
+    #[test]
+    fn batch_csv() {
+        let config = Config {
+            algorithm: Algorithm::Random,
+            target_score: Some(64),
+            seed: Some(10),
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        config.run_batch_csv(3, &mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(4, lines.len());
+        assert_eq!("seed,highest_tile,score,moves,won,moves_per_s", lines[0]);
+        for (i, line) in lines[1..].iter().enumerate() {
+            let fields: Vec<&str> = line.split(',').collect();
+            assert_eq!(6, fields.len(), "bad row {line:?}");
+            assert_eq!(10 + i as u64, fields[0].parse::<u64>().unwrap());
+            let highest_tile: u32 = fields[1].parse().unwrap();
+            assert!(highest_tile.is_power_of_two());
+            fields[2].parse::<u64>().unwrap();
+            assert!(fields[3].parse::<u32>().unwrap() > 0);
+            assert_eq!(highest_tile >= 64, fields[4].parse::<bool>().unwrap());
+            fields[5].parse::<f64>().unwrap();
+        }
+    }
+
+    #[test]
+    fn step_ai_matches_run() {
+        let config = Config {
+            algorithm: Algorithm::Random,
+            target_score: None,
+            seed: Some(7),
+            ..Config::default()
+        };
+        let result = config.run_headless();
+        let mut game = Game::from_rng(StdRng::seed_from_u64(7));
+        let mut moves = 0;
+        while game.step_ai(&config).is_some() {
+            moves += 1;
+        }
+        assert_eq!(result.moves, moves);
+        assert_eq!(result.state, *game.state());
+        assert_eq!(result.score, game.score());
+        assert_eq!(None, game.step_ai(&config));
+    }
+
+    #[test]
+    fn corner_switches() {
+        let corner = |i: usize| {
+            let mut exps = [0; 16];
+            exps[i] = 5;
+            State::from_exponents(&exps).unwrap()
+        };
+        let mut tracker = CornerTracker::default();
+        tracker.observe(&corner(0));
+        assert_eq!(0, tracker.switches);
+        tracker.observe(&corner(0));
+        assert_eq!(0, tracker.switches);
+        tracker.observe(&corner(15));
+        assert_eq!(1, tracker.switches);
+        tracker.observe(&corner(3));
+        tracker.observe(&corner(0));
+        assert_eq!(3, tracker.switches);
+    }
+
+    #[test]
+    fn printed_line_starts_with_move() {
+        let s = State::from_exponents(&[0, 0, 1, 1, 0, 1, 2, 3, 0, 1, 2, 3, 3, 6, 9, 10]).unwrap();
+        // the line follows the configured weights too
+        let flat = WeightConfig::from_base([1.0; 16]);
+        for weights in [None, Some(flat)] {
+            let config = Config {
+                algorithm: Algorithm::ExpectimaxWeight(Depth::Fixed(1)),
+                weights,
+                ..Config::default()
+            };
+            let m = config
+                .choose(&s, &mut ThreadRng::default(), true)
+                .unwrap()
+                .m;
+            let line = config.principal_variation(&s, m, 3).unwrap();
+            let printed = format_line(&line);
+            assert!(printed.starts_with(&format!("{m:?}")), "{printed}");
+            assert_eq!(line.len(), printed.split(' ').count());
+        }
+        let random = Config {
+            algorithm: Algorithm::Random,
+            ..Config::default()
+        };
+        assert_eq!(None, random.principal_variation(&s, Move::Left, 3));
+    }
+
+    #[test]
+    fn analysis_comes_from_the_choice() {
+        // a lone tile in the corner can go right or down, which tie
+        let s = State::from_exponents(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        let config = Config {
+            algorithm: Algorithm::ExpectimaxWeight(Depth::Fixed(1)),
+            tie_break: TieBreak::Random,
+            ..Config::default()
+        };
+        for seed in 0..8 {
+            let choice = config
+                .choose(&s, &mut StdRng::seed_from_u64(seed), true)
+                .unwrap();
+            let tie = choice.near_tie().expect("moves should tie");
+            assert!([tie.best.0, tie.runner_up.0].contains(&choice.m));
+            // the line continues from the move played, even when the tie
+            // break picks the runner-up
+            let line = config.principal_variation(&s, choice.m, 3).unwrap();
+            assert_eq!((s, choice.m), line[0]);
+        }
+        // without analysis, only tie-breaking searches keep their scores
+        let prefer_order = Config {
+            tie_break: TieBreak::PreferOrder,
+            ..config
+        };
+        let choice = prefer_order
+            .choose(&s, &mut ThreadRng::default(), false)
+            .unwrap();
+        assert!(choice.scored.is_none());
+        assert_eq!(None, choice.near_tie());
+    }
+
+    #[test]
+    fn history() {
+        let config = Config {
+            algorithm: Algorithm::Random,
+            target_score: None,
+            record_history: true,
+            seed: Some(3),
+            ..Config::default()
+        };
+        let result = config.run_headless();
+        assert_eq!(result.moves as usize, result.history.len());
+        assert_eq!(result.state, *result.history.last().unwrap());
+        let start = *Game::from_rng(StdRng::seed_from_u64(3)).state();
+        let mut prev = start;
+        for s in result.history.iter() {
+            assert!(prev.diff(s).is_some(), "not a legal move:\n{prev}\n{s}");
+            prev = *s;
+        }
+
+        let config = Config {
+            record_history: false,
+            ..config
+        };
+        assert!(config.run_headless().history.is_empty());
+    }
+
+    #[test]
+    fn presets() {
+        for h in Heuristic::ALL {
+            let config = Config {
+                algorithm: Algorithm::ExpectimaxWeight(Depth::Fixed(1)),
+                target_score: Some(64),
+                seed: Some(2),
+                ..Config::default()
+            }
+            .with_preset(h.name())
+            .unwrap();
+            assert_eq!(
+                Algorithm::ExpectimaxHeuristic(Depth::Fixed(1), h),
+                config.algorithm
+            );
+            assert!(config.run_headless().won, "{h:?} lost");
+        }
+        let err = Config::default().with_preset("nope").unwrap_err();
+        assert!(err.to_string().contains("\"nope\""), "{err}");
+    }
+
+    #[test]
+    fn custom_weights() {
+        let config = |weights| Config {
+            algorithm: Algorithm::ExpectimaxWeight(Depth::Fixed(1)),
+            target_score: Some(64),
+            record_history: true,
+            weights,
+            seed: Some(6),
+            ..Config::default()
+        };
+        let default = config(None).run_headless();
+        assert_eq!(
+            default.history,
+            config(Some(WeightConfig::default())).run_headless().history
+        );
+        let flat = WeightConfig::from_base([1.0; 16]);
+        assert_ne!(default.history, config(Some(flat)).run_headless().history);
+    }
+
+    #[test]
+    fn tie_break() {
+        // moves that merge the same tiles tie on the sum of the tiles
+        let config = |tie_break| Config {
+            algorithm: Algorithm::ExpectimaxSum(Depth::Fixed(1)),
+            target_score: Some(64),
+            record_history: true,
+            tie_break,
+            seed: Some(6),
+            ..Config::default()
+        };
+        let default = config(TieBreak::PreferOrder).run_headless();
+        let random = config(TieBreak::Random).run_headless();
+        assert_ne!(default.history, random.history);
+        assert_eq!(
+            random.history,
+            config(TieBreak::Random).run_headless().history
+        );
+    }
+
+    #[test]
+    fn empty_weight() {
+        for algorithm in [
+            Algorithm::ExpectimaxWeight(Depth::Fixed(1)),
+            Algorithm::MinimaxWorst(Depth::Fixed(1)),
+            Algorithm::ExpectimaxSampled {
+                depth: 1,
+                samples: 4,
+            },
+        ] {
+            let config = |empty_weight| Config {
+                algorithm,
+                target_score: Some(64),
+                record_history: true,
+                empty_weight,
+                seed: Some(6),
+                ..Config::default()
+            };
+            let default = config(0.0).run_headless();
+            let history = config(100.0).run_headless().history;
+            assert_ne!(default.history, history, "{algorithm:?}");
+        }
+    }
+
+    #[test]
+    fn survival() {
+        let config = |survival| Config {
+            algorithm: Algorithm::ExpectimaxWeight(Depth::Fixed(1)),
+            target_score: None,
+            survival,
+            seed: Some(8),
+            ..Config::default()
+        };
+        let s = State::from_exponents(&[2, 0, 2, 3, 0, 1, 6, 3, 3, 1, 4, 1, 4, 2, 1, 2]).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let m = config(true).choose(&s, &mut rng, false).unwrap().m;
+        assert_eq!(ai::survival_move(&s, &weight_score).unwrap().0, m);
+        // survival makes no difference with lots of space
+        let open = *Game::from_rng(StdRng::seed_from_u64(8)).state();
+        let chosen = |survival| {
+            let c = config(survival).choose(&open, &mut StdRng::seed_from_u64(0), false);
+            c.map(|c| (c.m, c.next))
+        };
+        assert_eq!(chosen(false), chosen(true));
+        // the other searches that score with weight_score survive the same way
+        for algorithm in [
+            Algorithm::MinimaxWorst(Depth::Fixed(1)),
+            Algorithm::ExpectimaxSampled {
+                depth: 1,
+                samples: 2,
+            },
+        ] {
+            let config = Config {
+                algorithm,
+                ..config(true)
+            };
+            assert_eq!(m, config.choose(&s, &mut rng, false).unwrap().m);
+        }
+    }
+
+    #[test]
+    fn latency_percentiles() {
+        let ms = Duration::from_millis;
+        let latency = Latency::from_durations((1..=100).rev().map(ms).collect());
+        assert_eq!(
+            Latency {
+                count: 100,
+                p50: ms(50),
+                p90: ms(90),
+                p95: ms(95),
+                p99: ms(99),
+                max: ms(100),
+            },
+            latency
+        );
+        let latency = Latency::from_durations(vec![ms(3), ms(1), ms(2)]);
+        assert_eq!(
+            (ms(2), ms(3), ms(3), ms(3)),
+            (latency.p50, latency.p90, latency.p95, latency.p99)
+        );
+        assert_eq!(Latency::default(), Latency::from_durations(vec![]));
+        assert_eq!(ms(7), Latency::from_durations(vec![ms(7)]).p50);
+    }
+
+    #[test]
+    fn latency_every_move() {
+        let config = Config {
+            algorithm: Algorithm::ExpectimaxWeight(Depth::Fixed(1)),
+            target_score: Some(128),
+            seed: Some(6),
+            ..Config::default()
+        };
+        let result = config.run_headless();
+        assert!(result.moves > 0);
+        assert_eq!(result.moves as usize, result.latency.count);
+        let latency = result.latency;
+        assert!(latency.p50 <= latency.p90 && latency.p90 <= latency.p95);
+        assert!(latency.p95 <= latency.p99 && latency.p99 <= latency.max);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn config_serde_roundtrip() {
+        let config = Config {
+            algorithm: Algorithm::ExpectimaxHeuristic(
+                Depth::Timed(Duration::from_millis(20)),
+                Heuristic::Snake,
+            ),
+            agent: Some(Arc::new(Algorithm::Random)),
+            stall: Some(StallLimit {
+                moves: 10,
+                min_gain: 4,
+            }),
+            weights: Some(WeightConfig::from_base([0.5; 16])),
+            seed: Some(3),
+            ..Config::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        let parsed: Config = serde_json::from_str(&json).unwrap();
+        // custom agents aren't serialized
+        assert!(parsed.agent.is_none());
+        let config = Config {
+            agent: None,
+            ..config
+        };
+        assert_eq!(format!("{config:?}"), format!("{parsed:?}"));
+    }
+
+    #[test]
+    fn min_empty() {
+        let config = Config {
+            algorithm: Algorithm::Random,
+            target_score: None,
+            record_history: true,
+            seed: Some(5),
+            ..Config::default()
+        };
+        let result = config.run_headless();
+        let empties: Vec<u32> = result
+            .history
+            .iter()
+            .map(|s| s.empty().len() as u32)
+            .collect();
+        for &empty in empties.iter() {
+            assert!(result.min_empty <= empty);
+        }
+        // a random game ends when the board fills up
+        assert_eq!(0, result.min_empty);
+        assert!(empties.contains(&result.min_empty));
+    }
+
+    #[test]
+    fn stall_detector() {
+        let mut stall = StallDetector::new(
+            StallLimit {
+                moves: 2,
+                min_gain: 8,
+            },
+            0,
+        );
+        // not enough history yet
+        assert!(!stall.observe(0));
+        assert!(!stall.observe(8));
+        assert!(!stall.observe(12));
+        // 12 - 8 < 8
+        assert!(stall.observe(12));
+        assert!(!stall.observe(100));
+    }
+
+    #[test]
+    fn stall_stops_game() {
+        // random play can't possibly score this fast
+        let config = Config {
+            algorithm: Algorithm::Random,
+            target_score: None,
+            stall: Some(StallLimit {
+                moves: 5,
+                min_gain: 1_000_000,
+            }),
+            ..Config::default()
+        };
+        let result = config.run_headless();
+        assert_eq!(Outcome::Stalled, result.outcome);
+        assert_eq!(5, result.moves);
+    }
+
+    #[test]
+    fn no_stall_by_default() {
+        let config = Config {
+            algorithm: Algorithm::Random,
+            target_score: None,
+            ..Config::default()
+        };
+        assert_eq!(Outcome::GameOver, config.run_headless().outcome);
+    }
+
+    #[test]
+    fn unreachable_target() {
+        let config = Config {
+            algorithm: Algorithm::Random,
+            target_score: Some(1 << 16),
+            seed: Some(1),
+            ..Config::default()
+        };
+        let result = config.run_headless();
+        assert_eq!(Outcome::Unreachable, result.outcome);
+        assert_eq!(0, result.moves);
+        assert!(!result.won);
+        // random play runs out of moves long before 2048, and then the target
+        // is out of reach too
+        let config = Config {
+            target_score: Some(2048),
+            ..config
+        };
+        let result = config.run_headless();
+        assert_eq!(Outcome::Unreachable, result.outcome);
+        assert!(result.moves > 0);
+        assert!(result.state.game_over());
+    }
+}
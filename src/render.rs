@@ -0,0 +1,101 @@
+//! Drawing the board after every move while a game is played.
+//!
+//! On a terminal, a [`ClearingRenderer`] redraws the screen for each move so
+//! the board stays in place. An [`AppendingRenderer`] writes each board after
+//! the last instead, so the whole game ends up in the scrollback or a log.
+use std::io;
+
+use termcolor::{StandardStream, WriteColor};
+
+use crate::{write_state, State, Style, Theme};
+
+/// Shows the boards of a game as it's played.
+pub trait Renderer {
+    /// Show `s` under a heading (such as the number of moves so far).
+    fn board(&mut self, heading: &str, s: &State) -> io::Result<()>;
+
+    /// Show a line of text below the last board.
+    fn note(&mut self, line: &str) -> io::Result<()>;
+}
+
+/// Clears the terminal before drawing each board to standard output.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ClearingRenderer {
+    style: Style,
+}
+
+impl ClearingRenderer {
+    pub fn new(style: Style) -> Self {
+        Self { style }
+    }
+}
+
+impl Renderer for ClearingRenderer {
+    fn board(&mut self, heading: &str, s: &State) -> io::Result<()> {
+        _ = clearscreen::clear();
+        println!("{heading}");
+        let mut stdout = StandardStream::stdout(self.style.color_choice());
+        write_state(s, self.style.theme, &mut stdout)
+    }
+
+    fn note(&mut self, line: &str) -> io::Result<()> {
+        println!("{line}");
+        Ok(())
+    }
+}
+
+/// Writes every board to `out` one after another, without clearing anything.
+pub struct AppendingRenderer<W> {
+    out: W,
+    theme: Theme,
+}
+
+impl<W: WriteColor> AppendingRenderer<W> {
+    pub fn new(out: W, theme: Theme) -> Self {
+        Self { out, theme }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: WriteColor> Renderer for AppendingRenderer<W> {
+    fn board(&mut self, heading: &str, s: &State) -> io::Result<()> {
+        writeln!(self.out, "{heading}")?;
+        write_state(s, self.theme, &mut self.out)?;
+        self.out.flush()
+    }
+
+    fn note(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.out, "{line}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use termcolor::NoColor;
+
+    use super::{AppendingRenderer, Renderer};
+    use crate::game::Move;
+    use crate::{render_board, State, Theme};
+
+    #[test]
+    fn appending() {
+        let start = State::from_tiles([1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0]);
+        let next = start.apply_move(Move::Right).unwrap();
+        let mut renderer = AppendingRenderer::new(NoColor::new(Vec::new()), Theme::Plain);
+        renderer.board("start", &start).unwrap();
+        renderer.board("1: right", &next).unwrap();
+        renderer.note("done").unwrap();
+        let out = String::from_utf8(renderer.into_inner().into_inner()).unwrap();
+        assert_eq!(
+            format!(
+                "start\n{}1: right\n{}done\n",
+                render_board(&start),
+                render_board(&next)
+            ),
+            out
+        );
+    }
+}
@@ -0,0 +1,486 @@
+//! Recorded games and post-hoc analysis of them.
+//!
+//! A [`Replay`] is the starting board followed by each move and the board that
+//! resulted from it (after the random tile was added). The text format is one
+//! board per line, with each board written as 16 hex digits giving the
+//! exponent of each cell in linear order:
+//!
+//! ```text
+//! 0000000000100010
+//! left 1000000000000011
+//! up 1000000001000001
+//! ```
+//!
+//! A [`Recorder`] writes this format a line at a time while a game is played,
+//! and [`show_replay`] plays a recording back on the terminal.
+use std::{error, fmt, io, thread, time::Duration};
+
+use crate::ai::{score_moves, top_scored_move};
+use crate::game::{Move, State, UnknownMove};
+use crate::{print_state, render_board, Style};
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Replay {
+    pub initial: State,
+    /// Each move along with the board after it was made and a tile was added.
+    pub steps: Vec<(Move, State)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number of the problem.
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+impl error::Error for ParseError {}
+
+fn board_string(s: &State) -> String {
+    s.exponent_grid().iter().map(|x| format!("{x:x}")).collect()
+}
+
+fn parse_board(s: &str) -> Result<State, String> {
+    if s.len() != 16 {
+        return Err(format!("board {s:?} should have 16 cells"));
+    }
+    let mut state = State::default();
+    for (i, c) in s.chars().enumerate() {
+        let x = c
+            .to_digit(16)
+            .ok_or_else(|| format!("invalid cell {c:?} in board {s:?}"))?;
+        state.add(i, x as u8);
+    }
+    Ok(state)
+}
+
+impl Replay {
+    pub fn new(initial: State) -> Self {
+        Self {
+            initial,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Record a move and the board it resulted in.
+    pub fn push(&mut self, m: Move, s: State) {
+        self.steps.push((m, s));
+    }
+
+    /// Parse a replay from the format written by its `Display` implementation.
+    pub fn parse(s: &str) -> Result<Self, ParseError> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(i, l)| (i + 1, l.trim()))
+            .filter(|(_, l)| !l.is_empty());
+        let (line, first) = lines.next().ok_or(ParseError {
+            line: 1,
+            msg: "missing initial board".to_string(),
+        })?;
+        let initial = parse_board(first).map_err(|msg| ParseError { line, msg })?;
+        let mut replay = Self::new(initial);
+        for (line, l) in lines {
+            let err = |msg: String| ParseError { line, msg };
+            let (m, board) = l
+                .split_once(' ')
+                .ok_or_else(|| err(format!("expected a move and a board, got {l:?}")))?;
+            let m = m.parse().map_err(|e: UnknownMove| err(e.to_string()))?;
+            replay.push(m, parse_board(board.trim()).map_err(err)?);
+        }
+        Ok(replay)
+    }
+
+    /// Check that each recorded board follows from the one before it: the
+    /// move is legal, and the board after it differs only by a new 2 or 4 in
+    /// an empty cell.
+    ///
+    /// A recording from an older version of the engine might not; this finds
+    /// the first step where it went differently.
+    pub fn check(&self) -> Result<(), Divergence> {
+        for (index, ((before, m), &(_, after))) in self.moves().zip(&self.steps).enumerate() {
+            let follows = before.apply_move(m).is_some_and(|moved| {
+                let (moved, after) = (moved.exponent_grid(), after.exponent_grid());
+                let changed: Vec<usize> = (0..16).filter(|&i| moved[i] != after[i]).collect();
+                matches!(changed[..], [i] if moved[i] == 0 && (after[i] == 1 || after[i] == 2))
+            });
+            if !follows {
+                return Err(Divergence { index, m });
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterate over each move along with the board it was made from.
+    pub fn moves(&self) -> impl Iterator<Item = (&State, Move)> {
+        let befores = std::iter::once(&self.initial).chain(self.steps.iter().map(|(_, s)| s));
+        befores.zip(self.steps.iter().map(|&(m, _)| m))
+    }
+}
+
+/// The first step of a [`Replay`] that the engine doesn't reproduce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    /// 0-based index of the step in [`Replay::steps`].
+    pub index: usize,
+    pub m: Move,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "move {} ({}) does not lead to the recorded board",
+            self.index + 1,
+            self.m
+        )
+    }
+}
+
+impl error::Error for Divergence {}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", board_string(&self.initial))?;
+        for (m, s) in self.steps.iter() {
+            writeln!(f, "{} {}", m, board_string(s))?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a game in the [`Replay`] format as it's played, flushing after
+/// every move so the recording survives the game being interrupted.
+pub struct Recorder<W: io::Write> {
+    out: W,
+}
+
+impl<W: io::Write> Recorder<W> {
+    /// Start a recording of a game starting from `initial`.
+    pub fn new(mut out: W, initial: &State) -> io::Result<Self> {
+        writeln!(out, "{}", board_string(initial))?;
+        out.flush()?;
+        Ok(Self { out })
+    }
+
+    /// Record a move and the board it resulted in.
+    pub fn record(&mut self, m: Move, s: &State) -> io::Result<()> {
+        writeln!(self.out, "{} {}", m, board_string(s))?;
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Play `log` back on the terminal, drawing each board with `style` and
+/// waiting `delay` between frames.
+///
+/// Stops before the first step that doesn't follow from the board before it
+/// (see [`Replay::check`]) and returns where that was.
+pub fn show_replay(log: &Replay, delay: Duration, style: Style) -> Result<(), Divergence> {
+    _ = clearscreen::clear();
+    println!("start");
+    print_state(&log.initial, style);
+    let divergence = log.check().err();
+    for (index, &(m, s)) in log.steps.iter().enumerate() {
+        if divergence.is_some_and(|d| d.index == index) {
+            break;
+        }
+        thread::sleep(delay);
+        _ = clearscreen::clear();
+        println!("{}: {}", index + 1, m);
+        print_state(&s, style);
+    }
+    divergence.map_or(Ok(()), Err)
+}
+
+/// Write every board in `log` as a grid of tile values (see
+/// [`render_board`]), as a text animation with one frame per board.
+///
+/// Each frame starts with a line saying which move led to it, and frames are
+/// separated by blank lines.
+pub fn write_frames(log: &Replay, mut out: impl io::Write) -> io::Result<()> {
+    writeln!(out, "start")?;
+    write!(out, "{}", render_board(&log.initial))?;
+    for (i, (m, s)) in log.steps.iter().enumerate() {
+        writeln!(out)?;
+        writeln!(out, "{}: {}", i + 1, m)?;
+        write!(out, "{}", render_board(s))?;
+    }
+    Ok(())
+}
+
+fn move_letter(m: Move) -> char {
+    match m {
+        Move::Left => 'L',
+        Move::Right => 'R',
+        Move::Up => 'U',
+        Move::Down => 'D',
+    }
+}
+
+/// Write the moves in `log` in a compact notation: one letter per move (`L`,
+/// `R`, `U` or `D`), followed by the number of merges it made if there were
+/// any, separated by spaces. For example, `L R1 U2 D`.
+///
+/// Unlike the [`Replay`] format this leaves out the random tiles, so it can't
+/// be replayed by itself.
+pub fn format_game_notation(log: &Replay) -> String {
+    let moves: Vec<String> = log
+        .moves()
+        .map(|(s, m)| match s.merges_for_move(m) {
+            0 => move_letter(m).to_string(),
+            merges => format!("{}{merges}", move_letter(m)),
+        })
+        .collect();
+    moves.join(" ")
+}
+
+/// Parse the moves out of the notation written by [`format_game_notation`].
+///
+/// Merge counts are checked for syntax but otherwise ignored, and moves can
+/// be split over several lines.
+pub fn parse_game_notation(s: &str) -> Result<Vec<Move>, ParseError> {
+    let mut moves = Vec::new();
+    for (i, l) in s.lines().enumerate() {
+        for token in l.split_whitespace() {
+            let err = |msg: String| ParseError { line: i + 1, msg };
+            let mut chars = token.chars();
+            let letter = chars.next().unwrap();
+            let m = Move::ALL
+                .into_iter()
+                .find(|&m| move_letter(m) == letter)
+                .ok_or_else(|| err(format!("unknown move {letter:?} in {token:?}")))?;
+            let merges = chars.as_str();
+            if !merges.is_empty() && merges.parse::<u32>().is_err() {
+                return Err(err(format!("invalid merge count in {token:?}")));
+            }
+            moves.push(m);
+        }
+    }
+    Ok(moves)
+}
+
+/// How a recorded move compares to what the AI would have played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveAnnotation {
+    /// The move recorded in the replay.
+    pub played: Move,
+    /// The AI's top choice and its expectimax score.
+    pub best: (Move, f32),
+    /// The expectimax score of the played move, or `None` if it was not legal.
+    pub played_score: Option<f32>,
+    /// If the played move was the top choice, its lead over the runner-up (0
+    /// for a forced move); otherwise how much worse than the top choice it
+    /// scored (infinite if it was not legal).
+    pub margin: f32,
+}
+
+impl MoveAnnotation {
+    pub fn is_top_choice(&self) -> bool {
+        self.played == self.best.0
+    }
+}
+
+/// Label each move in `log` by re-scoring the board it was made from.
+///
+/// Annotations are in the same order as the replay's moves; if some board in
+/// the replay has no legal moves the annotations stop there.
+pub fn annotate_replay(
+    log: &Replay,
+    search_depth: u32,
+    terminal_score: &impl Fn(&State) -> f32,
+) -> Vec<MoveAnnotation> {
+    let mut annotations = Vec::new();
+    for (s, played) in log.moves() {
+        let scored = score_moves(s, search_depth, terminal_score);
+        let (best, _, best_score) = match top_scored_move(&scored) {
+            Some(top) => top,
+            None => break,
+        };
+        let played_score = scored
+            .iter()
+            .find(|&&(m, _, _)| m == played)
+            .map(|&(_, _, score)| score);
+        let margin = match played_score {
+            Some(_) if played == best => scored
+                .iter()
+                .filter(|&&(m, _, _)| m != best)
+                .map(|&(_, _, score)| best_score - score)
+                .reduce(f32::min)
+                .unwrap_or(0.0),
+            Some(score) => best_score - score,
+            None => f32::INFINITY,
+        };
+        annotations.push(MoveAnnotation {
+            played,
+            best: (best, best_score),
+            played_score,
+            margin,
+        });
+    }
+    annotations
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{
+        annotate_replay, format_game_notation, parse_game_notation, write_frames, Divergence,
+        Recorder, Replay,
+    };
+    use crate::ai::{expectimax_weight_move, weight_score};
+    use crate::game::{Game, Move, State};
+
+    fn ai_replay(moves: usize) -> Replay {
+        let mut game = Game::from_rng(StdRng::seed_from_u64(1));
+        let mut replay = Replay::new(*game.state());
+        for _ in 0..moves {
+            let (m, s) = expectimax_weight_move(game.state(), 1).expect("game ended early");
+            game.next_state(m, s);
+            replay.push(m, *game.state());
+        }
+        replay
+    }
+
+    #[test]
+    fn roundtrip() {
+        let replay = ai_replay(10);
+        assert_eq!(replay, Replay::parse(&replay.to_string()).unwrap());
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut game = Game::from_rng(StdRng::seed_from_u64(2));
+        let mut recorder = Recorder::new(Vec::new(), game.state()).unwrap();
+        let mut boards = vec![*game.state()];
+        for _ in 0..15 {
+            let (m, s) = expectimax_weight_move(game.state(), 1).expect("game ended early");
+            game.next_state(m, s);
+            recorder.record(m, game.state()).unwrap();
+            boards.push(*game.state());
+        }
+        let text = String::from_utf8(recorder.into_inner()).unwrap();
+        let replay = Replay::parse(&text).unwrap();
+        assert_eq!(Ok(()), replay.check());
+        let replayed: Vec<State> = std::iter::once(replay.initial)
+            .chain(replay.steps.iter().map(|&(_, s)| s))
+            .collect();
+        assert_eq!(boards, replayed);
+    }
+
+    #[test]
+    fn divergence() {
+        let mut replay = ai_replay(10);
+        assert_eq!(Ok(()), replay.check());
+        // pretend the engine used to move differently on the fourth move
+        let (m, _) = replay.steps[3];
+        replay.steps[3].0 = Move::ALL.into_iter().find(|&other| other != m).unwrap();
+        let err = replay.check().unwrap_err();
+        assert_eq!(
+            Divergence {
+                index: 3,
+                m: replay.steps[3].0
+            },
+            err
+        );
+        assert!(err.to_string().starts_with("move 4 "), "{err}");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Replay::parse("").is_err());
+        assert!(Replay::parse("00001").is_err());
+        let err = Replay::parse("0000000000000011\nsideways 0000000000000111").unwrap_err();
+        assert_eq!(2, err.line);
+    }
+
+    #[test]
+    fn notation_roundtrip() {
+        let replay = ai_replay(30);
+        let notation = format_game_notation(&replay);
+        let moves: Vec<Move> = replay.steps.iter().map(|&(m, _)| m).collect();
+        assert_eq!(moves, parse_game_notation(&notation).unwrap());
+        // the AI merges something early on
+        assert!(notation.chars().any(|c| c.is_ascii_digit()), "{notation}");
+    }
+
+    #[test]
+    fn notation_parse() {
+        assert_eq!(
+            vec![Move::Left, Move::Right, Move::Up, Move::Down],
+            parse_game_notation("L R1\n U12 D\n").unwrap()
+        );
+        assert_eq!(Vec::<Move>::new(), parse_game_notation("").unwrap());
+        assert_eq!(2, parse_game_notation("L\nX").unwrap_err().line);
+        assert!(parse_game_notation("Lx").is_err());
+    }
+
+    #[test]
+    fn ai_moves_are_top_choice() {
+        let replay = ai_replay(20);
+        let annotations = annotate_replay(&replay, 1, &weight_score);
+        assert_eq!(replay.steps.len(), annotations.len());
+        for (i, a) in annotations.iter().enumerate() {
+            assert!(a.is_top_choice(), "move {i} was not the top choice: {a:?}");
+            assert!(a.margin >= 0.0);
+        }
+    }
+
+    #[test]
+    fn annotate_illegal_move() {
+        // a single tile in the top-left corner can't move up or left
+        let mut s = State::default();
+        s.add(0, 1);
+        let mut replay = Replay::new(s);
+        replay.push(Move::Up, s);
+        let annotations = annotate_replay(&replay, 1, &weight_score);
+        assert_eq!(1, annotations.len());
+        assert!(!annotations[0].is_top_choice());
+        assert_eq!(None, annotations[0].played_score);
+        assert!(annotations[0].margin.is_infinite());
+    }
+
+    #[test]
+    fn frames() {
+        let replay = ai_replay(5);
+        let mut out = Vec::new();
+        write_frames(&replay, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let frames: Vec<&str> = text.split("\n\n").collect();
+        assert_eq!(replay.steps.len() + 1, frames.len());
+        let boards = std::iter::once(&replay.initial).chain(replay.steps.iter().map(|(_, s)| s));
+        for (frame, s) in frames.iter().zip(boards) {
+            let lines: Vec<&str> = frame.lines().collect();
+            assert_eq!(10, lines.len(), "bad frame:\n{frame}");
+            let mut tiles = Vec::new();
+            for (i, line) in lines[1..].iter().enumerate() {
+                if i % 2 == 0 {
+                    assert!(line.starts_with('+'));
+                    continue;
+                }
+                let cells: Vec<&str> = line.split('|').collect();
+                // empty strings before the first and after the last bar
+                assert_eq!(6, cells.len(), "bad row {line:?}");
+                for cell in &cells[1..5] {
+                    let cell = cell.trim();
+                    tiles.push(if cell.is_empty() {
+                        1
+                    } else {
+                        cell.parse().unwrap()
+                    });
+                }
+            }
+            let expected: Vec<u32> = (0..16).map(|i| s.tile(i)).collect();
+            assert_eq!(expected, tiles);
+        }
+    }
+}
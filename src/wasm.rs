@@ -0,0 +1,157 @@
+//! Functions for running the AI from JavaScript, behind the `wasm` feature.
+//!
+//! These are plain `extern "C"` exports rather than wasm-bindgen bindings, so
+//! they need no generated glue. Build the library as a WebAssembly module with
+//!
+//! ```text
+//! cargo rustc --lib --release --features wasm \
+//!     --target wasm32-unknown-unknown --crate-type cdylib
+//! ```
+//!
+//! and call the exports on the instance (see `examples/wasm/index.html`).
+//! Boards are passed packed into a `u64` as by [`State::pack`], which is a
+//! `BigInt` in JavaScript. Moves are passed as codes: their index in
+//! [`Move::ALL`] (0 left, 1 right, 2 up, 3 down), or -1 for no move.
+//!
+//! The shift tables are built on the first move, on the heap, so the first call
+//! is slower but doesn't need a large stack.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::game::Game;
+use crate::{Config, Move, State};
+
+// There's no entropy source to fall back on, so anything that needs one (like
+// thread_rng) fails; games are seeded by the caller instead.
+#[cfg(target_arch = "wasm32")]
+fn no_entropy(_: &mut [u8]) -> Result<(), getrandom::Error> {
+    Err(getrandom::Error::UNSUPPORTED)
+}
+
+#[cfg(target_arch = "wasm32")]
+getrandom::register_custom_getrandom!(no_entropy);
+
+fn move_code(m: Option<Move>) -> i32 {
+    m.map_or(-1, |m| {
+        Move::ALL.iter().position(|&x| x == m).unwrap() as i32
+    })
+}
+
+/// A game played by the AI with the default [`Config`].
+pub struct WasmGame {
+    game: Game<StdRng>,
+    config: Config,
+}
+
+impl WasmGame {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            game: Game::from_rng(StdRng::seed_from_u64(seed)),
+            config: Config::default(),
+        }
+    }
+
+    /// The exponent of each cell, in linear order (0 for empty cells).
+    pub fn state_tiles(&self) -> Vec<u8> {
+        self.game.state().to_tiles().to_vec()
+    }
+
+    /// Make the AI's move and add a tile, returning `None` once the game is
+    /// over.
+    pub fn step_ai(&mut self) -> Option<Move> {
+        self.game.step_ai(&self.config)
+    }
+
+    pub fn highest_tile(&self) -> u32 {
+        self.game.state().highest_tile()
+    }
+}
+
+/// The code of the move the AI would make from the packed `board`, or -1 if
+/// there are no legal moves.
+#[no_mangle]
+pub extern "C" fn r2048_best_move(board: u64) -> i32 {
+    let s = State::from_u64(board);
+    // the default algorithm doesn't draw random numbers
+    let mut rng = StdRng::seed_from_u64(0);
+    move_code(Config::default().choose(&s, &mut rng, false).map(|c| c.m))
+}
+
+/// Start a new game with tiles added by an RNG seeded with `seed`. It must be
+/// freed with [`r2048_game_free`].
+#[no_mangle]
+pub extern "C" fn r2048_game_new(seed: u64) -> *mut WasmGame {
+    Box::into_raw(Box::new(WasmGame::new(seed)))
+}
+
+/// # Safety
+///
+/// `game` must come from [`r2048_game_new`] and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn r2048_game_free(game: *mut WasmGame) {
+    drop(Box::from_raw(game));
+}
+
+/// Make the AI's move, returning its code (-1 once the game is over).
+///
+/// # Safety
+///
+/// `game` must come from [`r2048_game_new`] and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn r2048_game_step_ai(game: *mut WasmGame) -> i32 {
+    move_code((*game).step_ai())
+}
+
+/// The current board, packed.
+///
+/// # Safety
+///
+/// `game` must come from [`r2048_game_new`] and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn r2048_game_state(game: *const WasmGame) -> u64 {
+    (*game).game.state().pack()
+}
+
+/// # Safety
+///
+/// `game` must come from [`r2048_game_new`] and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn r2048_game_highest_tile(game: *const WasmGame) -> u32 {
+    (*game).highest_tile()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn best_move() {
+        // only moving left changes this board
+        let s = State::from_exponents(&[0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4]).unwrap();
+        assert_eq!(0, r2048_best_move(s.pack()));
+        assert_eq!(-1, r2048_best_move(State::default().pack()));
+    }
+
+    #[test]
+    fn play_game() {
+        let game = r2048_game_new(5);
+        let mut moves = 0;
+        unsafe {
+            while r2048_game_highest_tile(game) < 128 {
+                let before = State::from_u64(r2048_game_state(game));
+                let code = r2048_game_step_ai(game);
+                assert!((0..4).contains(&code), "game ended early");
+                assert!(before.is_legal(Move::ALL[code as usize]));
+                moves += 1;
+            }
+            let tiles = (*game).state_tiles();
+            assert_eq!(
+                State::from_tiles(tiles.try_into().unwrap()).pack(),
+                r2048_game_state(game)
+            );
+            r2048_game_free(game);
+        }
+        assert!(moves > 20);
+    }
+}
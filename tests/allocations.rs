@@ -0,0 +1,56 @@
+//! Guard against regressions in how much the search allocates.
+//!
+//! This replaces the global allocator, so it only runs with `cargo test
+//! --features count-allocations`.
+#![cfg(feature = "count-allocations")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use r2048_ai::ai::expectimax_weight_move;
+use r2048_ai::game::State;
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// allocations made by this thread while running `f`
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(|n| n.get());
+    let x = f();
+    (x, ALLOCATIONS.with(|n| n.get()) - before)
+}
+
+/// The most allocations a depth-2 search of the board below may make. It made
+/// 1117 allocations when this test was written; lower this as allocations
+/// are removed from the search.
+const DEPTH_2_CEILING: usize = 1_200;
+
+#[test]
+fn depth_2_search_allocations() {
+    let s = State::from_exponents(&[1, 2, 0, 0, 3, 1, 0, 0, 5, 2, 1, 0, 6, 4, 2, 1]).unwrap();
+    // warm up lazily-initialized tables so they aren't counted
+    expectimax_weight_move(&s, 1);
+    let (m, allocations) = count_allocations(|| expectimax_weight_move(&s, 2));
+    assert!(m.is_some());
+    assert!(
+        allocations <= DEPTH_2_CEILING,
+        "{allocations} allocations is more than the ceiling of {DEPTH_2_CEILING}"
+    );
+}